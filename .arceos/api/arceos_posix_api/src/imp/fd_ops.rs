@@ -1,5 +1,5 @@
-use alloc::sync::Arc;
-use core::ffi::c_int;
+use alloc::{collections::BTreeSet, sync::Arc, vec::Vec};
+use core::{ffi::c_int, ops::Deref};
 
 use axerrno::{LinuxError, LinuxResult};
 use axio::PollState;
@@ -22,17 +22,104 @@ pub trait FileLike: Send + Sync {
     fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult;
 }
 
+/// A file descriptor table, which also records the close-on-exec flag of each
+/// file descriptor.
+///
+/// It dereferences to the table of files for lookups. Files are added and
+/// removed through its own methods so that the flags stay in sync.
+pub struct FdTable {
+    files: FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>,
+    cloexec: BTreeSet<usize>,
+    limit: usize,
+}
+
+impl FdTable {
+    /// Creates an empty table.
+    pub fn new() -> Self {
+        Self {
+            files: FlattenObjects::new(),
+            cloexec: BTreeSet::new(),
+            limit: AX_FILE_LIMIT,
+        }
+    }
+
+    /// Returns the limit of new file descriptors, which must be less than it.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Sets the limit of new file descriptors, i.e. the soft limit of
+    /// `RLIMIT_NOFILE`. It is capped at [`AX_FILE_LIMIT`]. Files already open
+    /// at higher descriptors are kept.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit.min(AX_FILE_LIMIT);
+    }
+
+    /// Adds a file at the lowest free file descriptor.
+    pub fn add(&mut self, f: Arc<dyn FileLike>, cloexec: bool) -> LinuxResult<usize> {
+        let fd = self.files.add(f).map_err(|_| LinuxError::EMFILE)?;
+        if fd >= self.limit {
+            self.files.remove(fd);
+            return Err(LinuxError::EMFILE);
+        }
+        self.set_cloexec(fd, cloexec);
+        Ok(fd)
+    }
+
+    /// Adds a file at the file descriptor `fd`, which must be free.
+    pub fn add_at(&mut self, fd: usize, f: Arc<dyn FileLike>, cloexec: bool) -> LinuxResult<usize> {
+        self.files.add_at(fd, f).map_err(|_| LinuxError::EMFILE)?;
+        self.set_cloexec(fd, cloexec);
+        Ok(fd)
+    }
+
+    /// Removes the file at the file descriptor `fd`.
+    pub fn remove(&mut self, fd: usize) -> Option<Arc<dyn FileLike>> {
+        self.cloexec.remove(&fd);
+        self.files.remove(fd)
+    }
+
+    /// Returns whether the file descriptor `fd` is closed on `execve`.
+    pub fn cloexec(&self, fd: usize) -> bool {
+        self.cloexec.contains(&fd)
+    }
+
+    /// Sets whether the file descriptor `fd` is closed on `execve`.
+    pub fn set_cloexec(&mut self, fd: usize, cloexec: bool) {
+        if cloexec {
+            self.cloexec.insert(fd);
+        } else {
+            self.cloexec.remove(&fd);
+        }
+    }
+}
+
+impl Default for FdTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for FdTable {
+    type Target = FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.files
+    }
+}
+
 def_resource! {
-    pub static FD_TABLE: ResArc<RwLock<FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>>> = ResArc::new();
+    pub static FD_TABLE: ResArc<RwLock<FdTable>> = ResArc::new();
 }
 
 impl FD_TABLE {
     /// Return a copy of the inner table.
-    pub fn copy_inner(&self) -> RwLock<FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>> {
+    pub fn copy_inner(&self) -> RwLock<FdTable> {
         let table = self.read();
-        let mut new_table = FlattenObjects::new();
+        let mut new_table = FdTable::new();
+        new_table.limit = table.limit;
         for id in table.ids() {
-            let _ = new_table.add_at(id, table.get(id).unwrap().clone());
+            let _ = new_table.add_at(id, table.get(id).unwrap().clone(), table.cloexec(id));
         }
         RwLock::new(new_table)
     }
@@ -49,7 +136,12 @@ pub fn get_file_like(fd: c_int) -> LinuxResult<Arc<dyn FileLike>> {
 
 /// Add a file to the file descriptor table.
 pub fn add_file_like(f: Arc<dyn FileLike>) -> LinuxResult<c_int> {
-    Ok(FD_TABLE.write().add(f).map_err(|_| LinuxError::EMFILE)? as c_int)
+    add_file_like_with_cloexec(f, false)
+}
+
+/// Add a file to the file descriptor table, and set its close-on-exec flag.
+pub fn add_file_like_with_cloexec(f: Arc<dyn FileLike>, cloexec: bool) -> LinuxResult<c_int> {
+    Ok(FD_TABLE.write().add(f, cloexec)? as c_int)
 }
 
 /// Close a file by `fd`.
@@ -62,22 +154,34 @@ pub fn close_file_like(fd: c_int) -> LinuxResult {
     Ok(())
 }
 
+/// Close all files with the close-on-exec flag set, which is done by
+/// `execve`.
+pub fn close_on_exec() {
+    let files: Vec<_> = {
+        let mut table = FD_TABLE.write();
+        let fds: Vec<_> = table.cloexec.iter().copied().collect();
+        fds.into_iter().filter_map(|fd| table.remove(fd)).collect()
+    };
+    // The files are dropped without holding the lock.
+    drop(files);
+}
+
 /// Close a file by `fd`.
 pub fn sys_close(fd: c_int) -> c_int {
     debug!("sys_close <= {}", fd);
     syscall_body!(sys_close, close_file_like(fd).map(|_| 0))
 }
 
-fn dup_fd(old_fd: c_int) -> LinuxResult<c_int> {
+fn dup_fd(old_fd: c_int, cloexec: bool) -> LinuxResult<c_int> {
     let f = get_file_like(old_fd)?;
-    let new_fd = add_file_like(f)?;
+    let new_fd = add_file_like_with_cloexec(f, cloexec)?;
     Ok(new_fd)
 }
 
 /// Duplicate a file descriptor.
 pub fn sys_dup(old_fd: c_int) -> c_int {
     debug!("sys_dup <= {}", old_fd);
-    syscall_body!(sys_dup, dup_fd(old_fd))
+    syscall_body!(sys_dup, dup_fd(old_fd, false))
 }
 
 /// Duplicate a file descriptor, but it uses the file descriptor number specified in `new_fd`.
@@ -94,31 +198,65 @@ pub fn sys_dup2(old_fd: c_int, new_fd: c_int) -> c_int {
                 return Ok(r);
             }
         }
-        if new_fd as usize >= AX_FILE_LIMIT {
-            return Err(LinuxError::EBADF);
+        dup_fd_at(old_fd, new_fd, false)
+    })
+}
+
+/// Duplicate a file descriptor to `new_fd` like `dup2`, but `flags` may
+/// contain `O_CLOEXEC`, and `old_fd` must differ from `new_fd`.
+pub fn sys_dup3(old_fd: c_int, new_fd: c_int, flags: c_int) -> c_int {
+    debug!(
+        "sys_dup3 <= old_fd: {}, new_fd: {}, flags: {:#x}",
+        old_fd, new_fd, flags
+    );
+    syscall_body!(sys_dup3, {
+        let flags = flags as u32;
+        if old_fd == new_fd || flags & !ctypes::O_CLOEXEC != 0 {
+            return Err(LinuxError::EINVAL);
         }
+        dup_fd_at(old_fd, new_fd, flags & ctypes::O_CLOEXEC != 0)
+    })
+}
 
-        let f = get_file_like(old_fd)?;
-        FD_TABLE
-            .write()
-            .add_at(new_fd as usize, f)
-            .map_err(|_| LinuxError::EMFILE)?;
+fn dup_fd_at(old_fd: c_int, new_fd: c_int, cloexec: bool) -> LinuxResult<c_int> {
+    if new_fd < 0 || new_fd as usize >= FD_TABLE.read().limit() {
+        return Err(LinuxError::EBADF);
+    }
 
-        Ok(new_fd)
-    })
+    let f = get_file_like(old_fd)?;
+    FD_TABLE.write().add_at(new_fd as usize, f, cloexec)?;
+
+    Ok(new_fd)
 }
 
 /// Manipulate file descriptor.
 ///
-/// TODO: `SET/GET` command is ignored, hard-code stdin/stdout
+/// TODO: `F_GETFL` and file locks are not supported, and `F_SETFL` is
+/// hard-coded for stdin/stdout
 pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> c_int {
     debug!("sys_fcntl <= fd: {} cmd: {} arg: {}", fd, cmd, arg);
     syscall_body!(sys_fcntl, {
         match cmd as u32 {
-            ctypes::F_DUPFD => dup_fd(fd),
-            ctypes::F_DUPFD_CLOEXEC => {
-                // TODO: Change fd flags
-                dup_fd(fd)
+            ctypes::F_DUPFD => dup_fd(fd, false),
+            ctypes::F_DUPFD_CLOEXEC => dup_fd(fd, true),
+            ctypes::F_GETFD => {
+                let table = FD_TABLE.read();
+                if table.get(fd as usize).is_none() {
+                    return Err(LinuxError::EBADF);
+                }
+                Ok(if table.cloexec(fd as usize) {
+                    ctypes::FD_CLOEXEC as c_int
+                } else {
+                    0
+                })
+            }
+            ctypes::F_SETFD => {
+                let mut table = FD_TABLE.write();
+                if table.get(fd as usize).is_none() {
+                    return Err(LinuxError::EBADF);
+                }
+                table.set_cloexec(fd as usize, arg & ctypes::FD_CLOEXEC as usize != 0);
+                Ok(0)
             }
             ctypes::F_SETFL => {
                 if fd == 0 || fd == 1 || fd == 2 {
@@ -137,15 +275,15 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> c_int {
 
 #[ctor_bare::register_ctor]
 fn init_stdio() {
-    let mut fd_table = flatten_objects::FlattenObjects::new();
+    let mut fd_table = FdTable::new();
     fd_table
-        .add_at(0, Arc::new(stdin()) as _)
+        .add_at(0, Arc::new(stdin()) as _, false)
         .unwrap_or_else(|_| panic!()); // stdin
     fd_table
-        .add_at(1, Arc::new(stdout()) as _)
+        .add_at(1, Arc::new(stdout()) as _, false)
         .unwrap_or_else(|_| panic!()); // stdout
     fd_table
-        .add_at(2, Arc::new(stdout()) as _)
+        .add_at(2, Arc::new(stdout()) as _, false)
         .unwrap_or_else(|_| panic!()); // stderr
     FD_TABLE.init_new(spin::RwLock::new(fd_table));
 }
@@ -0,0 +1,80 @@
+//! Owners and permission bits of files.
+//!
+//! The filesystems do not store the owner of a file, so it is recorded here,
+//! by path, when a file is created or changed. Files without a record belong
+//! to root, with the permission bits reported by the filesystem.
+
+use alloc::{collections::BTreeMap, string::String};
+
+use axerrno::AxResult;
+use spin::RwLock;
+
+/// The set-user-ID bit.
+pub const S_ISUID: u32 = 0o4000;
+/// The set-group-ID bit.
+pub const S_ISGID: u32 = 0o2000;
+/// The sticky bit.
+pub const S_ISVTX: u32 = 0o1000;
+
+/// The owner and the permission bits of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileMeta {
+    /// The user ID of the owner.
+    pub uid: u32,
+    /// The group ID of the owner.
+    pub gid: u32,
+    /// The permission bits, including [`S_ISUID`], [`S_ISGID`] and
+    /// [`S_ISVTX`].
+    pub mode: u32,
+    /// Whether the file is a directory.
+    pub is_dir: bool,
+}
+
+static FILE_META: RwLock<BTreeMap<String, (u32, u32, u32)>> = RwLock::new(BTreeMap::new());
+
+/// Returns the key of `path` in [`FILE_META`], which is the canonical path
+/// without the trailing slash of directories.
+fn key(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    }
+}
+
+/// Returns the owner and the permission bits of the file at `path`, which
+/// must be a canonical path with links resolved (see
+/// [`FilePath`](super::path_link::FilePath)).
+pub fn file_meta(path: &str) -> AxResult<FileMeta> {
+    let metadata = axfs::api::metadata(path)?;
+    let default = (0, 0, metadata.permissions().bits() as u32);
+    let (uid, gid, mode) = FILE_META.read().get(key(path)).copied().unwrap_or(default);
+    Ok(FileMeta {
+        uid,
+        gid,
+        mode,
+        is_dir: metadata.is_dir(),
+    })
+}
+
+/// Records the owner and the permission bits of the file at `path`, e.g.
+/// when it is created.
+pub fn set_file_meta(path: &str, uid: u32, gid: u32, mode: u32) {
+    FILE_META
+        .write()
+        .insert(String::from(key(path)), (uid, gid, mode & 0o7777));
+}
+
+/// Forgets the file at `path`, after it has been removed.
+pub fn remove_file_meta(path: &str) {
+    FILE_META.write().remove(key(path));
+}
+
+/// Moves the record of the file at `old` to `new`, after it has been
+/// renamed.
+pub fn rename_file_meta(old: &str, new: &str) {
+    let mut meta = FILE_META.write();
+    match meta.remove(key(old)) {
+        Some(entry) => meta.insert(String::from(key(new)), entry),
+        None => meta.remove(key(new)),
+    };
+}
@@ -8,6 +8,8 @@ use axio::{PollState, SeekFrom};
 use axsync::Mutex;
 
 use super::fd_ops::{FileLike, get_file_like};
+use super::file_meta::{file_meta, rename_file_meta};
+use super::path_link::FilePath;
 use crate::AT_FDCWD;
 use crate::{ctypes, utils::char_ptr_to_str};
 
@@ -25,8 +27,8 @@ impl File {
         }
     }
 
-    fn add_to_fd_table(self) -> LinuxResult<c_int> {
-        super::fd_ops::add_file_like(Arc::new(self))
+    fn add_to_fd_table(self, cloexec: bool) -> LinuxResult<c_int> {
+        super::fd_ops::add_file_like_with_cloexec(Arc::new(self), cloexec)
     }
 
     pub fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
@@ -59,14 +61,18 @@ impl FileLike for File {
     fn stat(&self) -> LinuxResult<ctypes::stat> {
         let metadata = self.inner.lock().get_attr()?;
         let ty = metadata.file_type() as u8;
-        let perm = metadata.perm().bits() as u32;
+        let owner = FilePath::new(&self.path).and_then(|path| file_meta(&path));
+        let (uid, gid, perm) = match owner {
+            Ok(owner) => (owner.uid, owner.gid, owner.mode),
+            Err(_) => (0, 0, metadata.perm().bits() as u32),
+        };
         let st_mode = ((ty as u32) << 12) | perm;
         Ok(ctypes::stat {
             st_ino: 1,
             st_nlink: 1,
             st_mode,
-            st_uid: 1000,
-            st_gid: 1000,
+            st_uid: uid,
+            st_gid: gid,
             st_size: metadata.size() as _,
             st_blocks: metadata.blocks() as _,
             st_blksize: 512,
@@ -134,6 +140,7 @@ pub fn sys_open(filename: *const c_char, flags: c_int, mode: ctypes::mode_t) ->
             axfs::fops::Directory::open_dir,
             filename?,
             &flags_to_options(flags, mode),
+            flags as u32 & ctypes::O_CLOEXEC != 0,
         )
     })
 }
@@ -170,6 +177,7 @@ pub fn sys_openat(
             |filename, options| dir.inner.lock().open_dir_at(filename, options),
             filename,
             &flags_to_options(flags, mode),
+            flags as u32 & ctypes::O_CLOEXEC != 0,
         )
     }) {
         Ok(fd) => fd,
@@ -187,6 +195,7 @@ fn add_file_or_directory_fd<F, D, E>(
     open_dir: D,
     filename: &str,
     options: &OpenOptions,
+    cloexec: bool,
 ) -> LinuxResult<c_int>
 where
     E: Into<LinuxError>,
@@ -196,7 +205,7 @@ where
     if !options.has_directory() {
         match open_file(filename, options)
             .map_err(Into::into)
-            .and_then(|f| File::new(f, filename.into()).add_to_fd_table())
+            .and_then(|f| File::new(f, filename.into()).add_to_fd_table(cloexec))
         {
             Err(LinuxError::EISDIR) => {}
             r => return r,
@@ -207,7 +216,7 @@ where
         open_dir(filename, options).map_err(Into::into)?,
         filename.to_string(),
     )
-    .add_to_fd_table()
+    .add_to_fd_table(cloexec)
 }
 
 /// Set the position of the file indicated by `fd`.
@@ -306,6 +315,9 @@ pub fn sys_rename(old: *const c_char, new: *const c_char) -> c_int {
         let new_path = char_ptr_to_str(new)?;
         debug!("sys_rename <= old: {:?}, new: {:?}", old_path, new_path);
         axfs::api::rename(old_path, new_path)?;
+        if let (Ok(old_path), Ok(new_path)) = (FilePath::new(old_path), FilePath::new(new_path)) {
+            rename_file_meta(&old_path, &new_path);
+        }
         Ok(0)
     })
 }
@@ -324,8 +336,8 @@ impl Directory {
         }
     }
 
-    fn add_to_fd_table(self) -> LinuxResult<c_int> {
-        super::fd_ops::add_file_like(Arc::new(self))
+    fn add_to_fd_table(self, cloexec: bool) -> LinuxResult<c_int> {
+        super::fd_ops::add_file_like_with_cloexec(Arc::new(self), cloexec)
     }
 
     /// Open a directory by `fd`.
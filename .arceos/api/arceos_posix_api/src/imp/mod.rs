@@ -9,6 +9,8 @@ pub mod time;
 #[cfg(feature = "fd")]
 pub mod fd_ops;
 #[cfg(feature = "fs")]
+pub mod file_meta;
+#[cfg(feature = "fs")]
 pub mod fs;
 #[cfg(any(feature = "select", feature = "epoll"))]
 pub mod io_mpx;
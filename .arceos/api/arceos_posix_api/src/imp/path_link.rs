@@ -227,6 +227,11 @@ impl HardlinkManager {
             .unwrap_or_else(|| path.to_string())
     }
 
+    /// 判断路径本身是否为链接，不跟随链接
+    pub fn is_link(&self, path: &str) -> bool {
+        self.inner.read().links.contains_key(path)
+    }
+
     pub fn link_count(&self, path: &FilePath) -> usize {
         let inner = self.inner.read();
         inner
@@ -1,11 +1,12 @@
 use alloc::sync::Arc;
 use core::ffi::c_int;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use axerrno::{LinuxError, LinuxResult};
 use axio::PollState;
 use axsync::Mutex;
 
-use super::fd_ops::{FileLike, add_file_like, close_file_like};
+use super::fd_ops::{FileLike, add_file_like_with_cloexec, close_file_like};
 use crate::ctypes;
 
 #[derive(Copy, Clone, PartialEq)]
@@ -77,6 +78,7 @@ impl PipeRingBuffer {
 pub struct Pipe {
     readable: bool,
     buffer: Arc<Mutex<PipeRingBuffer>>,
+    nonblocking: AtomicBool,
 }
 
 impl Pipe {
@@ -85,10 +87,12 @@ impl Pipe {
         let read_end = Pipe {
             readable: true,
             buffer: buffer.clone(),
+            nonblocking: AtomicBool::new(false),
         };
         let write_end = Pipe {
             readable: false,
             buffer,
+            nonblocking: AtomicBool::new(false),
         };
         (read_end, write_end)
     }
@@ -120,6 +124,13 @@ impl FileLike for Pipe {
                 if self.write_end_close() {
                     return Ok(read_size);
                 }
+                if self.nonblocking.load(Ordering::Relaxed) {
+                    return if read_size > 0 {
+                        Ok(read_size)
+                    } else {
+                        Err(LinuxError::EAGAIN)
+                    };
+                }
                 drop(ring_buffer);
                 // Data not ready, wait for write end
                 crate::sys_sched_yield(); // TODO: use synconize primitive
@@ -145,6 +156,13 @@ impl FileLike for Pipe {
             let mut ring_buffer = self.buffer.lock();
             let loop_write = ring_buffer.available_write();
             if loop_write == 0 {
+                if self.nonblocking.load(Ordering::Relaxed) {
+                    return if write_size > 0 {
+                        Ok(write_size)
+                    } else {
+                        Err(LinuxError::EAGAIN)
+                    };
+                }
                 drop(ring_buffer);
                 // Buffer is full, wait for read end to consume
                 crate::sys_sched_yield(); // TODO: use synconize primitive
@@ -185,7 +203,8 @@ impl FileLike for Pipe {
         })
     }
 
-    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
         Ok(())
     }
 }
@@ -194,17 +213,35 @@ impl FileLike for Pipe {
 ///
 /// Return 0 if succeed
 pub fn sys_pipe(fds: &mut [c_int]) -> c_int {
-    debug!("sys_pipe <= {:#x}", fds.as_ptr() as usize);
-    syscall_body!(sys_pipe, {
+    sys_pipe2(fds, 0)
+}
+
+/// Create a pipe with the flags `O_CLOEXEC` and `O_NONBLOCK`, which apply to
+/// both ends
+///
+/// Return 0 if succeed
+pub fn sys_pipe2(fds: &mut [c_int], flags: c_int) -> c_int {
+    debug!("sys_pipe2 <= {:#x} {:#x}", fds.as_ptr() as usize, flags);
+    syscall_body!(sys_pipe2, {
         if fds.len() != 2 {
             return Err(LinuxError::EFAULT);
         }
+        let flags = flags as u32;
+        if flags & !(ctypes::O_CLOEXEC | ctypes::O_NONBLOCK) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let cloexec = flags & ctypes::O_CLOEXEC != 0;
 
         let (read_end, write_end) = Pipe::new();
-        let read_fd = add_file_like(Arc::new(read_end))?;
-        let write_fd = add_file_like(Arc::new(write_end)).inspect_err(|_| {
-            close_file_like(read_fd).ok();
-        })?;
+        if flags & ctypes::O_NONBLOCK != 0 {
+            read_end.set_nonblocking(true)?;
+            write_end.set_nonblocking(true)?;
+        }
+        let read_fd = add_file_like_with_cloexec(Arc::new(read_end), cloexec)?;
+        let write_fd =
+            add_file_like_with_cloexec(Arc::new(write_end), cloexec).inspect_err(|_| {
+                close_file_like(read_fd).ok();
+            })?;
 
         fds[0] = read_fd as c_int;
         fds[1] = write_fd as c_int;
@@ -30,6 +30,11 @@ pub mod config {
 #[allow(dead_code, non_snake_case, non_camel_case_types, non_upper_case_globals, clippy::upper_case_acronyms, missing_docs)]
 pub mod ctypes;
 
+#[cfg(feature = "fs")]
+pub use imp::file_meta::{
+    FileMeta, S_ISGID, S_ISUID, S_ISVTX, file_meta, remove_file_meta, rename_file_meta,
+    set_file_meta,
+};
 pub use imp::io::*;
 #[cfg(feature = "fs")]
 pub use imp::path_link::{AT_FDCWD, FilePath, HARDLINK_MANAGER, handle_file_path};
@@ -40,7 +45,8 @@ pub use imp::time::{sys_clock_gettime, sys_get_time_of_day, sys_nanosleep};
 
 #[cfg(feature = "fd")]
 pub use imp::fd_ops::{
-    FD_TABLE, add_file_like, get_file_like, sys_close, sys_dup, sys_dup2, sys_fcntl,
+    AX_FILE_LIMIT, FD_TABLE, FdTable, FileLike, add_file_like, add_file_like_with_cloexec,
+    close_on_exec, get_file_like, sys_close, sys_dup, sys_dup2, sys_dup3, sys_fcntl,
 };
 #[cfg(feature = "fs")]
 pub use imp::fs::{
@@ -58,7 +64,7 @@ pub use imp::net::{
     sys_socket,
 };
 #[cfg(feature = "pipe")]
-pub use imp::pipe::sys_pipe;
+pub use imp::pipe::{sys_pipe, sys_pipe2};
 #[cfg(feature = "multitask")]
 pub use imp::pthread::mutex::{
     sys_pthread_mutex_init, sys_pthread_mutex_lock, sys_pthread_mutex_unlock,
@@ -234,17 +234,23 @@ impl File {
     /// After the write, the cursor will be advanced by the number of bytes
     /// written.
     pub fn write(&mut self, buf: &[u8]) -> AxResult<usize> {
-        let offset = if self.is_append {
-            self.get_attr()?.size()
-        } else {
-            self.offset
-        };
+        let offset = self.write_position()?;
         let node = self.access_node(Cap::WRITE)?;
         let write_len = node.write_at(offset, buf)?;
         self.offset = offset + write_len as u64;
         Ok(write_len)
     }
 
+    /// Returns the position where the next [`write`](Self::write) starts,
+    /// which is the end of the file in append mode.
+    pub fn write_position(&self) -> AxResult<u64> {
+        if self.is_append {
+            Ok(self.get_attr()?.size())
+        } else {
+            Ok(self.offset)
+        }
+    }
+
     /// Writes the file at the given position. Returns the number of bytes
     /// written.
     ///
@@ -108,6 +108,31 @@ pub fn flush_icache_all() {
     unsafe { asm!("ic iallu; dsb sy; isb") };
 }
 
+/// Makes the instructions written to the range `[vaddr, vaddr + size)`
+/// visible to instruction fetches.
+///
+/// The data cache lines of the range are cleaned to the point of
+/// unification, and then the instruction cache lines are invalidated, with
+/// the line sizes read from `CTR_EL0`.
+#[inline]
+pub fn flush_icache_range(vaddr: VirtAddr, size: usize) {
+    let ctr: usize;
+    unsafe { asm!("mrs {}, ctr_el0", out(reg) ctr) };
+    let dline = 4 << ((ctr >> 16) & 0xf);
+    let iline = 4 << (ctr & 0xf);
+    let (start, end) = (vaddr.as_usize(), vaddr.as_usize() + size);
+    unsafe {
+        for addr in (start & !(dline - 1)..end).step_by(dline) {
+            asm!("dc cvau, {}", in(reg) addr);
+        }
+        asm!("dsb ish");
+        for addr in (start & !(iline - 1)..end).step_by(iline) {
+            asm!("ic ivau, {}", in(reg) addr);
+        }
+        asm!("dsb ish; isb");
+    }
+}
+
 /// Sets the base address of the exception vector (writes `VBAR_EL1`).
 #[inline]
 pub fn set_exception_vector_base(vbar_el1: usize) {
@@ -37,8 +37,20 @@ fn invalid_exception(tf: &TrapFrame, kind: TrapKind, source: TrapSource) {
 }
 
 #[unsafe(no_mangle)]
-fn handle_irq_exception(_tf: &TrapFrame) {
+#[cfg_attr(not(feature = "uspace"), allow(unused_variables))]
+fn handle_irq_exception(tf: &mut TrapFrame) {
     handle_trap!(IRQ, 0);
+    #[cfg(feature = "uspace")]
+    if is_from_user(tf) {
+        crate::trap::handle_user_return(tf);
+    }
+}
+
+/// Returns `true` if the exception is taken from user space, i.e. if
+/// SPSR_EL1.M[3:0] is EL0t.
+#[cfg(feature = "uspace")]
+const fn is_from_user(tf: &TrapFrame) -> bool {
+    tf.spsr & 0b1111 == 0
 }
 
 fn handle_instruction_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
@@ -100,7 +112,8 @@ fn handle_sync_exception(tf: &mut TrapFrame) {
     match esr.read_as_enum(ESR_EL1::EC) {
         #[cfg(feature = "uspace")]
         Some(ESR_EL1::EC::Value::SVC64) => {
-            tf.r[0] = crate::trap::handle_syscall(tf, tf.r[8] as usize) as u64;
+            let syscall_num = tf.r[8] as usize;
+            tf.r[0] = crate::trap::handle_syscall(tf, syscall_num) as u64;
         }
         Some(ESR_EL1::EC::Value::InstrAbortLowerEL) => handle_instruction_abort(tf, iss, true),
         Some(ESR_EL1::EC::Value::InstrAbortCurrentEL) => handle_instruction_abort(tf, iss, false),
@@ -120,4 +133,8 @@ fn handle_sync_exception(tf: &mut TrapFrame) {
             );
         }
     }
+    #[cfg(feature = "uspace")]
+    if is_from_user(tf) {
+        crate::trap::handle_user_return(tf);
+    }
 }
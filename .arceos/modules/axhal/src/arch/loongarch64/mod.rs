@@ -127,6 +127,16 @@ pub fn flush_tlb(vaddr: Option<VirtAddr>) {
     }
 }
 
+/// Makes the instructions written to the range `[vaddr, vaddr + size)`
+/// visible to instruction fetches.
+///
+/// `ibar 0` synchronizes the whole instruction cache, so the range is not
+/// used.
+#[inline]
+pub fn flush_icache_range(_vaddr: VirtAddr, _size: usize) {
+    unsafe { asm!("ibar 0") };
+}
+
 /// Writes Exception Entry Base Address Register (`eentry`).
 ///
 /// - ECFG: <https://loongson.github.io/LoongArch-Documentation/LoongArch-Vol1-EN.html#exception-configuration>
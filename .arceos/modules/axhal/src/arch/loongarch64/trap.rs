@@ -40,8 +40,11 @@ fn loongarch64_trap_handler(tf: &mut TrapFrame, from_user: bool) {
     match estat.cause() {
         #[cfg(feature = "uspace")]
         Trap::Exception(Exception::Syscall) => {
-            tf.regs.a0 = crate::trap::handle_syscall(tf, tf.regs.a7) as usize;
+            // The syscall handler may redirect the return address, e.g. for
+            // `rt_sigreturn`, so skip `syscall` beforehand.
             tf.era += 4;
+            let syscall_num = tf.regs.a7;
+            tf.regs.a0 = crate::trap::handle_syscall(tf, syscall_num) as usize;
         }
         Trap::Exception(Exception::LoadPageFault)
         | Trap::Exception(Exception::PageNonReadableFault) => {
@@ -69,4 +72,8 @@ fn loongarch64_trap_handler(tf: &mut TrapFrame, from_user: bool) {
             );
         }
     }
+    #[cfg(feature = "uspace")]
+    if from_user {
+        crate::trap::handle_user_return(tf);
+    }
 }
@@ -82,6 +82,16 @@ pub fn flush_tlb(vaddr: Option<VirtAddr>) {
     }
 }
 
+/// Makes the instructions written to the range `[vaddr, vaddr + size)`
+/// visible to instruction fetches of the current hart.
+///
+/// `fence.i` synchronizes the whole instruction cache, so the range is not
+/// used.
+#[inline]
+pub fn flush_icache_range(_vaddr: VirtAddr, _size: usize) {
+    unsafe { core::arch::asm!("fence.i") };
+}
+
 /// Writes Supervisor Trap Vector Base Address Register (`stvec`).
 #[inline]
 pub fn set_trap_vector_base(stvec: usize) {
@@ -40,8 +40,11 @@ fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
         match cause {
             #[cfg(feature = "uspace")]
             Trap::Exception(E::UserEnvCall) => {
-                tf.regs.a0 = crate::trap::handle_syscall(tf, tf.regs.a7) as usize;
+                // The syscall handler may redirect the return address, e.g.
+                // for `rt_sigreturn`, so skip `ecall` beforehand.
                 tf.sepc += 4;
+                let syscall_num = tf.regs.a7;
+                tf.regs.a0 = crate::trap::handle_syscall(tf, syscall_num) as usize;
             }
             Trap::Exception(E::LoadPageFault) => {
                 handle_page_fault(tf, MappingFlags::READ, from_user)
@@ -68,4 +71,8 @@ fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
             tf
         );
     }
+    #[cfg(feature = "uspace")]
+    if from_user {
+        crate::trap::handle_user_return(tf);
+    }
 }
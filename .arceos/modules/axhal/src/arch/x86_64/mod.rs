@@ -102,6 +102,14 @@ pub fn flush_tlb(vaddr: Option<VirtAddr>) {
     }
 }
 
+/// Makes the instructions written to the range `[vaddr, vaddr + size)`
+/// visible to instruction fetches.
+///
+/// The instruction cache is coherent with the data cache on x86, so nothing
+/// has to be done.
+#[inline]
+pub fn flush_icache_range(_vaddr: VirtAddr, _size: usize) {}
+
 /// Reads the thread pointer of the current CPU.
 ///
 /// It is used to implement TLS (Thread Local Storage).
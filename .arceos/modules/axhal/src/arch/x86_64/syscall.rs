@@ -16,7 +16,14 @@ core::arch::global_asm!(
 
 #[unsafe(no_mangle)]
 pub(super) fn x86_syscall_handler(tf: &mut TrapFrame) {
-    tf.rax = crate::trap::handle_syscall(tf, tf.rax as usize) as u64;
+    // `syscall` does not save the segment selectors, but they are needed if
+    // the handlers change `rcx` or `r11`, and `syscall_entry` has to return
+    // with `iretq`.
+    tf.cs = GdtStruct::UCODE64_SELECTOR.0 as _;
+    tf.ss = GdtStruct::UDATA_SELECTOR.0 as _;
+    let syscall_num = tf.rax as usize;
+    tf.rax = crate::trap::handle_syscall(tf, syscall_num) as u64;
+    crate::trap::handle_user_return(tf);
 }
 
 /// Initializes syscall support and setups the syscall handler.
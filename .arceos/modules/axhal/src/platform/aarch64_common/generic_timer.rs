@@ -77,6 +77,17 @@ pub(crate) fn init_early() {
 }
 
 pub(crate) fn init_percpu() {
+    // Allow user space to read `CNTPCT_EL0` (`CNTKCTL_EL1.EL0PCTEN`), e.g. in
+    // the vDSO.
+    #[cfg(feature = "uspace")]
+    unsafe {
+        core::arch::asm!(
+            "mrs {tmp}, cntkctl_el1",
+            "orr {tmp}, {tmp}, #1",
+            "msr cntkctl_el1, {tmp}",
+            tmp = out(reg) _,
+        );
+    }
     #[cfg(feature = "irq")]
     {
         CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::SET);
@@ -55,6 +55,11 @@ pub(super) fn init_early() {
 }
 
 pub(super) fn init_percpu() {
+    // Allow user space to read the `time` CSR, e.g. in the vDSO.
+    #[cfg(feature = "uspace")]
+    unsafe {
+        core::arch::asm!("csrs scounteren, {}", in(reg) 1 << 1);
+    }
     #[cfg(feature = "irq")]
     sbi_rt::set_timer(0);
 }
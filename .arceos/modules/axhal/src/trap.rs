@@ -20,7 +20,16 @@ pub static PAGE_FAULT: [fn(VirtAddr, MappingFlags, bool) -> bool];
 /// A slice of syscall handler functions.
 #[cfg(feature = "uspace")]
 #[def_trap_handler]
-pub static SYSCALL: [fn(&TrapFrame, usize) -> isize];
+pub static SYSCALL: [fn(&mut TrapFrame, usize) -> isize];
+
+/// A slice of functions called before returning to user space from a
+/// syscall, an interrupt or an exception, e.g. to deliver pending signals.
+///
+/// For a syscall, they are called after its return value is written to the
+/// trap frame, which they may modify.
+#[cfg(feature = "uspace")]
+#[def_trap_handler]
+pub static USER_RETURN: [fn(&mut TrapFrame)];
 
 #[allow(unused_macros)]
 macro_rules! handle_trap {
@@ -40,6 +49,14 @@ macro_rules! handle_trap {
 
 /// Call the external syscall handler.
 #[cfg(feature = "uspace")]
-pub(crate) fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
+pub(crate) fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
     SYSCALL[0](tf, syscall_num)
 }
+
+/// Call the handlers registered to run before returning to user space.
+#[cfg(feature = "uspace")]
+pub(crate) fn handle_user_return(tf: &mut TrapFrame) {
+    for handler in USER_RETURN.iter() {
+        handler(tf);
+    }
+}
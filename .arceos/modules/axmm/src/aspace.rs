@@ -1,3 +1,4 @@
+use alloc::sync::Arc;
 use core::fmt;
 
 use axerrno::{AxError, AxResult, ax_err};
@@ -8,7 +9,7 @@ use memory_addr::{
 };
 use memory_set::{MemoryArea, MemorySet};
 
-use crate::backend::Backend;
+use crate::backend::{Backend, PageCache};
 use crate::mapping_err_to_ax_err;
 
 /// The virtual memory address space.
@@ -44,6 +45,22 @@ impl AddrSpace {
         self.pt.root_paddr()
     }
 
+    /// Returns the total size of the mapped areas.
+    pub fn mapped_size(&self) -> usize {
+        self.areas.iter().map(|area| area.size()).sum()
+    }
+
+    /// Returns the total size of the pages present in the page table, i.e.
+    /// the resident set size.
+    pub fn resident_size(&self) -> usize {
+        self.areas
+            .iter()
+            .flat_map(|area| PageIter4K::new(area.start(), area.end()).unwrap())
+            .filter(|&vaddr| self.pt.query(vaddr).is_ok())
+            .count()
+            * PAGE_SIZE_4K
+    }
+
     /// Checks if the address space contains the given address range.
     pub fn contains_range(&self, start: VirtAddr, size: usize) -> bool {
         self.va_range
@@ -161,6 +178,39 @@ impl AddrSpace {
         Ok(())
     }
 
+    /// Add a new file mapping.
+    ///
+    /// See [`Backend`] for more details about the mapping backends. The page at
+    /// `vaddr` is filled with the contents of `cache` at offset
+    /// `vaddr - va_offset`, up to `data_end`.
+    ///
+    /// The `flags` parameter indicates the mapping permissions and attributes.
+    ///
+    /// Returns an error if the address range is out of the address space or not
+    /// aligned.
+    pub fn map_file(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        cache: Arc<PageCache>,
+        va_offset: usize,
+        data_end: VirtAddr,
+    ) -> AxResult {
+        self.validate_region(start, size)?;
+
+        let area = MemoryArea::new(
+            start,
+            size,
+            flags,
+            Backend::new_file(cache, va_offset, data_end),
+        );
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        Ok(())
+    }
+
     /// Populates the area with physical frames, returning false if the area
     /// contains unmapped area.
     pub fn populate_area(&mut self, mut start: VirtAddr, size: usize) -> AxResult {
@@ -169,20 +219,21 @@ impl AddrSpace {
 
         while let Some(area) = self.areas.find(start) {
             let backend = area.backend();
-            if let Backend::Alloc { populate } = backend {
-                if !*populate {
-                    for addr in PageIter4K::new(start, area.end().min(end)).unwrap() {
-                        match self.pt.query(addr) {
-                            Ok(_) => {}
-                            // If the page is not mapped, try map it.
-                            Err(PagingError::NotMapped) => {
-                                if !backend.handle_page_fault(addr, area.flags(), &mut self.pt) {
-                                    return Err(AxError::NoMemory);
-                                }
+            if matches!(
+                backend,
+                Backend::Alloc { populate: false } | Backend::File { .. }
+            ) {
+                for addr in PageIter4K::new(start, area.end().min(end)).unwrap() {
+                    match self.pt.query(addr) {
+                        Ok(_) => {}
+                        // If the page is not mapped, try map it.
+                        Err(PagingError::NotMapped) => {
+                            if !backend.handle_page_fault(addr, area.flags(), &mut self.pt) {
+                                return Err(AxError::NoMemory);
                             }
-                            Err(_) => return Err(AxError::BadAddress),
-                        };
-                    }
+                        }
+                        Err(_) => return Err(AxError::BadAddress),
+                    };
                 }
             }
             start = area.end();
@@ -381,6 +432,19 @@ impl AddrSpace {
         false
     }
 
+    /// Returns whether handling a page fault at `vaddr` reads the page from
+    /// a file, which makes it a major page fault.
+    pub fn is_major_fault(&self, vaddr: VirtAddr) -> bool {
+        match self.areas.find(vaddr).map(MemoryArea::backend) {
+            Some(Backend::File {
+                cache,
+                va_offset,
+                data_end,
+            }) => Backend::page_fault_reads_file(vaddr, cache, *va_offset, *data_end),
+            _ => false,
+        }
+    }
+
     /// Clone a [`AddrSpace`] by re-mapping all [`MemoryArea`]s in a new page table and copying data in user space.
     pub fn clone_or_err(&mut self) -> AxResult<Self> {
         let mut new_aspace = Self::new_empty(self.base(), self.size())?;
@@ -394,6 +458,11 @@ impl AddrSpace {
                 .areas
                 .map(new_area, &mut new_aspace.pt, false)
                 .map_err(mapping_err_to_ax_err)?;
+            // Linear mappings share the same physical memory, so there is
+            // nothing to copy.
+            if matches!(backend, Backend::Linear { .. }) {
+                continue;
+            }
             // Copy data from old memory area to new memory area.
             for vaddr in
                 PageIter4K::new(area.start(), area.end()).expect("Failed to create page iterator")
@@ -404,6 +473,24 @@ impl AddrSpace {
                     Err(PagingError::NotMapped) => continue,
                     Err(_) => return Err(AxError::BadAddress),
                 };
+                // The pages of a file mapping may be shared with the page
+                // cache, which must not be written.
+                if let Backend::File {
+                    cache, va_offset, ..
+                } = backend
+                {
+                    if !Backend::clone_page_file(
+                        vaddr,
+                        addr,
+                        area.flags(),
+                        &mut new_aspace.pt,
+                        cache,
+                        *va_offset,
+                    ) {
+                        return Err(AxError::NoMemory);
+                    }
+                    continue;
+                }
                 let new_addr = match new_aspace.pt.query(vaddr) {
                     Ok((paddr, _, _)) => paddr,
                     // If the page is not mapped, try map it.
@@ -0,0 +1,270 @@
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, btree_map::Entry},
+    sync::Arc,
+};
+
+use axerrno::AxResult;
+use axhal::mem::phys_to_virt;
+use axhal::paging::{MappingFlags, PageSize, PageTable};
+use kspin::SpinNoIrq;
+use memory_addr::{PAGE_SIZE_4K, PageIter4K, PhysAddr, VirtAddr};
+
+use super::Backend;
+use super::alloc::{alloc_frame, dealloc_frame};
+
+/// Where the contents of a [`PageCache`] come from, usually a file.
+pub trait FileSource: Send + Sync {
+    /// Reads the file at `offset`. Returns the number of bytes read, which is
+    /// 0 at the end of the file.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize>;
+}
+
+/// The pages of a file, read on demand and shared by all the file-backed
+/// mappings of it.
+///
+/// The frames are freed when the cache is dropped, i.e. when no mapping uses
+/// it anymore.
+pub struct PageCache {
+    source: Box<dyn FileSource>,
+    pages: SpinNoIrq<BTreeMap<usize, PhysAddr>>,
+}
+
+impl PageCache {
+    /// Creates an empty page cache for `source`.
+    pub fn new(source: Box<dyn FileSource>) -> Self {
+        Self {
+            source,
+            pages: SpinNoIrq::new(BTreeMap::new()),
+        }
+    }
+
+    /// Returns the frame holding page `index` of the file, reading it on the
+    /// first access. The frame is shared and must not be modified.
+    ///
+    /// Returns `None` if the page is beyond the end of the file, or it cannot
+    /// be read.
+    fn page(&self, index: usize) -> Option<PhysAddr> {
+        if let Some(frame) = self.pages.lock().get(&index) {
+            return Some(*frame);
+        }
+
+        // Read the page without holding the lock, since reading may sleep.
+        let frame = alloc_frame(true)?;
+        let buf = unsafe {
+            core::slice::from_raw_parts_mut(phys_to_virt(frame).as_mut_ptr(), PAGE_SIZE_4K)
+        };
+        let offset = (index * PAGE_SIZE_4K) as u64;
+        let mut read = 0;
+        while read < PAGE_SIZE_4K {
+            match self.source.read_at(offset + read as u64, &mut buf[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(_) => {
+                    read = 0;
+                    break;
+                }
+            }
+        }
+        if read == 0 {
+            dealloc_frame(frame);
+            return None;
+        }
+
+        match self.pages.lock().entry(index) {
+            // Another CPU has read it meanwhile.
+            Entry::Occupied(entry) => {
+                dealloc_frame(frame);
+                Some(*entry.get())
+            }
+            Entry::Vacant(entry) => Some(*entry.insert(frame)),
+        }
+    }
+
+    /// Returns whether page `index` of the file has been read.
+    fn contains(&self, index: usize) -> bool {
+        self.pages.lock().contains_key(&index)
+    }
+
+    /// Returns whether `frame` is the cached page `index`, rather than a
+    /// private copy of it.
+    fn is_cached(&self, index: usize, frame: PhysAddr) -> bool {
+        self.pages.lock().get(&index) == Some(&frame)
+    }
+}
+
+impl Drop for PageCache {
+    fn drop(&mut self) {
+        for frame in self.pages.lock().values() {
+            dealloc_frame(*frame);
+        }
+    }
+}
+
+/// Returns the index of the file page mapped at `vaddr`.
+fn page_index(vaddr: VirtAddr, va_offset: usize) -> usize {
+    vaddr.align_down_4k().as_usize().wrapping_sub(va_offset) / PAGE_SIZE_4K
+}
+
+/// Allocates a private copy of page `index` of the file, keeping only the
+/// first `len` bytes of it.
+fn private_copy(cache: &PageCache, index: usize, len: usize) -> Option<PhysAddr> {
+    let src = cache.page(index)?;
+    let frame = alloc_frame(false)?;
+    let dst = phys_to_virt(frame).as_mut_ptr();
+    unsafe {
+        core::ptr::copy_nonoverlapping(phys_to_virt(src).as_ptr(), dst, len);
+        core::ptr::write_bytes(dst.add(len), 0, PAGE_SIZE_4K - len);
+    }
+    Some(frame)
+}
+
+impl Backend {
+    /// Creates a new file-backed mapping backend.
+    ///
+    /// The page at `vaddr` holds the file contents at offset
+    /// `vaddr - va_offset`, up to `data_end`. Pages after `data_end` are
+    /// zero-filled.
+    pub fn new_file(cache: Arc<PageCache>, va_offset: usize, data_end: VirtAddr) -> Self {
+        Self::File {
+            cache,
+            va_offset,
+            data_end,
+        }
+    }
+
+    pub(crate) fn map_file(start: VirtAddr, size: usize, flags: MappingFlags) -> bool {
+        debug!("map_file: [{:#x}, {:#x}) {:?}", start, start + size, flags);
+        // create mapping entries on demand later in `handle_page_fault_file`.
+        true
+    }
+
+    pub(crate) fn unmap_file(
+        start: VirtAddr,
+        size: usize,
+        pt: &mut PageTable,
+        cache: &PageCache,
+        va_offset: usize,
+    ) -> bool {
+        debug!("unmap_file: [{:#x}, {:#x})", start, start + size);
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            if let Ok((frame, page_size, tlb)) = pt.unmap(addr) {
+                if page_size.is_huge() {
+                    return false;
+                }
+                tlb.flush();
+                // Shared pages are freed with the page cache.
+                if !cache.is_cached(page_index(addr, va_offset), frame) {
+                    dealloc_frame(frame);
+                }
+            }
+        }
+        true
+    }
+
+    /// Replaces the shared pages in the range with private copies, before
+    /// they are made writable.
+    pub(crate) fn protect_file(
+        start: VirtAddr,
+        size: usize,
+        pt: &mut PageTable,
+        cache: &PageCache,
+        va_offset: usize,
+    ) -> bool {
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            let Ok((frame, flags, _)) = pt.query(addr) else {
+                continue;
+            };
+            let index = page_index(addr, va_offset);
+            if !cache.is_cached(index, frame) {
+                continue;
+            }
+            let Some(copy) = private_copy(cache, index, PAGE_SIZE_4K) else {
+                return false;
+            };
+            match pt.unmap(addr) {
+                Ok((_, _, tlb)) => tlb.flush(),
+                Err(_) => return false,
+            }
+            if pt.map(addr, copy, PageSize::Size4K, flags).is_err() {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Returns whether a page fault at `vaddr` has to read the page from the
+    /// file, i.e. it is a major page fault.
+    pub(crate) fn page_fault_reads_file(
+        vaddr: VirtAddr,
+        cache: &PageCache,
+        va_offset: usize,
+        data_end: VirtAddr,
+    ) -> bool {
+        let page = vaddr.align_down_4k();
+        page < data_end && !cache.contains(page_index(page, va_offset))
+    }
+
+    pub(crate) fn handle_page_fault_file(
+        vaddr: VirtAddr,
+        orig_flags: MappingFlags,
+        pt: &mut PageTable,
+        cache: &PageCache,
+        va_offset: usize,
+        data_end: VirtAddr,
+    ) -> bool {
+        let page = vaddr.align_down_4k();
+        let index = page_index(page, va_offset);
+        let frame = if page >= data_end {
+            // bss
+            alloc_frame(true)
+        } else if page + PAGE_SIZE_4K <= data_end && !orig_flags.contains(MappingFlags::WRITE) {
+            cache.page(index)
+        } else {
+            private_copy(cache, index, (data_end - page).min(PAGE_SIZE_4K))
+        };
+        let Some(frame) = frame else {
+            return false;
+        };
+        // The page has just been written through the linear mapping, which
+        // instruction fetches do not see yet.
+        if orig_flags.contains(MappingFlags::EXECUTE) {
+            axhal::arch::flush_icache_range(phys_to_virt(frame), PAGE_SIZE_4K);
+        }
+        pt.map(page, frame, PageSize::Size4K, orig_flags)
+            .map(|tlb| tlb.flush())
+            .is_ok()
+    }
+
+    /// Maps the page at `vaddr`, which is mapped to `frame` in the parent
+    /// address space, into the page table `pt` of a forked address space.
+    ///
+    /// Shared pages are mapped again, private pages are copied.
+    pub(crate) fn clone_page_file(
+        vaddr: VirtAddr,
+        frame: PhysAddr,
+        flags: MappingFlags,
+        pt: &mut PageTable,
+        cache: &PageCache,
+        va_offset: usize,
+    ) -> bool {
+        let new_frame = if cache.is_cached(page_index(vaddr, va_offset), frame) {
+            frame
+        } else {
+            let Some(new_frame) = alloc_frame(false) else {
+                return false;
+            };
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    phys_to_virt(frame).as_ptr(),
+                    phys_to_virt(new_frame).as_mut_ptr(),
+                    PAGE_SIZE_4K,
+                )
+            };
+            new_frame
+        };
+        pt.map(vaddr, new_frame, PageSize::Size4K, flags)
+            .map(|tlb| tlb.ignore())
+            .is_ok()
+    }
+}
@@ -1,20 +1,27 @@
 //! Memory mapping backends.
 
+use ::alloc::sync::Arc;
 use axhal::paging::{MappingFlags, PageTable};
 use memory_addr::VirtAddr;
 use memory_set::MappingBackend;
 
 mod alloc;
+mod file;
 mod linear;
 
+pub use self::file::{FileSource, PageCache};
+
 /// A unified enum type for different memory mapping backends.
 ///
-/// Currently, two backends are implemented:
+/// Currently, three backends are implemented:
 ///
 /// - **Linear**: used for linear mappings. The target physical frames are
 ///   contiguous and their addresses should be known when creating the mapping.
 /// - **Allocation**: used in general, or for lazy mappings. The target physical
 ///   frames are obtained from the global allocator.
+/// - **File**: used for file mappings, e.g. the segments of an executable. The
+///   pages are read from the file on demand, and shared through a
+///   [`PageCache`] as long as they are not written.
 #[derive(Clone)]
 pub enum Backend {
     /// Linear mapping backend.
@@ -36,6 +43,21 @@ pub enum Backend {
         /// Whether to populate the physical frames when creating the mapping.
         populate: bool,
     },
+    /// File mapping backend.
+    ///
+    /// The physical frames are allocated on demand. The page at `vaddr` is
+    /// filled with the file contents at offset `vaddr - va_offset`, up to
+    /// `data_end`, and the rest of the mapping is zero-filled. Read-only pages
+    /// map the frames of the page cache directly, writable pages get a private
+    /// copy.
+    File {
+        /// The pages of the file.
+        cache: Arc<PageCache>,
+        /// `vaddr - file offset`.
+        va_offset: usize,
+        /// The end of the file contents in the mapping.
+        data_end: VirtAddr,
+    },
 }
 
 impl MappingBackend for Backend {
@@ -46,6 +68,7 @@ impl MappingBackend for Backend {
         match *self {
             Self::Linear { pa_va_offset } => Self::map_linear(start, size, flags, pt, pa_va_offset),
             Self::Alloc { populate } => Self::map_alloc(start, size, flags, pt, populate),
+            Self::File { .. } => Self::map_file(start, size, flags),
         }
     }
 
@@ -53,6 +76,11 @@ impl MappingBackend for Backend {
         match *self {
             Self::Linear { pa_va_offset } => Self::unmap_linear(start, size, pt, pa_va_offset),
             Self::Alloc { populate } => Self::unmap_alloc(start, size, pt, populate),
+            Self::File {
+                ref cache,
+                va_offset,
+                ..
+            } => Self::unmap_file(start, size, pt, cache, va_offset),
         }
     }
 
@@ -63,6 +91,19 @@ impl MappingBackend for Backend {
         new_flags: Self::Flags,
         page_table: &mut Self::PageTable,
     ) -> bool {
+        if let Self::File {
+            ref cache,
+            va_offset,
+            ..
+        } = *self
+        {
+            // Shared pages must not become writable.
+            if new_flags.contains(MappingFlags::WRITE)
+                && !Self::protect_file(start, size, page_table, cache, va_offset)
+            {
+                return false;
+            }
+        }
         page_table
             .protect_region(start, size, new_flags, true)
             .map(|tlb| tlb.ignore())
@@ -82,6 +123,13 @@ impl Backend {
             Self::Alloc { populate } => {
                 Self::handle_page_fault_alloc(vaddr, orig_flags, page_table, populate)
             }
+            Self::File {
+                ref cache,
+                va_offset,
+                data_end,
+            } => Self::handle_page_fault_file(
+                vaddr, orig_flags, page_table, cache, va_offset, data_end,
+            ),
         }
     }
 }
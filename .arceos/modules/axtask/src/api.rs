@@ -7,8 +7,10 @@ use alloc::{
 
 use kernel_guard::NoPreemptIrqSave;
 
-pub(crate) use crate::run_queue::{current_run_queue, select_run_queue};
+pub(crate) use crate::run_queue::{current_run_queue, select_run_queue, task_run_queue};
 
+#[doc(cfg(feature = "multitask"))]
+pub use crate::sched::{NICE_MAX, NICE_MIN, RT_PRIO_MAX, SchedPolicy};
 #[doc(cfg(feature = "multitask"))]
 pub use crate::task::{CurrentTask, TaskId, TaskInner};
 #[doc(cfg(feature = "multitask"))]
@@ -31,14 +33,14 @@ cfg_if::cfg_if! {
     if #[cfg(feature = "sched_rr")] {
         const MAX_TIME_SLICE: usize = 5;
         pub(crate) type AxTask = scheduler::RRTask<TaskInner, MAX_TIME_SLICE>;
-        pub(crate) type Scheduler = scheduler::RRScheduler<TaskInner, MAX_TIME_SLICE>;
+        pub(crate) type FairScheduler = scheduler::RRScheduler<TaskInner, MAX_TIME_SLICE>;
     } else if #[cfg(feature = "sched_cfs")] {
-        pub(crate) type AxTask = scheduler::CFSTask<TaskInner>;
-        pub(crate) type Scheduler = scheduler::CFScheduler<TaskInner>;
+        pub(crate) type AxTask = TaskInner;
+        pub(crate) type FairScheduler = crate::cfs::CfsScheduler;
     } else {
         // If no scheduler features are set, use FIFO as the default.
         pub(crate) type AxTask = scheduler::FifoTask<TaskInner>;
-        pub(crate) type Scheduler = scheduler::FifoScheduler<TaskInner>;
+        pub(crate) type FairScheduler = scheduler::FifoScheduler<TaskInner>;
     }
 }
 
@@ -84,7 +86,10 @@ pub fn init_scheduler() {
     #[cfg(feature = "irq")]
     crate::timers::init();
 
-    info!("  use {} scheduler.", Scheduler::scheduler_name());
+    info!(
+        "  use {} scheduler.",
+        crate::sched::Scheduler::scheduler_name()
+    );
 }
 
 /// Initializes the task scheduler for secondary CPUs.
@@ -150,11 +155,39 @@ pub fn set_priority(prio: isize) -> bool {
     current_run_queue::<NoPreemptIrqSave>().set_current_priority(prio)
 }
 
+/// Set the priority for the given task, like [`set_priority`].
+///
+/// Returns `true` if the priority is set successfully.
+pub fn set_task_priority(task: &AxTaskRef, prio: isize) -> bool {
+    loop {
+        if let Some(ok) = task_run_queue::<NoPreemptIrqSave>(task).set_priority(task, prio) {
+            return ok;
+        }
+    }
+}
+
+/// Sets the scheduling policy and the real-time priority of `task`, which
+/// takes effect immediately.
+///
+/// Returns `false` if `priority` is out of the range of `policy`.
+pub fn set_sched_params(task: &AxTaskRef, policy: SchedPolicy, priority: u8) -> bool {
+    let (min, max) = policy.priority_range();
+    if !(min..=max).contains(&priority) {
+        return false;
+    }
+    while !task_run_queue::<NoPreemptIrqSave>(task).set_sched_params(task, policy, priority) {}
+    true
+}
+
+/// Returns the time slice of [`SchedPolicy::RoundRobin`] tasks, in
+/// nanoseconds.
+pub fn rr_interval_nanos() -> u64 {
+    crate::sched::RR_TIME_SLICE as u64 * axhal::time::NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64
+}
+
 /// Set the affinity for the current task.
 /// [`AxCpuMask`] is used to specify the CPU affinity.
 /// Returns `true` if the affinity is set successfully.
-///
-/// TODO: support set the affinity for other tasks.
 pub fn set_current_affinity(cpumask: AxCpuMask) -> bool {
     if cpumask.is_empty() {
         false
@@ -166,14 +199,8 @@ pub fn set_current_affinity(cpumask: AxCpuMask) -> bool {
         // the affinity. If not, we need to migrate the task to the correct CPU.
         #[cfg(feature = "smp")]
         if !cpumask.get(axhal::cpu::this_cpu_id()) {
-            const MIGRATION_TASK_STACK_SIZE: usize = 4096;
             // Spawn a new migration task for migrating.
-            let migration_task = TaskInner::new(
-                move || crate::run_queue::migrate_entry(curr),
-                "migration-task".into(),
-                MIGRATION_TASK_STACK_SIZE,
-            )
-            .into_arc();
+            let migration_task = crate::run_queue::migration_task(curr);
 
             // Migrate the current task to the correct CPU using the migration task.
             current_run_queue::<NoPreemptIrqSave>().migrate_current(migration_task);
@@ -184,6 +211,24 @@ pub fn set_current_affinity(cpumask: AxCpuMask) -> bool {
     }
 }
 
+/// Set the affinity for the given task, like [`set_current_affinity`].
+///
+/// A ready task is moved to a run queue matching the affinity at once. A
+/// task running on another CPU migrates when it is preempted or yields, and
+/// a blocked one when it is woken up.
+pub fn set_task_affinity(task: &AxTaskRef, cpumask: AxCpuMask) -> bool {
+    if current().ptr_eq(task) {
+        return set_current_affinity(cpumask);
+    }
+    if cpumask.is_empty() {
+        return false;
+    }
+    task.set_cpumask(cpumask);
+    #[cfg(feature = "smp")]
+    while !task_run_queue::<NoPreemptIrqSave>(task).apply_affinity(task) {}
+    true
+}
+
 /// Current task gives up the CPU time voluntarily, and switches to another
 /// ready task.
 pub fn yield_now() {
@@ -0,0 +1,154 @@
+//! The Completely Fair Scheduler for the normal tasks.
+//!
+//! Every task has a virtual runtime, which grows with the CPU time it uses,
+//! inversely proportional to the weight of its nice value. The task with the
+//! smallest virtual runtime runs next, and is preempted on a timer tick once
+//! it is ahead of another ready task, so that the tasks share the CPU by
+//! their weights.
+
+use alloc::collections::BTreeMap;
+
+use scheduler::BaseScheduler;
+
+use crate::AxTaskRef;
+use crate::sched::{NICE_MAX, NICE_MIN};
+
+/// The weight of the nice value 0.
+const NICE_0_WEIGHT: u64 = 1024;
+
+/// The weights of the nice values from -20 to 19. Each step multiplies the
+/// weight by about 1.25, which changes the CPU share of a task competing
+/// with another by about 10%.
+const NICE_TO_WEIGHT: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291, 29154, 23254, 18705, 14949, 11916, 9548, 7620, 6100, 4904,
+    3906, 3121, 2501, 1991, 1586, 1277, 1024, 820, 655, 526, 423, 335, 272, 215, 172, 137, 110, 87,
+    70, 56, 45, 36, 29, 23, 18, 15,
+];
+
+/// How far (in nanoseconds of virtual runtime) a woken task may be placed
+/// behind the others, so that tasks sleeping often get the CPU soon after
+/// waking up, but cannot save up CPU time by sleeping.
+const SLEEPER_CREDIT_NS: u64 = 3_000_000;
+
+pub(crate) struct CfsScheduler {
+    /// The ready tasks, ordered by virtual runtime and then by ID.
+    ready_queue: BTreeMap<(u64, u64), AxTaskRef>,
+    /// A lower bound of the virtual runtimes of the ready and the running
+    /// tasks, which never decreases.
+    min_vruntime: u64,
+    /// The ID of the task that yielded, which is not picked next if another
+    /// task is ready.
+    skip: Option<u64>,
+}
+
+impl CfsScheduler {
+    pub const fn new() -> Self {
+        Self {
+            ready_queue: BTreeMap::new(),
+            min_vruntime: 0,
+            skip: None,
+        }
+    }
+
+    pub fn scheduler_name() -> &'static str {
+        "CFS"
+    }
+
+    fn key(task: &AxTaskRef) -> (u64, u64) {
+        (task.vruntime(), task.id().as_u64())
+    }
+
+    /// Adds the CPU time `task` has used since the last update to its
+    /// virtual runtime.
+    fn charge(task: &AxTaskRef, on_cpu: bool) {
+        let weight = NICE_TO_WEIGHT[(task.nice() - NICE_MIN) as usize];
+        let delta = task.take_exec_time(on_cpu) * NICE_0_WEIGHT / weight;
+        task.set_vruntime(task.vruntime() + delta);
+    }
+
+    /// Charges `task`, which is running on this CPU, and records the current
+    /// `min_vruntime` for it.
+    fn update_vruntime(&self, task: &AxTaskRef) {
+        Self::charge(task, true);
+        task.set_min_vruntime(self.min_vruntime);
+    }
+
+    /// Advances `min_vruntime` to the smallest virtual runtime of the ready
+    /// tasks and the running one.
+    fn update_min_vruntime(&mut self, running: u64) {
+        let min = match self.ready_queue.first_key_value() {
+            Some((&(leftmost, _), _)) => leftmost.min(running),
+            None => running,
+        };
+        self.min_vruntime = self.min_vruntime.max(min);
+    }
+}
+
+impl BaseScheduler for CfsScheduler {
+    type SchedItem = AxTaskRef;
+
+    fn init(&mut self) {}
+
+    /// Adds a new or woken task, which may come from another CPU.
+    ///
+    /// The task keeps its lag, i.e. how far its virtual runtime was ahead of
+    /// the `min_vruntime` of its run queue when it last ran, except that it
+    /// is at most [`SLEEPER_CREDIT_NS`] behind. A new task has no lag.
+    fn add_task(&mut self, task: AxTaskRef) {
+        Self::charge(&task, false);
+        let lag = task.vlag().max(-(SLEEPER_CREDIT_NS as i64));
+        task.set_vruntime(self.min_vruntime.saturating_add_signed(lag));
+        task.set_min_vruntime(self.min_vruntime);
+        self.ready_queue.insert(Self::key(&task), task);
+    }
+
+    fn remove_task(&mut self, task: &AxTaskRef) -> Option<AxTaskRef> {
+        self.ready_queue.remove(&Self::key(task))
+    }
+
+    fn pick_next_task(&mut self) -> Option<AxTaskRef> {
+        let mut keys = self.ready_queue.keys();
+        let mut key = *keys.next()?;
+        if self.skip == Some(key.1) {
+            if let Some(&next) = keys.next() {
+                key = next;
+            }
+        }
+        self.skip = None;
+        let task = self.ready_queue.remove(&key);
+        self.update_min_vruntime(key.0);
+        task
+    }
+
+    /// Puts back a task that was preempted or, if not `preempt`, yielded.
+    /// A task that yielded is skipped once, so that it gives the CPU to the
+    /// others without giving up its share.
+    fn put_prev_task(&mut self, prev: AxTaskRef, preempt: bool) {
+        self.update_vruntime(&prev);
+        self.skip = (!preempt).then(|| prev.id().as_u64());
+        self.ready_queue.insert(Self::key(&prev), prev);
+    }
+
+    fn task_tick(&mut self, current: &AxTaskRef) -> bool {
+        self.update_vruntime(current);
+        let vruntime = current.vruntime();
+        self.update_min_vruntime(vruntime);
+        self.ready_queue
+            .first_key_value()
+            .is_some_and(|(&(leftmost, _), _)| leftmost < vruntime)
+    }
+
+    /// Sets the nice value of `task`, which must be from [`NICE_MIN`] to
+    /// [`NICE_MAX`].
+    fn set_priority(&mut self, task: &AxTaskRef, prio: isize) -> bool {
+        if !(NICE_MIN as isize..=NICE_MAX as isize).contains(&prio) {
+            return false;
+        }
+        // The time used so far is weighted by the old nice value.
+        if task.is_running() {
+            self.update_vruntime(task);
+        }
+        task.set_nice(prio as i8);
+        true
+    }
+}
@@ -18,12 +18,15 @@
 //!   and it can be overriden by other scheduler features.
 //! - `sched_rr`: Use the [Round-robin preemptive scheduler][2]. It also enables
 //!   the `multitask` and `preempt` features if it is enabled.
-//! - `sched_cfs`: Use the [Completely Fair Scheduler][3]. It also enables the
-//!   the `multitask` and `preempt` features if it is enabled.
+//! - `sched_cfs`: Use the Completely Fair Scheduler, which weights the CPU
+//!   time of tasks by their nice values. It also enables the `multitask` and
+//!   `preempt` features if it is enabled.
+//!
+//! The schedulers above are for the normal tasks. Real-time tasks (see
+//! [`SchedPolicy`]) always run before them.
 //!
 //! [1]: scheduler::FifoScheduler
 //! [2]: scheduler::RRScheduler
-//! [3]: scheduler::CFScheduler
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
@@ -41,6 +44,9 @@ cfg_if::cfg_if! {
 
         #[macro_use]
         mod run_queue;
+        mod sched;
+        #[cfg(feature = "sched_cfs")]
+        mod cfs;
         mod task;
         mod task_ext;
         mod api;
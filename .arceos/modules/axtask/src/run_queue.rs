@@ -8,13 +8,13 @@ use alloc::sync::Weak;
 use kernel_guard::BaseGuard;
 use kspin::SpinRaw;
 use lazyinit::LazyInit;
-use scheduler::BaseScheduler;
 
 use axhal::cpu::this_cpu_id;
 
+use crate::sched::{SchedPolicy, Scheduler};
 use crate::task::{CurrentTask, TaskState};
 use crate::wait_queue::WaitQueueGuard;
-use crate::{AxCpuMask, AxTaskRef, Scheduler, TaskInner, WaitQueue};
+use crate::{AxCpuMask, AxTaskRef, TaskInner, WaitQueue};
 
 macro_rules! percpu_static {
     ($(
@@ -178,6 +178,25 @@ pub(crate) fn select_run_queue<G: BaseGuard>(task: &AxTaskRef) -> AxRunQueueRef<
     }
 }
 
+/// Returns the run queue that `task` was last put into, where it is queued
+/// if it is ready.
+#[inline]
+pub(crate) fn task_run_queue<G: BaseGuard>(task: &AxTaskRef) -> AxRunQueueRef<'static, G> {
+    let irq_state = G::acquire();
+    #[cfg(not(feature = "smp"))]
+    let inner = {
+        let _ = task;
+        unsafe { RUN_QUEUE.current_ref_mut_raw() }
+    };
+    #[cfg(feature = "smp")]
+    let inner = get_run_queue(task.run_queue());
+    AxRunQueueRef {
+        inner,
+        state: irq_state,
+        _phantom: core::marker::PhantomData,
+    }
+}
+
 /// [`AxRunQueue`] represents a run queue for global system or a specific CPU.
 pub(crate) struct AxRunQueue {
     /// The ID of the CPU this run queue is associated with.
@@ -186,6 +205,10 @@ pub(crate) struct AxRunQueue {
     /// Since irq and preempt are preserved by the kernel guard hold by `AxRunQueueRef`,
     /// we just use a simple raw spin lock here.
     scheduler: SpinRaw<Scheduler>,
+    /// The task running on the CPU of this run queue, through which other
+    /// CPUs ask it to be preempted.
+    #[cfg(all(feature = "smp", feature = "preempt"))]
+    running: SpinRaw<Weak<crate::AxTask>>,
 }
 
 /// A reference to the run queue with specific guard.
@@ -238,6 +261,7 @@ impl<G: BaseGuard> AxRunQueueRef<'_, G> {
         );
         assert!(task.is_ready());
         self.inner.scheduler.lock().add_task(task);
+        self.inner.check_preempt_current();
     }
 
     /// Unblock one task by inserting it into the run queue.
@@ -264,7 +288,68 @@ impl<G: BaseGuard> AxRunQueueRef<'_, G> {
                 #[cfg(feature = "preempt")]
                 crate::current().set_preempt_pending(true);
             }
+            self.inner.check_preempt_current();
+        }
+    }
+
+    /// Changes the scheduling policy and the real-time priority of `task`,
+    /// moving it to its new queue if it is ready.
+    ///
+    /// Returns `false` if the task has been put into another run queue
+    /// meanwhile, where it should be changed instead.
+    pub fn set_sched_params(
+        &mut self,
+        task: &AxTaskRef,
+        policy: SchedPolicy,
+        priority: u8,
+    ) -> bool {
+        let mut scheduler = self.inner.scheduler.lock();
+        if task.run_queue() != self.inner.cpu_id {
+            return false;
+        }
+        scheduler.set_sched_params(task, policy, priority);
+        drop(scheduler);
+        self.inner.check_preempt_current();
+        true
+    }
+
+    /// Moves `task` away from this run queue if its CPU affinity no longer
+    /// allows this CPU.
+    ///
+    /// A ready task is moved to another run queue at once, and a running
+    /// one is asked to reschedule, which migrates it. A blocked task is put
+    /// into a proper run queue when it is woken up.
+    ///
+    /// Returns `false` if the task has been put into another run queue
+    /// meanwhile, where it should be checked instead.
+    #[cfg(feature = "smp")]
+    pub fn apply_affinity(&mut self, task: &AxTaskRef) -> bool {
+        let mut scheduler = self.inner.scheduler.lock();
+        if task.run_queue() != self.inner.cpu_id {
+            return false;
+        }
+        if let Some(task) = scheduler.remove_disallowed(task) {
+            drop(scheduler);
+            select_run_queue::<kernel_guard::NoPreemptIrqSave>(&task).add_task(task);
+            return true;
+        }
+        #[cfg(feature = "preempt")]
+        if task.is_running() && !task.cpumask().get(self.inner.cpu_id) {
+            task.set_preempt_pending(true);
+        }
+        true
+    }
+
+    /// Sets the priority of `task` in the scheduler of the normal tasks.
+    ///
+    /// Returns `None` if the task has been put into another run queue
+    /// meanwhile, where it should be changed instead.
+    pub fn set_priority(&mut self, task: &AxTaskRef, prio: isize) -> Option<bool> {
+        let mut scheduler = self.inner.scheduler.lock();
+        if task.run_queue() != self.inner.cpu_id {
+            return None;
         }
+        Some(scheduler.set_priority(task, prio))
     }
 }
 
@@ -283,6 +368,10 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
     /// This function will put the current task into this run queue with `Ready` state,
     /// and reschedule to the next task on this run queue.
     pub fn yield_current(&mut self) {
+        #[cfg(feature = "smp")]
+        if self.migrate_current_if_disallowed() {
+            return;
+        }
         let curr = &self.current_task;
         trace!("task yield: {}", curr.id_name());
         assert!(curr.is_running());
@@ -293,6 +382,20 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
         self.inner.resched();
     }
 
+    /// Migrates the current task if its CPU affinity, which may have been
+    /// changed by another task, no longer allows this CPU.
+    ///
+    /// Returns `true` if the task has been migrated.
+    #[cfg(feature = "smp")]
+    fn migrate_current_if_disallowed(&mut self) -> bool {
+        if self.current_task.cpumask().get(self.inner.cpu_id) {
+            return false;
+        }
+        let migration_task = migration_task(self.current_task.clone());
+        self.migrate_current(migration_task);
+        true
+    }
+
     /// Migrate the current task to a new run queue matching its CPU affinity and reschedule.
     /// This function will spawn a new `migration_task` to perform the migration, which will set
     /// current task to `Ready` state and select a proper run queue for it according to its CPU affinity,
@@ -342,8 +445,12 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
             can_preempt
         );
         if can_preempt {
+            #[cfg(feature = "smp")]
+            if self.migrate_current_if_disallowed() {
+                return;
+            }
             self.inner
-                .put_task_with_state(curr.clone(), TaskState::Running, true);
+                .put_task_with_state(self.current_task.clone(), TaskState::Running, true);
             self.inner.resched();
         } else {
             curr.set_preempt_pending(true);
@@ -450,11 +557,13 @@ impl AxRunQueue {
         // gc task should be pinned to the current CPU.
         gc_task.set_cpumask(AxCpuMask::one_shot(cpu_id));
 
-        let mut scheduler = Scheduler::new();
+        let mut scheduler = Scheduler::new(cpu_id);
         scheduler.add_task(gc_task);
         Self {
             cpu_id,
             scheduler: SpinRaw::new(scheduler),
+            #[cfg(all(feature = "smp", feature = "preempt"))]
+            running: SpinRaw::new(Weak::new()),
         }
     }
 
@@ -493,14 +602,45 @@ impl AxRunQueue {
                     core::hint::spin_loop();
                 }
             }
-            // TODO: priority
-            self.scheduler.lock().put_prev_task(task, preempt);
+            // A woken task is placed like a new one, e.g. at the back of its
+            // queue.
+            let mut scheduler = self.scheduler.lock();
+            if current_state == TaskState::Blocked {
+                scheduler.add_task(task);
+            } else {
+                scheduler.put_prev_task(task, preempt);
+            }
             true
         } else {
             false
         }
     }
 
+    /// Requests a reschedule if a ready task should preempt the task running
+    /// on the CPU of this run queue.
+    ///
+    /// The running task of another CPU is marked preempt-pending, and is
+    /// switched out when that CPU next enables preemption, e.g. on its next
+    /// interrupt, instead of waiting for its time slice to run out.
+    fn check_preempt_current(&self) {
+        #[cfg(feature = "preempt")]
+        if self.cpu_id == this_cpu_id() {
+            let curr = crate::current();
+            if self.scheduler.lock().should_preempt(curr.as_task_ref()) {
+                curr.set_preempt_pending(true);
+            }
+        }
+        #[cfg(all(feature = "smp", feature = "preempt"))]
+        if self.cpu_id != this_cpu_id() {
+            let scheduler = self.scheduler.lock();
+            if let Some(curr) = self.running.lock().upgrade() {
+                if scheduler.should_preempt(&curr) {
+                    curr.set_preempt_pending(true);
+                }
+            }
+        }
+    }
+
     /// Core reschedule subroutine.
     /// Pick the next task to run and switch to it.
     fn resched(&mut self) {
@@ -540,10 +680,25 @@ impl AxRunQueue {
             return;
         }
 
+        let now = axhal::time::monotonic_time_nanos();
+        prev_task.switch_out(now);
+        next_task.switch_in(now);
+        // A task that blocked gave up the CPU voluntarily, while one that is
+        // still runnable was preempted.
+        match prev_task.state() {
+            TaskState::Blocked => prev_task.count_context_switch(true),
+            TaskState::Ready => prev_task.count_context_switch(false),
+            _ => {}
+        }
+
         // Claim the task as running, we do this before switching to it
         // such that any running task will have this set.
         #[cfg(feature = "smp")]
         next_task.set_on_cpu(true);
+        #[cfg(all(feature = "smp", feature = "preempt"))]
+        {
+            *self.running.lock() = Arc::downgrade(&next_task);
+        }
 
         unsafe {
             let prev_ctx_ptr = prev_task.ctx_mut_ptr();
@@ -597,17 +752,30 @@ fn gc_entry() {
     }
 }
 
+/// Creates a task that migrates `task`, which should be the current task, to
+/// a run queue matching its CPU affinity.
+#[cfg(feature = "smp")]
+pub(crate) fn migration_task(task: AxTaskRef) -> AxTaskRef {
+    const MIGRATION_TASK_STACK_SIZE: usize = 4096;
+    TaskInner::new(
+        move || migrate_entry(task),
+        "migration-task".into(),
+        MIGRATION_TASK_STACK_SIZE,
+    )
+    .into_arc()
+}
+
 /// The task routine for migrating the current task to the correct CPU.
 ///
 /// It calls `select_run_queue` to get the correct run queue for the task, and
 /// then puts the task to the scheduler of target run queue.
 #[cfg(feature = "smp")]
-pub(crate) fn migrate_entry(migrated_task: AxTaskRef) {
+fn migrate_entry(migrated_task: AxTaskRef) {
     select_run_queue::<kernel_guard::NoPreemptIrqSave>(&migrated_task)
         .inner
         .scheduler
         .lock()
-        .put_prev_task(migrated_task, false)
+        .add_task(migrated_task)
 }
 
 /// Clear the `on_cpu` field of previous task running on this CPU.
@@ -0,0 +1,227 @@
+//! Scheduling classes.
+//!
+//! Real-time tasks (`SCHED_FIFO` and `SCHED_RR`) are kept in one FIFO queue
+//! per priority, and always run before the normal tasks, which are
+//! scheduled by the scheduler selected by cargo features.
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+use scheduler::BaseScheduler;
+
+use crate::{AxTaskRef, FairScheduler, TaskState};
+
+/// The highest real-time priority.
+pub const RT_PRIO_MAX: u8 = 99;
+
+/// The lowest nice value, i.e. the highest priority.
+pub const NICE_MIN: i8 = -20;
+/// The highest nice value, i.e. the lowest priority.
+pub const NICE_MAX: i8 = 19;
+
+/// The time slice of `SCHED_RR` tasks, in timer ticks. It is 100
+/// milliseconds, short enough for tasks of the same priority to take turns
+/// responsively, and at least one tick.
+pub(crate) const RR_TIME_SLICE: usize = if axconfig::TICKS_PER_SEC >= 10 {
+    axconfig::TICKS_PER_SEC / 10
+} else {
+    1
+};
+
+/// The scheduling policy of a task.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedPolicy {
+    /// The default time-sharing policy (`SCHED_OTHER`).
+    Normal = 0,
+    /// Real-time, first in first out (`SCHED_FIFO`). The task runs until it
+    /// blocks, yields or is preempted by a task of a higher priority.
+    Fifo = 1,
+    /// Real-time, round-robin (`SCHED_RR`). Like [`SchedPolicy::Fifo`], but
+    /// tasks of the same priority take turns every time slice.
+    RoundRobin = 2,
+}
+
+impl SchedPolicy {
+    /// Returns whether the policy is a real-time one.
+    pub const fn is_realtime(self) -> bool {
+        matches!(self, Self::Fifo | Self::RoundRobin)
+    }
+
+    /// Returns the range of the priorities of the policy.
+    pub const fn priority_range(self) -> (u8, u8) {
+        if self.is_realtime() {
+            (1, RT_PRIO_MAX)
+        } else {
+            (0, 0)
+        }
+    }
+}
+
+impl From<u8> for SchedPolicy {
+    #[inline]
+    fn from(policy: u8) -> Self {
+        match policy {
+            0 => Self::Normal,
+            1 => Self::Fifo,
+            2 => Self::RoundRobin,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// The scheduler of a run queue, with the real-time classes on top of the
+/// fair one.
+pub(crate) struct Scheduler {
+    /// The CPU of the run queue.
+    cpu_id: usize,
+    /// The ready real-time tasks, indexed by priority.
+    rt_queues: [VecDeque<AxTaskRef>; RT_PRIO_MAX as usize + 1],
+    /// The number of tasks in `rt_queues`.
+    rt_count: usize,
+    /// The scheduler of the normal tasks.
+    fair: FairScheduler,
+}
+
+impl Scheduler {
+    pub fn new(cpu_id: usize) -> Self {
+        Self {
+            cpu_id,
+            rt_queues: [const { VecDeque::new() }; RT_PRIO_MAX as usize + 1],
+            rt_count: 0,
+            fair: FairScheduler::new(),
+        }
+    }
+
+    pub fn scheduler_name() -> &'static str {
+        FairScheduler::scheduler_name()
+    }
+
+    /// Returns the highest priority of the ready real-time tasks, or 0 if
+    /// there is none.
+    fn rt_top_priority(&self) -> u8 {
+        if self.rt_count == 0 {
+            return 0;
+        }
+        (1..=RT_PRIO_MAX)
+            .rev()
+            .find(|&prio| !self.rt_queues[prio as usize].is_empty())
+            .unwrap_or(0)
+    }
+
+    /// Returns whether a ready task should preempt `current`.
+    pub fn should_preempt(&self, current: &AxTaskRef) -> bool {
+        self.rt_top_priority() > current.sched_params().1
+    }
+
+    fn push_rt(&mut self, task: AxTaskRef, prio: u8, front: bool) {
+        let queue = &mut self.rt_queues[prio as usize];
+        if front {
+            queue.push_front(task);
+        } else {
+            queue.push_back(task);
+        }
+        self.rt_count += 1;
+    }
+
+    /// Adds a new or woken task.
+    pub fn add_task(&mut self, task: AxTaskRef) {
+        task.set_run_queue(self.cpu_id);
+        let (policy, prio) = task.sched_params();
+        if policy.is_realtime() {
+            task.reset_rr_ticks();
+            self.push_rt(task, prio, false);
+        } else {
+            self.fair.add_task(task);
+        }
+    }
+
+    /// Removes `task` if it is in this scheduler.
+    pub fn remove_task(&mut self, task: &AxTaskRef) -> Option<AxTaskRef> {
+        let (policy, prio) = task.sched_params();
+        if policy.is_realtime() {
+            let queue = &mut self.rt_queues[prio as usize];
+            let index = queue.iter().position(|t| Arc::ptr_eq(t, task))?;
+            self.rt_count -= 1;
+            queue.remove(index)
+        } else {
+            self.fair.remove_task(task)
+        }
+    }
+
+    pub fn pick_next_task(&mut self) -> Option<AxTaskRef> {
+        match self.rt_top_priority() {
+            0 => self.fair.pick_next_task(),
+            prio => {
+                self.rt_count -= 1;
+                self.rt_queues[prio as usize].pop_front()
+            }
+        }
+    }
+
+    /// Puts back `prev`. If `preempt`, it was preempted, and a real-time
+    /// task goes back to the head of its queue unless its time slice is
+    /// used up.
+    pub fn put_prev_task(&mut self, prev: AxTaskRef, preempt: bool) {
+        prev.set_run_queue(self.cpu_id);
+        match prev.sched_params() {
+            (SchedPolicy::Normal, _) => self.fair.put_prev_task(prev, preempt),
+            (SchedPolicy::Fifo, prio) => self.push_rt(prev, prio, preempt),
+            (SchedPolicy::RoundRobin, prio) => {
+                let front = preempt && prev.rr_ticks() > 0;
+                if !front {
+                    prev.reset_rr_ticks();
+                }
+                self.push_rt(prev, prio, front);
+            }
+        }
+    }
+
+    /// Advances the state of `current` by a tick. Returns whether it should
+    /// be preempted.
+    pub fn task_tick(&mut self, current: &AxTaskRef) -> bool {
+        if self.should_preempt(current) {
+            return true;
+        }
+        match current.sched_params().0 {
+            SchedPolicy::Normal => self.fair.task_tick(current),
+            SchedPolicy::Fifo => false,
+            SchedPolicy::RoundRobin => current.tick_rr() == 0,
+        }
+    }
+
+    /// Changes the scheduling policy and the real-time priority of `task`,
+    /// moving it to its new queue if it is ready.
+    pub fn set_sched_params(&mut self, task: &AxTaskRef, policy: SchedPolicy, priority: u8) {
+        let queued = if task.state() == TaskState::Ready {
+            self.remove_task(task)
+        } else {
+            None
+        };
+        #[cfg(feature = "sched_cfs")]
+        if task.sched_params().0.is_realtime() != policy.is_realtime() {
+            // The time run in the other class does not count.
+            task.take_exec_time(task.is_running());
+        }
+        task.set_sched_params(policy, priority);
+        if let Some(task) = queued {
+            self.add_task(task);
+        }
+    }
+
+    /// Removes `task` if it is ready here but its CPU affinity no longer
+    /// allows this CPU, so that it can be moved to another run queue.
+    #[cfg(feature = "smp")]
+    pub fn remove_disallowed(&mut self, task: &AxTaskRef) -> Option<AxTaskRef> {
+        if task.cpumask().get(self.cpu_id) || task.state() != TaskState::Ready {
+            return None;
+        }
+        self.remove_task(task)
+    }
+
+    /// Sets the priority of `task` in the scheduler of the normal tasks,
+    /// e.g. the nice value with `sched_cfs`.
+    pub fn set_priority(&mut self, task: &AxTaskRef, prio: isize) -> bool {
+        self.fair.set_priority(task, prio)
+    }
+}
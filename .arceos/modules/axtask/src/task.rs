@@ -1,11 +1,10 @@
 use alloc::{boxed::Box, string::String, sync::Arc};
 use core::ops::Deref;
-use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU8, AtomicU64, Ordering};
+use core::sync::atomic::{
+    AtomicBool, AtomicI8, AtomicI32, AtomicU8, AtomicU16, AtomicU64, AtomicUsize, Ordering,
+};
 use core::{alloc::Layout, cell::UnsafeCell, fmt, ptr::NonNull};
 
-#[cfg(feature = "preempt")]
-use core::sync::atomic::AtomicUsize;
-
 use kspin::SpinNoIrq;
 use memory_addr::{VirtAddr, align_up_4k};
 
@@ -13,8 +12,9 @@ use axhal::arch::TaskContext;
 #[cfg(feature = "tls")]
 use axhal::tls::TlsArea;
 
+use crate::sched::{NICE_MAX, NICE_MIN, RR_TIME_SLICE, SchedPolicy};
 use crate::task_ext::AxTaskExt;
-use crate::{AxCpuMask, AxTask, AxTaskRef, WaitQueue};
+use crate::{AxCpuMask, AxTaskRef, WaitQueue};
 
 /// A unique identifier for a thread.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -47,6 +47,28 @@ pub struct TaskInner {
 
     /// CPU affinity mask.
     cpumask: SpinNoIrq<AxCpuMask>,
+    /// The CPU whose run queue the task was last put into.
+    run_queue: AtomicUsize,
+
+    /// The scheduling policy and the real-time priority, in the high and the
+    /// low byte, so that they are always read together.
+    sched_params: AtomicU16,
+    /// The ticks left in the time slice of a [`SchedPolicy::RoundRobin`]
+    /// task.
+    rr_ticks: AtomicUsize,
+    /// The nice value, from [`NICE_MIN`] to [`NICE_MAX`].
+    nice: AtomicI8,
+
+    /// The virtual runtime, in nanoseconds.
+    #[cfg(feature = "sched_cfs")]
+    vruntime: AtomicU64,
+    /// The `min_vruntime` of the run queue when `vruntime` was last
+    /// updated on it.
+    #[cfg(feature = "sched_cfs")]
+    min_vruntime: AtomicU64,
+    /// The CPU time already added to `vruntime`, in nanoseconds.
+    #[cfg(feature = "sched_cfs")]
+    exec_time_ns: AtomicU64,
 
     /// Mark whether the task is in the wait queue.
     in_wait_queue: AtomicBool,
@@ -69,6 +91,17 @@ pub struct TaskInner {
     exit_code: AtomicI32,
     wait_for_exit: WaitQueue,
 
+    /// The number of voluntary context switches, i.e. the task blocked.
+    nvcsw: AtomicU64,
+    /// The number of involuntary context switches, i.e. the task was
+    /// preempted or yielded.
+    nivcsw: AtomicU64,
+    /// The time spent on CPUs until the task was last switched out, in
+    /// nanoseconds.
+    cpu_time_ns: AtomicU64,
+    /// The monotonic time when the task was last switched in.
+    switched_in_ns: AtomicU64,
+
     kstack: Option<TaskStack>,
     ctx: UnsafeCell<TaskContext>,
     task_ext: AxTaskExt,
@@ -191,6 +224,20 @@ impl TaskInner {
         self.ctx.get_mut()
     }
 
+    /// Returns a mutable reference to the task context of a task that may
+    /// have been spawned.
+    ///
+    /// # Safety
+    ///
+    /// The caller must be the task itself, and the task must not be switched
+    /// out while the reference is alive. Changes to the context take effect
+    /// the next time the task is switched in.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn ctx_mut_unchecked(&self) -> &mut TaskContext {
+        unsafe { &mut *self.ctx_mut_ptr() }
+    }
+
     /// Returns the top address of the kernel stack.
     #[inline]
     pub const fn kernel_stack_top(&self) -> Option<VirtAddr> {
@@ -217,6 +264,45 @@ impl TaskInner {
         *self.cpumask.lock() = cpumask
     }
 
+    /// Returns the scheduling policy and the real-time priority of the task.
+    #[inline]
+    pub fn sched_params(&self) -> (SchedPolicy, u8) {
+        let params = self.sched_params.load(Ordering::Acquire);
+        (((params >> 8) as u8).into(), params as u8)
+    }
+
+    /// Sets the scheduling policy and the real-time priority of a task that
+    /// has not been spawned, e.g. to inherit them from its parent.
+    ///
+    /// Returns `false` if `priority` is out of the range of `policy`.
+    pub fn init_sched_params(&mut self, policy: SchedPolicy, priority: u8) -> bool {
+        let (min, max) = policy.priority_range();
+        if !(min..=max).contains(&priority) {
+            return false;
+        }
+        self.set_sched_params(policy, priority);
+        true
+    }
+
+    /// Returns the nice value of the task.
+    #[inline]
+    pub fn nice(&self) -> i8 {
+        self.nice.load(Ordering::Relaxed)
+    }
+
+    /// Sets the nice value of a task that has not been spawned, e.g. to
+    /// inherit it from its parent.
+    ///
+    /// Returns `false` if `nice` is out of the range from [`NICE_MIN`] to
+    /// [`NICE_MAX`].
+    pub fn init_nice(&mut self, nice: i8) -> bool {
+        if !(NICE_MIN..=NICE_MAX).contains(&nice) {
+            return false;
+        }
+        *self.nice.get_mut() = nice;
+        true
+    }
+
     /// Read the top address of the kernel stack for the task.
     #[inline]
     pub fn get_kernel_stack_top(&self) -> Option<usize> {
@@ -230,6 +316,27 @@ impl TaskInner {
     pub fn exit_code(&self) -> i32 {
         self.exit_code.load(Ordering::Acquire)
     }
+
+    /// Returns the time (in nanoseconds) the task has spent running on
+    /// CPUs.
+    pub fn cpu_time_ns(&self) -> u64 {
+        let cpu_time = self.cpu_time_ns.load(Ordering::Relaxed);
+        if self.is_running() {
+            let switched_in = self.switched_in_ns.load(Ordering::Relaxed);
+            cpu_time + axhal::time::monotonic_time_nanos().saturating_sub(switched_in)
+        } else {
+            cpu_time
+        }
+    }
+
+    /// Returns the numbers of voluntary and involuntary context switches of
+    /// the task.
+    pub fn context_switches(&self) -> (u64, u64) {
+        (
+            self.nvcsw.load(Ordering::Relaxed),
+            self.nivcsw.load(Ordering::Relaxed),
+        )
+    }
 }
 
 // private methods
@@ -244,6 +351,16 @@ impl TaskInner {
             state: AtomicU8::new(TaskState::Ready as u8),
             // By default, the task is allowed to run on all CPUs.
             cpumask: SpinNoIrq::new(AxCpuMask::full()),
+            run_queue: AtomicUsize::new(0),
+            sched_params: AtomicU16::new((SchedPolicy::Normal as u16) << 8),
+            rr_ticks: AtomicUsize::new(RR_TIME_SLICE),
+            nice: AtomicI8::new(0),
+            #[cfg(feature = "sched_cfs")]
+            vruntime: AtomicU64::new(0),
+            #[cfg(feature = "sched_cfs")]
+            min_vruntime: AtomicU64::new(0),
+            #[cfg(feature = "sched_cfs")]
+            exec_time_ns: AtomicU64::new(0),
             in_wait_queue: AtomicBool::new(false),
             #[cfg(feature = "irq")]
             timer_ticket_id: AtomicU64::new(0),
@@ -255,6 +372,10 @@ impl TaskInner {
             preempt_disable_count: AtomicUsize::new(0),
             exit_code: AtomicI32::new(0),
             wait_for_exit: WaitQueue::new(),
+            nvcsw: AtomicU64::new(0),
+            nivcsw: AtomicU64::new(0),
+            cpu_time_ns: AtomicU64::new(0),
+            switched_in_ns: AtomicU64::new(0),
             kstack: None,
             ctx: UnsafeCell::new(TaskContext::new()),
             task_ext: AxTaskExt::empty(),
@@ -283,7 +404,11 @@ impl TaskInner {
     }
 
     pub(crate) fn into_arc(self) -> AxTaskRef {
-        Arc::new(AxTask::new(self))
+        #[cfg(feature = "sched_cfs")]
+        let task = self;
+        #[cfg(not(feature = "sched_cfs"))]
+        let task = crate::AxTask::new(self);
+        Arc::new(task)
     }
 
     /// Returns the task's current state.
@@ -292,6 +417,114 @@ impl TaskInner {
         self.state.load(Ordering::Acquire).into()
     }
 
+    /// Records that the task is switched in at `now`.
+    #[inline]
+    pub(crate) fn switch_in(&self, now: u64) {
+        self.switched_in_ns.store(now, Ordering::Relaxed);
+    }
+
+    /// Records that the task is switched out at `now`, adding the time it
+    /// has been running to its CPU time.
+    #[inline]
+    pub(crate) fn switch_out(&self, now: u64) {
+        let switched_in = self.switched_in_ns.load(Ordering::Relaxed);
+        self.cpu_time_ns
+            .fetch_add(now.saturating_sub(switched_in), Ordering::Relaxed);
+    }
+
+    /// Sets the scheduling policy and the real-time priority. The task must
+    /// not be queued in a scheduler.
+    #[inline]
+    pub(crate) fn set_sched_params(&self, policy: SchedPolicy, priority: u8) {
+        self.sched_params
+            .store((policy as u16) << 8 | priority as u16, Ordering::Release);
+    }
+
+    /// Returns the CPU whose run queue the task was last put into.
+    #[inline]
+    pub(crate) fn run_queue(&self) -> usize {
+        self.run_queue.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_run_queue(&self, cpu_id: usize) {
+        self.run_queue.store(cpu_id, Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn rr_ticks(&self) -> usize {
+        self.rr_ticks.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub(crate) fn reset_rr_ticks(&self) {
+        self.rr_ticks.store(RR_TIME_SLICE, Ordering::Relaxed);
+    }
+
+    /// Consumes a tick of the time slice, and returns the ticks left.
+    #[inline]
+    pub(crate) fn tick_rr(&self) -> usize {
+        let ticks = self.rr_ticks().saturating_sub(1);
+        self.rr_ticks.store(ticks, Ordering::Relaxed);
+        ticks
+    }
+
+    #[cfg(feature = "sched_cfs")]
+    #[inline]
+    pub(crate) fn set_nice(&self, nice: i8) {
+        self.nice.store(nice, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "sched_cfs")]
+    #[inline]
+    pub(crate) fn vruntime(&self) -> u64 {
+        self.vruntime.load(Ordering::Relaxed)
+    }
+
+    /// Sets the virtual runtime. The task must not be queued in a
+    /// scheduler.
+    #[cfg(feature = "sched_cfs")]
+    #[inline]
+    pub(crate) fn set_vruntime(&self, vruntime: u64) {
+        self.vruntime.store(vruntime, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "sched_cfs")]
+    #[inline]
+    pub(crate) fn set_min_vruntime(&self, min_vruntime: u64) {
+        self.min_vruntime.store(min_vruntime, Ordering::Relaxed);
+    }
+
+    /// Returns how far the virtual runtime is ahead of the `min_vruntime`
+    /// recorded by [`Self::set_min_vruntime`].
+    #[cfg(feature = "sched_cfs")]
+    #[inline]
+    pub(crate) fn vlag(&self) -> i64 {
+        self.vruntime()
+            .wrapping_sub(self.min_vruntime.load(Ordering::Relaxed)) as i64
+    }
+
+    /// Returns the CPU time (in nanoseconds) used since the last call.
+    ///
+    /// `on_cpu` tells whether the task is running on a CPU, since its state
+    /// is changed before it is switched out.
+    #[cfg(feature = "sched_cfs")]
+    pub(crate) fn take_exec_time(&self, on_cpu: bool) -> u64 {
+        let mut cpu_time = self.cpu_time_ns.load(Ordering::Relaxed);
+        if on_cpu {
+            let switched_in = self.switched_in_ns.load(Ordering::Relaxed);
+            cpu_time += axhal::time::monotonic_time_nanos().saturating_sub(switched_in);
+        }
+        cpu_time.saturating_sub(self.exec_time_ns.swap(cpu_time, Ordering::Relaxed))
+    }
+
+    /// Counts a context switch away from the task.
+    #[inline]
+    pub(crate) fn count_context_switch(&self, voluntary: bool) {
+        let counter = if voluntary { &self.nvcsw } else { &self.nivcsw };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
     /// Set the task's state.
     #[inline]
     pub fn set_state(&self, state: TaskState) {
@@ -1,7 +1,8 @@
 use core::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Mutex, Once};
 
-use crate::{WaitQueue, api as axtask, current};
+use crate::sched::{RR_TIME_SLICE, SchedPolicy, Scheduler};
+use crate::{AxTaskRef, TaskInner, WaitQueue, api as axtask, current};
 
 static INIT: Once = Once::new();
 static SERIAL: Mutex<()> = Mutex::new(());
@@ -128,3 +129,154 @@ fn test_task_join() {
         assert_eq!(tasks[i].join(), Some(i as _));
     }
 }
+
+/// Creates a task which is not spawned, to be put into a [`Scheduler`]
+/// directly.
+fn new_task(name: &str, policy: SchedPolicy, priority: u8) -> AxTaskRef {
+    let mut task = TaskInner::new(|| {}, name.into(), 0x1000);
+    assert!(task.init_sched_params(policy, priority));
+    task.into_arc()
+}
+
+fn assert_picked(sched: &mut Scheduler, expected: &AxTaskRef) -> AxTaskRef {
+    let task = sched.pick_next_task().expect("no task is ready");
+    assert_eq!(task.id_name(), expected.id_name());
+    task
+}
+
+#[test]
+fn test_sched_fifo_preemption() {
+    let normal = new_task("N", SchedPolicy::Normal, 0);
+    let low = new_task("L", SchedPolicy::Fifo, 10);
+    let low2 = new_task("L2", SchedPolicy::Fifo, 10);
+    let high = new_task("H", SchedPolicy::Fifo, 20);
+    let mut sched = Scheduler::new(0);
+    sched.add_task(normal.clone());
+    sched.add_task(low.clone());
+    sched.add_task(low2.clone());
+
+    // Real-time tasks run before normal ones, and are not time-sliced.
+    let curr = assert_picked(&mut sched, &low);
+    assert!(!sched.task_tick(&curr));
+
+    // A task of a higher priority preempts the running one, which goes back
+    // to the head of its queue.
+    sched.add_task(high.clone());
+    assert!(sched.should_preempt(&curr));
+    assert!(sched.task_tick(&curr));
+    sched.put_prev_task(curr, true);
+    let curr = assert_picked(&mut sched, &high);
+    assert!(!sched.should_preempt(&curr));
+
+    // A task that yields goes to the tail of its queue, behind the tasks of
+    // the same priority.
+    sched.put_prev_task(curr, false);
+    // The task of the higher priority then exits, and is not put back.
+    assert_picked(&mut sched, &high);
+    let curr = assert_picked(&mut sched, &low);
+    sched.put_prev_task(curr, false);
+    assert_picked(&mut sched, &low2);
+    assert_picked(&mut sched, &low);
+    assert_picked(&mut sched, &normal);
+    assert!(sched.pick_next_task().is_none());
+}
+
+#[test]
+fn test_sched_rr_slice() {
+    assert!(RR_TIME_SLICE >= 2);
+    let a = new_task("A", SchedPolicy::RoundRobin, 10);
+    let b = new_task("B", SchedPolicy::RoundRobin, 10);
+    let mut sched = Scheduler::new(0);
+    sched.add_task(a.clone());
+    sched.add_task(b.clone());
+
+    // Preempted within its time slice, a task keeps its place and the rest
+    // of the slice.
+    let curr = assert_picked(&mut sched, &a);
+    assert!(!sched.task_tick(&curr));
+    sched.put_prev_task(curr, true);
+    let curr = assert_picked(&mut sched, &a);
+    assert_eq!(curr.rr_ticks(), RR_TIME_SLICE - 1);
+
+    // Once the slice is used up, the next task of the same priority runs,
+    // and the task gets a new slice at the tail of the queue.
+    for _ in 0..RR_TIME_SLICE - 2 {
+        assert!(!sched.task_tick(&curr));
+    }
+    assert!(sched.task_tick(&curr));
+    sched.put_prev_task(curr, true);
+    assert_eq!(a.rr_ticks(), RR_TIME_SLICE);
+    let curr = assert_picked(&mut sched, &b);
+    for _ in 0..RR_TIME_SLICE - 1 {
+        assert!(!sched.task_tick(&curr));
+    }
+    assert!(sched.task_tick(&curr));
+    sched.put_prev_task(curr, true);
+    assert_picked(&mut sched, &a);
+}
+
+#[cfg(feature = "sched_cfs")]
+#[test]
+fn test_cfs_nice_share() {
+    const TICK_NS: u64 = 1_000_000;
+    const TICKS: usize = 1000;
+
+    /// Charges `ns` of CPU time to `task`, as if it ran for that long and
+    /// was switched in again at a time that never comes.
+    fn run_for(task: &AxTaskRef, ns: u64) {
+        task.switch_in(0);
+        task.switch_out(ns);
+        task.switch_in(u64::MAX);
+    }
+
+    let tasks = [0, 10].map(|nice| {
+        let mut task = TaskInner::new(|| {}, format!("nice{nice}"), 0x1000);
+        assert!(task.init_nice(nice));
+        task.into_arc()
+    });
+    let mut sched = Scheduler::new(0);
+    for task in &tasks {
+        sched.add_task(task.clone());
+    }
+
+    let mut ran = [0usize; 2];
+    for _ in 0..TICKS {
+        let task = sched.pick_next_task().unwrap();
+        run_for(&task, TICK_NS);
+        let index = tasks.iter().position(|t| t.id() == task.id()).unwrap();
+        ran[index] += 1;
+        sched.put_prev_task(task, true);
+    }
+
+    // The CPU time is shared by the weights of the nice values 0 and 10,
+    // which are 1024 and 110.
+    let ratio = ran[0] as f64 / ran[1] as f64;
+    assert!((ratio - 1024.0 / 110.0).abs() < 0.5, "ratio {ratio}");
+    let vruntimes = tasks.each_ref().map(|task| task.vruntime());
+    assert!(vruntimes[0].abs_diff(vruntimes[1]) <= TICK_NS * 1024 / 110);
+}
+
+/// Checks that `remove_disallowed` takes a task out of a CPU's queue once its
+/// affinity mask no longer allows that CPU, and leaves it alone otherwise.
+#[cfg(feature = "smp")]
+#[test]
+fn test_remove_disallowed_task() {
+    use crate::AxCpuMask;
+
+    let task = new_task("A", SchedPolicy::Normal, 0);
+    let mut cpu0 = Scheduler::new(0);
+    let mut cpu1 = Scheduler::new(1);
+    cpu0.add_task(task.clone());
+
+    // A task allowed on its CPU stays there.
+    assert!(cpu0.remove_disallowed(&task).is_none());
+
+    // Otherwise it is taken out of the run queue, to be put into one of a
+    // CPU it is allowed on.
+    task.set_cpumask(AxCpuMask::one_shot(1));
+    let moved = cpu0.remove_disallowed(&task).expect("task not removed");
+    cpu1.add_task(moved);
+    assert_eq!(task.run_queue(), 1);
+    assert!(cpu0.pick_next_task().is_none());
+    assert_picked(&mut cpu1, &task);
+}
@@ -3,7 +3,12 @@ use core::ffi::{c_char, c_void};
 use alloc::string::ToString;
 use arceos_posix_api::AT_FDCWD;
 use axerrno::{AxError, LinuxError, LinuxResult};
+use axtask::{TaskExtRef, current};
 use macro_rules_attribute::apply;
+use starry_core::{
+    cred::{may_create, may_delete},
+    mm::invalidate_exec_cache,
+};
 
 use crate::{
     ptr::{PtrWrapper, UserConstPtr, UserPtr},
@@ -40,14 +45,19 @@ pub fn sys_mkdirat(dirfd: i32, path: UserConstPtr<c_char>, mode: u32) -> LinuxRe
         return Err(LinuxError::EINVAL);
     }
 
-    if mode != 0 {
-        info!("directory mode not supported.");
+    let cred = current().task_ext().cred();
+    let dir_path = arceos_posix_api::FilePath::new(path)?;
+    if axfs::api::metadata(&dir_path).is_ok() {
+        return Err(LinuxError::EEXIST);
     }
+    may_create(&cred, &dir_path)?;
 
-    axfs::api::create_dir(path).map(|_| 0).map_err(|err| {
+    axfs::api::create_dir(path).map_err(|err| {
         warn!("Failed to create directory {path}: {err:?}");
-        err.into()
-    })
+        LinuxError::from(err)
+    })?;
+    arceos_posix_api::set_file_meta(&dir_path, cred.fsuid, cred.fsgid, mode);
+    Ok(0)
 }
 
 #[repr(C)]
@@ -266,31 +276,36 @@ pub fn sys_unlinkat(dir_fd: isize, path: UserConstPtr<c_char>, flags: usize) ->
 
     const AT_REMOVEDIR: usize = 0x200;
 
-    arceos_posix_api::handle_file_path(dir_fd, Some(path.as_ptr() as _), false)
-        .inspect_err(|e| warn!("unlinkat error: {:?}", e))
-        .and_then(|path| {
-            if flags == AT_REMOVEDIR {
-                axfs::api::remove_dir(path.as_str())
-                    .inspect_err(|e| warn!("unlinkat error: {:?}", e))
-                    .map(|_| 0)
+    let path = arceos_posix_api::handle_file_path(dir_fd, Some(path.as_ptr() as _), false)
+        .inspect_err(|e| warn!("unlinkat error: {:?}", e))?;
+    may_delete(&current().task_ext().cred(), &path)?;
+
+    let result = if flags == AT_REMOVEDIR {
+        axfs::api::remove_dir(path.as_str()).inspect_err(|e| warn!("unlinkat error: {:?}", e))
+    } else {
+        axfs::api::metadata(path.as_str()).and_then(|metadata| {
+            if metadata.is_dir() {
+                Err(AxError::IsADirectory)
             } else {
-                axfs::api::metadata(path.as_str()).and_then(|metadata| {
-                    if metadata.is_dir() {
-                        Err(AxError::IsADirectory)
-                    } else {
-                        debug!("unlink file: {:?}", path);
-                        arceos_posix_api::HARDLINK_MANAGER
-                            .remove_link(&path)
-                            .ok_or_else(|| {
-                                debug!("unlink file error");
-                                AxError::NotFound
-                            })
-                            .map(|_| 0)
-                    }
-                })
+                debug!("unlink file: {:?}", path);
+                invalidate_exec_cache(&path);
+                arceos_posix_api::HARDLINK_MANAGER
+                    .remove_link(&path)
+                    .ok_or_else(|| {
+                        debug!("unlink file error");
+                        AxError::NotFound
+                    })
+                    .map(|_| ())
             }
         })
-        .map_err(|err| err.into())
+    };
+    result?;
+
+    // The file is gone unless other hard links refer to it.
+    if axfs::api::metadata(path.as_str()).is_err() {
+        arceos_posix_api::remove_file_meta(&path);
+    }
+    Ok(0)
 }
 
 pub fn sys_getcwd(buf: UserPtr<c_char>, size: usize) -> LinuxResult<isize> {
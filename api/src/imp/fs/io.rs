@@ -1,8 +1,16 @@
 use core::ffi::{c_char, c_void};
 
-use arceos_posix_api::ctypes::off_t;
+use alloc::vec::Vec;
+use arceos_posix_api::ctypes::{self, RLIMIT_FSIZE, off_t};
 use arceos_posix_api::{self as api, ctypes::mode_t};
-use axerrno::LinuxResult;
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{TaskExtRef, current};
+use starry_core::{
+    cred::{AccessMode, may_create, may_open},
+    mm::{add_exec_writer, deny_exec_write},
+    resource::RLIM_INFINITY,
+    signal::{SIGXFSZ, send_signal},
+};
 
 use crate::ptr::{PtrWrapper, UserConstPtr, UserPtr};
 
@@ -11,8 +19,33 @@ pub fn sys_read(fd: i32, buf: UserPtr<c_void>, count: usize) -> LinuxResult<isiz
     Ok(api::sys_read(fd, buf, count))
 }
 
+/// Limits a write of `len` bytes to the file `fd` by `RLIMIT_FSIZE`, which
+/// only applies to regular files.
+///
+/// Returns the number of bytes that may be written, so that a write crossing
+/// the limit is short. A write starting at or beyond the limit sends
+/// `SIGXFSZ` and fails with `EFBIG`.
+fn fsize_limit(fd: i32, len: usize) -> LinuxResult<usize> {
+    let curr = current();
+    let process = &curr.task_ext().process;
+    let limit = process.rlimit(RLIMIT_FSIZE).rlim_cur;
+    if limit == RLIM_INFINITY || len == 0 {
+        return Ok(len);
+    }
+    let Ok(file) = api::File::from_fd(fd) else {
+        return Ok(len);
+    };
+    let pos = file.inner().lock().write_position()?;
+    if pos >= limit {
+        send_signal(process, SIGXFSZ);
+        return Err(LinuxError::EFBIG);
+    }
+    Ok(len.min((limit - pos).try_into().unwrap_or(usize::MAX)))
+}
+
 pub fn sys_write(fd: i32, buf: UserConstPtr<c_void>, count: usize) -> LinuxResult<isize> {
     let buf = buf.get_as_bytes(count)?;
+    let count = fsize_limit(fd, count)?;
     Ok(api::sys_write(fd, buf, count))
 }
 
@@ -21,8 +54,27 @@ pub fn sys_writev(
     iov: UserConstPtr<api::ctypes::iovec>,
     iocnt: i32,
 ) -> LinuxResult<isize> {
-    let iov = iov.get_as_bytes(iocnt as _)?;
-    unsafe { Ok(api::sys_writev(fd, iov, iocnt)) }
+    let iov = iov.get_as_array(iocnt.max(0) as _)?;
+    let iovs = unsafe { core::slice::from_raw_parts(iov, iocnt.max(0) as _) };
+    let total = iovs.iter().map(|iov| iov.iov_len).sum();
+    let mut allowed = fsize_limit(fd, total)?;
+    if allowed == total {
+        return unsafe { Ok(api::sys_writev(fd, iov, iocnt)) };
+    }
+
+    // Only write the part of the buffers below the limit.
+    let iovs: Vec<_> = iovs
+        .iter()
+        .map_while(|iov| {
+            let len = iov.iov_len.min(allowed);
+            allowed -= len;
+            (len > 0).then_some(ctypes::iovec {
+                iov_base: iov.iov_base,
+                iov_len: len,
+            })
+        })
+        .collect();
+    unsafe { Ok(api::sys_writev(fd, iovs.as_ptr(), iovs.len() as _)) }
 }
 
 pub fn sys_readv(fd: i32, iov: UserPtr<api::ctypes::iovec>, iocnt: i32) -> LinuxResult<isize> {
@@ -30,6 +82,19 @@ pub fn sys_readv(fd: i32, iov: UserPtr<api::ctypes::iovec>, iocnt: i32) -> Linux
     unsafe { Ok(api::sys_readv(fd, iov, iocnt)) }
 }
 
+/// Returns the permissions needed to open a file with `flags`.
+fn open_access_mode(flags: u32) -> AccessMode {
+    let mut mode = match flags & 0b11 {
+        ctypes::O_RDONLY => AccessMode::READ,
+        ctypes::O_WRONLY => AccessMode::WRITE,
+        _ => AccessMode::READ | AccessMode::WRITE,
+    };
+    if flags & ctypes::O_TRUNC != 0 {
+        mode |= AccessMode::WRITE;
+    }
+    mode
+}
+
 pub fn sys_openat(
     dirfd: i32,
     path: UserConstPtr<c_char>,
@@ -37,7 +102,37 @@ pub fn sys_openat(
     modes: mode_t,
 ) -> LinuxResult<isize> {
     let path = path.get_as_null_terminated()?;
-    Ok(api::sys_openat(dirfd, path.as_ptr(), flags, modes) as _)
+
+    let cred = current().task_ext().cred();
+    let file_path = api::handle_file_path(dirfd as _, Some(path.as_ptr() as _), false)?;
+    let create = flags as u32 & ctypes::O_CREAT != 0 && axfs::api::metadata(&file_path).is_err();
+    let writable = flags as u32 & 0b11 != ctypes::O_RDONLY;
+    if create {
+        may_create(&cred, &file_path)?;
+    } else {
+        may_open(&cred, &file_path, open_access_mode(flags as u32))?;
+        // A running executable cannot be changed.
+        if writable || flags as u32 & ctypes::O_TRUNC != 0 {
+            deny_exec_write(&file_path)?;
+        }
+    }
+
+    let fd = api::sys_openat(dirfd, path.as_ptr(), flags, modes);
+    if create && fd >= 0 {
+        api::set_file_meta(&file_path, cred.fsuid, cred.fsgid, modes as u32);
+    }
+    if writable && fd >= 0 {
+        // The file cannot be executed while it is open for writing.
+        let registered = match api::File::from_fd(fd) {
+            Ok(file) => add_exec_writer(&file_path, &file),
+            Err(_) => Ok(()),
+        };
+        if let Err(err) = registered {
+            api::sys_close(fd);
+            return Err(err);
+        }
+    }
+    Ok(fd as _)
 }
 
 pub fn sys_open(path: UserConstPtr<c_char>, flags: i32, modes: mode_t) -> LinuxResult<isize> {
@@ -5,8 +5,8 @@ use axerrno::LinuxResult;
 
 use crate::ptr::{PtrWrapper, UserPtr};
 
-pub fn sys_pipe2(fds: UserPtr<i32>) -> LinuxResult<isize> {
+pub fn sys_pipe2(fds: UserPtr<i32>, flags: c_int) -> LinuxResult<isize> {
     let fds = fds.get_as_array(2)?;
     let fds_slice: &mut [c_int] = unsafe { core::slice::from_raw_parts_mut(fds, 2) };
-    Ok(api::sys_pipe(fds_slice) as _)
+    Ok(api::sys_pipe2(fds_slice, flags) as _)
 }
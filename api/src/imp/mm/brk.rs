@@ -1,3 +1,4 @@
+use arceos_posix_api::ctypes::RLIMIT_DATA;
 use axerrno::LinuxResult;
 use axtask::{TaskExtRef, current};
 use macro_rules_attribute::apply;
@@ -9,7 +10,13 @@ pub fn sys_brk(addr: usize) -> LinuxResult<isize> {
     let current_task = current();
     let mut return_val: isize = current_task.task_ext().get_heap_top() as isize;
     let heap_bottom = current_task.task_ext().get_heap_bottom() as usize;
-    if addr != 0 && addr >= heap_bottom && addr <= heap_bottom + axconfig::plat::USER_HEAP_SIZE {
+    // The heap is limited by `RLIMIT_DATA`, besides the area reserved for it.
+    let data_limit = current_task.task_ext().process.rlimit(RLIMIT_DATA).rlim_cur;
+    if addr != 0
+        && addr >= heap_bottom
+        && addr <= heap_bottom + axconfig::plat::USER_HEAP_SIZE
+        && (addr - heap_bottom) as u64 <= data_limit
+    {
         current_task.task_ext().set_heap_top(addr as u64);
         return_val = addr as isize;
     }
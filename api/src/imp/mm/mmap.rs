@@ -1,9 +1,11 @@
 use alloc::vec;
+use arceos_posix_api::ctypes::RLIMIT_AS;
 use axerrno::{LinuxError, LinuxResult};
 use axhal::paging::MappingFlags;
 use axtask::{TaskExtRef, current};
 use macro_rules_attribute::apply;
 use memory_addr::{VirtAddr, VirtAddrRange};
+use starry_core::vdso::vdso_range;
 
 use crate::{
     ptr::{PtrWrapper, UserPtr},
@@ -102,6 +104,11 @@ pub fn sys_mmap(
         addr, length, permission_flags, map_flags, fd, offset
     );
 
+    let as_limit = curr_ext.process.rlimit(RLIMIT_AS).rlim_cur;
+    if aspace.mapped_size().saturating_add(aligned_length) as u64 > as_limit {
+        return Err(LinuxError::ENOMEM);
+    }
+
     let start_addr = if map_flags.contains(MmapFlags::MAP_FIXED) {
         if addr.is_null() {
             return Err(LinuxError::EINVAL);
@@ -190,6 +197,12 @@ pub fn sys_mprotect(addr: UserPtr<usize>, length: usize, prot: i32) -> LinuxResu
     let mut aspace = curr_ext.aspace.lock();
     let length = memory_addr::align_up_4k(length);
     let start_addr = VirtAddr::from(addr as usize);
+    // The vvar page and the vDSO are shared with the kernel and every process.
+    if permission_flags.contains(MmapProt::PROT_WRITE)
+        && VirtAddrRange::from_start_size(start_addr, length).overlaps(vdso_range())
+    {
+        return Err(LinuxError::EACCES);
+    }
     aspace.protect(start_addr, length, permission_flags.into())?;
 
     Ok(0)
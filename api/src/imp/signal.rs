@@ -1,29 +1,137 @@
 use core::ffi::c_void;
 
-use axerrno::LinuxResult;
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::TrapFrame;
+use axtask::{TaskExtRef, current};
+use starry_core::{
+    cred::{CAP_KILL, Credentials},
+    process::{INIT_PID, ProcessData, find_process, find_thread, process_group, processes},
+    sigframe::restore_frame,
+    signal::{
+        MINSIGSTKSZ, SI_KERNEL, SI_TKILL, SI_USER, SIGCONT, SIGKILL, SIGSEGV, SIGSTOP, SigAction,
+        SigInfo, SignalStack, SignalStackFlags, discard_signals, force_signal, is_valid_signal,
+        send_signal_info, send_thread_signal, sig_bit,
+    },
+    task::read_trapframe_from_kstack,
+};
 
-use crate::ptr::{UserConstPtr, UserPtr};
+use crate::ptr::{PtrWrapper, UserConstPtr, UserPtr};
+
+/// `how` of sys_rt_sigprocmask
+const SIG_BLOCK: i32 = 0;
+const SIG_UNBLOCK: i32 = 1;
+const SIG_SETMASK: i32 = 2;
 
 pub fn sys_rt_sigprocmask(
-    _how: i32,
-    _set: UserConstPtr<c_void>,
-    _oldset: UserPtr<c_void>,
-    _sigsetsize: usize,
+    how: i32,
+    set: UserConstPtr<u64>,
+    oldset: UserPtr<u64>,
+    sigsetsize: usize,
 ) -> LinuxResult<isize> {
-    warn!("sys_rt_sigprocmask: not implemented");
+    if sigsetsize != size_of::<u64>() {
+        return Err(LinuxError::EINVAL);
+    }
+    let curr = current();
+    let task_ext = curr.task_ext();
+    let old = task_ext.blocked_signals();
+    if let Some(set) = set.nullable(UserConstPtr::get)? {
+        let set = unsafe { *set };
+        let blocked = match how {
+            SIG_BLOCK => old | set,
+            SIG_UNBLOCK => old & !set,
+            SIG_SETMASK => set,
+            _ => return Err(LinuxError::EINVAL),
+        };
+        task_ext.set_blocked_signals(blocked);
+    }
+    if let Some(oldset) = oldset.nullable(UserPtr::get)? {
+        unsafe { *oldset = old };
+    }
     Ok(0)
 }
 
 pub fn sys_rt_sigaction(
-    _signum: i32,
-    _act: UserConstPtr<c_void>,
-    _oldact: UserPtr<c_void>,
-    _sigsetsize: usize,
+    signum: i32,
+    act: UserConstPtr<SigAction>,
+    oldact: UserPtr<SigAction>,
+    sigsetsize: usize,
 ) -> LinuxResult<isize> {
-    warn!("sys_rt_sigaction: not implemented");
+    if sigsetsize != size_of::<u64>() || !is_valid_signal(signum as u32) {
+        return Err(LinuxError::EINVAL);
+    }
+    let signo = signum as u32;
+    let act = act.nullable(UserConstPtr::get)?;
+    if act.is_some() && (signo == SIGKILL || signo == SIGSTOP) {
+        return Err(LinuxError::EINVAL);
+    }
+    let oldact = oldact.nullable(UserPtr::get)?;
+
+    let process = &current().task_ext().process;
+    let actions = process.signal_actions();
+    let mut actions = actions.lock();
+    if let Some(oldact) = oldact {
+        unsafe { *oldact = actions.get(signo) };
+    }
+    if let Some(act) = act {
+        actions.set(signo, unsafe { *act });
+        // Pending signals that are now ignored are discarded.
+        if actions.is_ignored(signo) {
+            discard_signals(process, sig_bit(signo));
+        }
+    }
     Ok(0)
 }
 
+pub fn sys_sigaltstack(
+    ss: UserConstPtr<SignalStack>,
+    old_ss: UserPtr<SignalStack>,
+) -> LinuxResult<isize> {
+    let curr = current();
+    let task_ext = curr.task_ext();
+    let sp = read_trapframe_from_kstack(curr.get_kernel_stack_top().unwrap()).sp();
+    let old = task_ext.signal_stack();
+    if let Some(ss) = ss.nullable(UserConstPtr::get)? {
+        if old.contains(sp) {
+            return Err(LinuxError::EPERM);
+        }
+        let mut ss = unsafe { *ss };
+        let flags = ss.flags();
+        // SS_ONSTACK is accepted for compatibility, and means enabling.
+        let mode = flags - SignalStackFlags::SS_AUTODISARM;
+        if !mode.is_empty()
+            && mode != SignalStackFlags::SS_ONSTACK
+            && mode != SignalStackFlags::SS_DISABLE
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        if mode == SignalStackFlags::SS_DISABLE {
+            ss = SignalStack::default();
+        } else {
+            if ss.size < MINSIGSTKSZ {
+                return Err(LinuxError::ENOMEM);
+            }
+            ss.flags = (flags & SignalStackFlags::SS_AUTODISARM).bits();
+        }
+        task_ext.set_signal_stack(ss);
+    }
+    if let Some(old_ss) = old_ss.nullable(UserPtr::get)? {
+        unsafe { *old_ss = old.report(sp) };
+    }
+    Ok(0)
+}
+
+/// Returns from a signal handler to the context saved in its signal frame.
+///
+/// The thread gets `SIGSEGV` if the frame is invalid. The restored return
+/// value is returned, so that it is left unchanged in the trap frame.
+pub fn sys_rt_sigreturn(tf: &mut TrapFrame) -> LinuxResult<isize> {
+    if restore_frame(tf).is_err() {
+        force_signal(SigInfo::new(SIGSEGV, SI_KERNEL));
+    }
+    Ok(tf.retval() as isize)
+}
+
 // TODO: [stub] The method signature is not correct yet
 pub fn sys_rt_sigtimedwait(
     _signum: i32,
@@ -34,3 +142,120 @@ pub fn sys_rt_sigtimedwait(
     warn!("[sys_rt_sigaction] not implemented yet");
     Ok(0)
 }
+
+/// Returns the signal number of `sig`, or `None` for the null signal 0,
+/// which only checks that the target exists and may be signaled.
+fn parse_signal(sig: i32) -> LinuxResult<Option<u32>> {
+    match sig {
+        0 => Ok(None),
+        sig if sig > 0 && is_valid_signal(sig as u32) => Ok(Some(sig as u32)),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Checks that a thread with the credentials `cred` may send `signo` to
+/// `process`.
+///
+/// The real or effective user ID of the sender has to match the real or
+/// saved user ID of the target, unless the sender has `CAP_KILL`. `SIGCONT`
+/// may also be sent to any process in the same session.
+fn check_kill_permission(
+    cred: &Credentials,
+    process: &ProcessData,
+    signo: Option<u32>,
+) -> LinuxResult {
+    if cred.capable(CAP_KILL)
+        || (signo == Some(SIGCONT) && process.sid() == current().task_ext().process.sid())
+    {
+        return Ok(());
+    }
+    // A zombie has no threads left, and signals to it are discarded anyway.
+    let allowed = process.threads().first().is_none_or(|task| {
+        let target = task.task_ext().cred();
+        [cred.uid, cred.euid]
+            .iter()
+            .any(|&uid| uid == target.uid || uid == target.suid)
+    });
+    if allowed {
+        Ok(())
+    } else {
+        Err(LinuxError::EPERM)
+    }
+}
+
+/// Sends a signal to a process.
+///
+/// A positive `pid` selects the process containing the thread `pid`, 0 the
+/// process group of the caller, -1 every process but init and the caller,
+/// and any other negative value the process group `-pid`. The call
+/// succeeds if the signal was sent to at least one of them.
+pub fn sys_kill(pid: i32, sig: i32) -> LinuxResult<isize> {
+    let signo = parse_signal(sig)?;
+    let curr = current();
+    let task_ext = curr.task_ext();
+    let targets = match pid {
+        0 => process_group(task_ext.process.pgid()),
+        -1 => processes()
+            .into_iter()
+            .filter(|process| process.pid != INIT_PID && !Arc::ptr_eq(process, &task_ext.process))
+            .collect(),
+        i32::MIN => return Err(LinuxError::ESRCH),
+        pid if pid > 0 => find_thread(pid as usize)
+            .map(|task| task.task_ext().process.clone())
+            .or_else(|| find_process(pid as usize))
+            .into_iter()
+            .collect(),
+        pid => process_group(-pid as usize),
+    };
+
+    let cred = task_ext.cred();
+    let mut result = Err(LinuxError::ESRCH);
+    for process in targets {
+        if let Err(err) = check_kill_permission(&cred, &process, signo) {
+            if result.is_err() {
+                result = Err(err);
+            }
+            continue;
+        }
+        if let Some(signo) = signo {
+            let info = SigInfo::sender(signo, SI_USER, task_ext.process.pid, cred.uid);
+            send_signal_info(&process, info);
+        }
+        result = Ok(0);
+    }
+    result
+}
+
+/// Sends a signal to the thread `tid`, which has to belong to the process
+/// `tgid` unless it is `None`.
+fn send_to_thread(tgid: Option<i32>, tid: i32, sig: i32) -> LinuxResult<isize> {
+    let signo = parse_signal(sig)?;
+    if tid <= 0 || tgid.is_some_and(|tgid| tgid <= 0) {
+        return Err(LinuxError::EINVAL);
+    }
+    let task = find_thread(tid as usize).ok_or(LinuxError::ESRCH)?;
+    let process = &task.task_ext().process;
+    if tgid.is_some_and(|tgid| process.pid != tgid as usize) {
+        return Err(LinuxError::ESRCH);
+    }
+
+    let curr = current();
+    let cred = curr.task_ext().cred();
+    check_kill_permission(&cred, process, signo)?;
+    if let Some(signo) = signo {
+        let info = SigInfo::sender(signo, SI_TKILL, curr.task_ext().process.pid, cred.uid);
+        send_thread_signal(&task, info);
+    }
+    Ok(0)
+}
+
+/// Sends a signal to the thread `tid` of the process `tgid`.
+pub fn sys_tgkill(tgid: i32, tid: i32, sig: i32) -> LinuxResult<isize> {
+    send_to_thread(Some(tgid), tid, sig)
+}
+
+/// Sends a signal to the thread `tid`. It is obsolete, as the thread may
+/// have exited and its TID been reused; use [`sys_tgkill`] instead.
+pub fn sys_tkill(tid: i32, sig: i32) -> LinuxResult<isize> {
+    send_to_thread(None, tid, sig)
+}
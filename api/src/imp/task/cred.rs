@@ -0,0 +1,234 @@
+use alloc::vec::Vec;
+
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{TaskExtRef, current};
+use starry_core::{
+    cred::{CAP_FULL_SET, Credentials, NGROUPS_MAX},
+    process::find_thread,
+};
+
+use crate::ptr::{PtrWrapper, UserConstPtr, UserPtr};
+
+/// Converts an ID argument, where -1 means that the ID is left unchanged.
+fn optional_id(id: u32) -> Option<u32> {
+    (id != u32::MAX).then_some(id)
+}
+
+fn current_cred() -> Credentials {
+    current().task_ext().cred()
+}
+
+pub fn sys_getuid() -> LinuxResult<isize> {
+    Ok(current_cred().uid as _)
+}
+
+pub fn sys_geteuid() -> LinuxResult<isize> {
+    Ok(current_cred().euid as _)
+}
+
+pub fn sys_getgid() -> LinuxResult<isize> {
+    Ok(current_cred().gid as _)
+}
+
+pub fn sys_getegid() -> LinuxResult<isize> {
+    Ok(current_cred().egid as _)
+}
+
+pub fn sys_setuid(uid: u32) -> LinuxResult<isize> {
+    let uid = optional_id(uid).ok_or(LinuxError::EINVAL)?;
+    current().task_ext().update_cred(|cred| cred.set_uid(uid))?;
+    Ok(0)
+}
+
+pub fn sys_setgid(gid: u32) -> LinuxResult<isize> {
+    let gid = optional_id(gid).ok_or(LinuxError::EINVAL)?;
+    current().task_ext().update_cred(|cred| cred.set_gid(gid))?;
+    Ok(0)
+}
+
+pub fn sys_setreuid(ruid: u32, euid: u32) -> LinuxResult<isize> {
+    current()
+        .task_ext()
+        .update_cred(|cred| cred.set_reuid(optional_id(ruid), optional_id(euid)))?;
+    Ok(0)
+}
+
+pub fn sys_setregid(rgid: u32, egid: u32) -> LinuxResult<isize> {
+    current()
+        .task_ext()
+        .update_cred(|cred| cred.set_regid(optional_id(rgid), optional_id(egid)))?;
+    Ok(0)
+}
+
+pub fn sys_setresuid(ruid: u32, euid: u32, suid: u32) -> LinuxResult<isize> {
+    current().task_ext().update_cred(|cred| {
+        cred.set_resuid(optional_id(ruid), optional_id(euid), optional_id(suid))
+    })?;
+    Ok(0)
+}
+
+pub fn sys_setresgid(rgid: u32, egid: u32, sgid: u32) -> LinuxResult<isize> {
+    current().task_ext().update_cred(|cred| {
+        cred.set_resgid(optional_id(rgid), optional_id(egid), optional_id(sgid))
+    })?;
+    Ok(0)
+}
+
+pub fn sys_getresuid(
+    ruid: UserPtr<u32>,
+    euid: UserPtr<u32>,
+    suid: UserPtr<u32>,
+) -> LinuxResult<isize> {
+    let cred = current_cred();
+    unsafe {
+        *ruid.get()? = cred.uid;
+        *euid.get()? = cred.euid;
+        *suid.get()? = cred.suid;
+    }
+    Ok(0)
+}
+
+pub fn sys_getresgid(
+    rgid: UserPtr<u32>,
+    egid: UserPtr<u32>,
+    sgid: UserPtr<u32>,
+) -> LinuxResult<isize> {
+    let cred = current_cred();
+    unsafe {
+        *rgid.get()? = cred.gid;
+        *egid.get()? = cred.egid;
+        *sgid.get()? = cred.sgid;
+    }
+    Ok(0)
+}
+
+/// Returns the supplementary groups. With `size` 0, only their number is
+/// returned.
+pub fn sys_getgroups(size: i32, list: UserPtr<u32>) -> LinuxResult<isize> {
+    let groups = current_cred().groups;
+    if size < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    if size == 0 {
+        return Ok(groups.len() as _);
+    }
+    if (size as usize) < groups.len() {
+        return Err(LinuxError::EINVAL);
+    }
+    let list = list.get_as_array(groups.len())?;
+    unsafe { core::ptr::copy_nonoverlapping(groups.as_ptr(), list, groups.len()) };
+    Ok(groups.len() as _)
+}
+
+pub fn sys_setgroups(size: usize, list: UserConstPtr<u32>) -> LinuxResult<isize> {
+    if size > NGROUPS_MAX {
+        return Err(LinuxError::EINVAL);
+    }
+    let groups = if size == 0 {
+        Vec::new()
+    } else {
+        let list = list.get_as_array(size)?;
+        unsafe { core::slice::from_raw_parts(list, size) }.to_vec()
+    };
+    current()
+        .task_ext()
+        .update_cred(|cred| cred.set_groups(groups))?;
+    Ok(0)
+}
+
+/// The capability interface with 32-bit sets.
+const LINUX_CAPABILITY_VERSION_1: u32 = 0x1998_0330;
+/// Deprecated, same as [`LINUX_CAPABILITY_VERSION_3`].
+const LINUX_CAPABILITY_VERSION_2: u32 = 0x2007_1026;
+/// The capability interface with 64-bit sets, split into two
+/// [`CapUserData`].
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+#[repr(C)]
+pub struct CapUserHeader {
+    version: u32,
+    pid: i32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct CapUserData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// Returns the number of [`CapUserData`] used by the capability interface
+/// in `header`. An unknown version is replaced by the preferred one, and
+/// rejected with `EINVAL`.
+fn capability_sets(header: &mut CapUserHeader) -> LinuxResult<usize> {
+    match header.version {
+        LINUX_CAPABILITY_VERSION_1 => Ok(1),
+        LINUX_CAPABILITY_VERSION_2 | LINUX_CAPABILITY_VERSION_3 => Ok(2),
+        _ => {
+            header.version = LINUX_CAPABILITY_VERSION_3;
+            Err(LinuxError::EINVAL)
+        }
+    }
+}
+
+pub fn sys_capget(
+    header: UserPtr<CapUserHeader>,
+    data: UserPtr<CapUserData>,
+) -> LinuxResult<isize> {
+    let header = unsafe { &mut *header.get()? };
+    let sets = match capability_sets(header) {
+        Ok(sets) => sets,
+        // Probing for the preferred version.
+        Err(_) if data.address().as_ptr().is_null() => return Ok(0),
+        Err(err) => return Err(err),
+    };
+    let cred = match header.pid {
+        pid if pid < 0 => return Err(LinuxError::EINVAL),
+        0 => current_cred(),
+        pid => find_thread(pid as usize)
+            .ok_or(LinuxError::ESRCH)?
+            .task_ext()
+            .cred(),
+    };
+    let Some(data) = data.nullable(|data| data.get_as_array(sets))? else {
+        return Ok(0);
+    };
+    for i in 0..sets {
+        unsafe {
+            *data.add(i) = CapUserData {
+                effective: (cred.cap_effective >> (32 * i)) as u32,
+                permitted: (cred.cap_permitted >> (32 * i)) as u32,
+                inheritable: (cred.cap_inheritable >> (32 * i)) as u32,
+            };
+        }
+    }
+    Ok(0)
+}
+
+/// Sets the capabilities of the current process. Those of other processes
+/// cannot be changed, and fail with `EPERM`.
+pub fn sys_capset(
+    header: UserPtr<CapUserHeader>,
+    data: UserConstPtr<CapUserData>,
+) -> LinuxResult<isize> {
+    let header = unsafe { &mut *header.get()? };
+    let sets = capability_sets(header)?;
+    let curr = current();
+    if header.pid != 0 && header.pid as usize != curr.task_ext().tid {
+        return Err(LinuxError::EPERM);
+    }
+    let data = unsafe { core::slice::from_raw_parts(data.get_as_array(sets)?, sets) };
+    let combine = |f: fn(&CapUserData) -> u32| {
+        data.iter()
+            .enumerate()
+            .fold(0, |set, (i, data)| set | (f(data) as u64) << (32 * i))
+            & CAP_FULL_SET
+    };
+    let effective = combine(|data| data.effective);
+    let permitted = combine(|data| data.permitted);
+    let inheritable = combine(|data| data.inheritable);
+    curr.task_ext()
+        .update_cred(|cred| cred.set_capabilities(effective, permitted, inheritable))?;
+    Ok(0)
+}
@@ -0,0 +1,126 @@
+use core::time::Duration;
+
+use arceos_posix_api::ctypes::timespec;
+use axerrno::{LinuxError, LinuxResult};
+use macro_rules_attribute::apply;
+use starry_core::{
+    futex::{FUTEX_BITSET_MATCH_ANY, FutexKey, futex_requeue, futex_wait, futex_wake},
+    time::realtime_nanos,
+};
+
+use crate::{
+    ptr::{PtrWrapper, UserConstPtr},
+    syscall_instrument,
+};
+
+/// Futex operations
+const FUTEX_WAIT: u32 = 0;
+const FUTEX_WAKE: u32 = 1;
+const FUTEX_REQUEUE: u32 = 3;
+const FUTEX_CMP_REQUEUE: u32 = 4;
+const FUTEX_WAIT_BITSET: u32 = 9;
+const FUTEX_WAKE_BITSET: u32 = 10;
+
+/// Futex flags
+const FUTEX_PRIVATE_FLAG: u32 = 128;
+const FUTEX_CLOCK_REALTIME: u32 = 256;
+const FUTEX_CMD_MASK: u32 = !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME);
+
+/// Reads the value of the futex at `uaddr`.
+fn futex_value(uaddr: usize) -> LinuxResult<u32> {
+    let ptr = UserConstPtr::<u32>::from(uaddr).get()?;
+    Ok(unsafe { ptr.read_volatile() })
+}
+
+fn timespec_to_duration(ts: &timespec) -> LinuxResult<Duration> {
+    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+}
+
+/// Converts the timeout argument of a wait operation to a relative duration.
+///
+/// The timeout is relative for `FUTEX_WAIT`, and absolute for
+/// `FUTEX_WAIT_BITSET` (measured against `CLOCK_REALTIME` if
+/// `FUTEX_CLOCK_REALTIME` is set, or `CLOCK_MONOTONIC` otherwise).
+fn wait_timeout(
+    timeout: UserConstPtr<timespec>,
+    absolute: bool,
+    realtime: bool,
+) -> LinuxResult<Option<Duration>> {
+    let Some(ts) = timeout.nullable(UserConstPtr::get)? else {
+        return Ok(None);
+    };
+    let dur = timespec_to_duration(unsafe { &*ts })?;
+    if !absolute {
+        return Ok(Some(dur));
+    }
+    let now = if realtime {
+        Duration::from_nanos(realtime_nanos())
+    } else {
+        axhal::time::monotonic_time()
+    };
+    Ok(Some(dur.saturating_sub(now)))
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_futex(
+    uaddr: usize,
+    futex_op: u32,
+    val: u32,
+    timeout: usize,
+    uaddr2: usize,
+    val3: u32,
+) -> LinuxResult<isize> {
+    let cmd = futex_op & FUTEX_CMD_MASK;
+    let realtime = futex_op & FUTEX_CLOCK_REALTIME != 0;
+    if realtime && cmd != FUTEX_WAIT && cmd != FUTEX_WAIT_BITSET {
+        return Err(LinuxError::ENOSYS);
+    }
+    if uaddr % align_of::<u32>() != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let key = FutexKey::new_current(uaddr);
+
+    match cmd {
+        FUTEX_WAIT | FUTEX_WAIT_BITSET => {
+            let bitset = if cmd == FUTEX_WAIT {
+                FUTEX_BITSET_MATCH_ANY
+            } else {
+                val3
+            };
+            let timeout = wait_timeout(timeout.into(), cmd == FUTEX_WAIT_BITSET, realtime)?;
+            futex_wait(key, bitset, timeout, || Ok(futex_value(uaddr)? == val))?;
+            Ok(0)
+        }
+        FUTEX_WAKE | FUTEX_WAKE_BITSET => {
+            let bitset = if cmd == FUTEX_WAKE {
+                FUTEX_BITSET_MATCH_ANY
+            } else {
+                val3
+            };
+            if bitset == 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            Ok(futex_wake(key, val as usize, bitset) as _)
+        }
+        FUTEX_REQUEUE | FUTEX_CMP_REQUEUE => {
+            if uaddr2 % align_of::<u32>() != 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            // For requeue operations, the timeout argument is the maximum
+            // number of waiters to requeue.
+            let requeue_count = timeout;
+            let new_key = FutexKey::new_current(uaddr2);
+            let n = futex_requeue(key, val as usize, new_key, requeue_count, || {
+                Ok(cmd == FUTEX_REQUEUE || futex_value(uaddr)? == val3)
+            })?;
+            Ok(n as _)
+        }
+        _ => {
+            warn!("sys_futex: unsupported operation {}", futex_op);
+            Err(LinuxError::ENOSYS)
+        }
+    }
+}
@@ -1,5 +1,13 @@
+mod cred;
+mod futex;
+mod pidfd;
+mod resource;
 mod schedule;
 mod thread;
 
+pub use self::cred::*;
+pub use self::futex::*;
+pub use self::pidfd::*;
+pub use self::resource::*;
 pub use self::schedule::*;
 pub use self::thread::*;
@@ -0,0 +1,62 @@
+use alloc::sync::Arc;
+use core::ffi::c_int;
+
+use arceos_posix_api::{FileLike, add_file_like_with_cloexec, ctypes};
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use starry_core::process::ProcessData;
+
+/// A file descriptor referring to a process, created by `CLONE_PIDFD`.
+///
+/// It becomes readable when the process exits.
+pub struct PidFd {
+    process: Arc<ProcessData>,
+}
+
+impl PidFd {
+    /// Creates a pidfd for `process` in the fd table of the current task.
+    /// It is close-on-exec, so that it does not leak into executed programs.
+    pub fn add_to_fd_table(process: Arc<ProcessData>) -> LinuxResult<c_int> {
+        add_file_like_with_cloexec(Arc::new(Self { process }), true)
+    }
+
+    /// Returns the process this pidfd refers to.
+    pub fn process(&self) -> &Arc<ProcessData> {
+        &self.process
+    }
+}
+
+impl FileLike for PidFd {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        Ok(ctypes::stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode: 0o600, // anonymous inode, rw-------
+            st_blksize: 4096,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: self.process.is_zombie(),
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+use alloc::sync::Arc;
+
+use arceos_posix_api::{AX_FILE_LIMIT, FD_TABLE, ctypes::RLIMIT_NOFILE};
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{TaskExtRef, current};
+use macro_rules_attribute::apply;
+use starry_core::{
+    cred::CAP_SYS_RESOURCE,
+    ctypes::Rusage,
+    process::find_thread,
+    resource::{RLIM_NLIMITS, Rlimit, process_usage, thread_usage},
+};
+
+use crate::{
+    ptr::{PtrWrapper, UserConstPtr, UserPtr},
+    syscall_instrument,
+};
+
+/// Returns the limit of `resource` of the process with the thread `pid`, or
+/// of the current process if `pid` is 0, and replaces it with `new_limit`.
+///
+/// Without `CAP_SYS_RESOURCE`, only the processes whose user and group IDs
+/// all match the real ones of the caller can be accessed, and no hard limit
+/// can be raised.
+fn do_prlimit(pid: i32, resource: u32, new_limit: Option<Rlimit>) -> LinuxResult<Rlimit> {
+    if resource as usize >= RLIM_NLIMITS {
+        return Err(LinuxError::EINVAL);
+    }
+    let curr = current();
+    let task = match pid {
+        0 => curr.as_task_ref().clone(),
+        pid if pid > 0 => find_thread(pid as usize).ok_or(LinuxError::ESRCH)?,
+        _ => return Err(LinuxError::ESRCH),
+    };
+    let target = task.task_ext();
+    let cred = curr.task_ext().cred();
+    if !Arc::ptr_eq(&target.process, &curr.task_ext().process) {
+        let tcred = target.cred();
+        let same_user = [tcred.uid, tcred.euid, tcred.suid]
+            .iter()
+            .all(|&uid| uid == cred.uid)
+            && [tcred.gid, tcred.egid, tcred.sgid]
+                .iter()
+                .all(|&gid| gid == cred.gid);
+        if !same_user && !cred.capable(CAP_SYS_RESOURCE) {
+            return Err(LinuxError::EPERM);
+        }
+    }
+
+    let process = &target.process;
+    let old_limit = process.rlimit(resource);
+    if let Some(new_limit) = new_limit {
+        if new_limit.rlim_cur > new_limit.rlim_max {
+            return Err(LinuxError::EINVAL);
+        }
+        if new_limit.rlim_max > old_limit.rlim_max && !cred.capable(CAP_SYS_RESOURCE) {
+            return Err(LinuxError::EPERM);
+        }
+        if resource == RLIMIT_NOFILE && new_limit.rlim_max > AX_FILE_LIMIT as u64 {
+            return Err(LinuxError::EPERM);
+        }
+        process.set_rlimit(resource, new_limit);
+        if resource == RLIMIT_NOFILE {
+            FD_TABLE
+                .deref_from(&target.ns)
+                .write()
+                .set_limit(new_limit.rlim_cur as usize);
+        }
+    }
+    Ok(old_limit)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_prlimit64(
+    pid: i32,
+    resource: u32,
+    new_limit: UserConstPtr<Rlimit>,
+    old_limit: UserPtr<Rlimit>,
+) -> LinuxResult<isize> {
+    let new_limit = new_limit
+        .nullable(UserConstPtr::get)?
+        .map(|limit| unsafe { *limit });
+    let old_limit = old_limit.nullable(UserPtr::get)?;
+    let limit = do_prlimit(pid, resource, new_limit)?;
+    if let Some(old_limit) = old_limit {
+        unsafe { *old_limit = limit };
+    }
+    Ok(0)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_getrlimit(resource: u32, limit: UserPtr<Rlimit>) -> LinuxResult<isize> {
+    let limit = limit.get()?;
+    unsafe { *limit = do_prlimit(0, resource, None)? };
+    Ok(0)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_setrlimit(resource: u32, limit: UserConstPtr<Rlimit>) -> LinuxResult<isize> {
+    let limit = unsafe { *limit.get()? };
+    do_prlimit(0, resource, Some(limit))?;
+    Ok(0)
+}
+
+/// The resources used by the calling process.
+const RUSAGE_SELF: i32 = 0;
+/// The resources used by the reaped children of the calling process.
+const RUSAGE_CHILDREN: i32 = -1;
+/// The resources used by the calling thread.
+const RUSAGE_THREAD: i32 = 1;
+
+#[apply(syscall_instrument)]
+pub fn sys_getrusage(who: i32, usage: UserPtr<Rusage>) -> LinuxResult<isize> {
+    let curr = current();
+    let process = &curr.task_ext().process;
+    if who == RUSAGE_SELF || who == RUSAGE_THREAD {
+        process.update_maxrss(curr.task_ext().aspace.lock().resident_size() / 1024);
+    }
+    let result = match who {
+        RUSAGE_SELF => process_usage(process),
+        RUSAGE_CHILDREN => process.children_usage(),
+        RUSAGE_THREAD => {
+            // The threads share the address space, so the maximum resident
+            // set size is the one of the process.
+            let mut result = thread_usage(curr.as_task_ref());
+            result.maxrss_kb = process.exited_usage().maxrss_kb;
+            result
+        }
+        _ => return Err(LinuxError::EINVAL),
+    };
+    unsafe { *usage.get()? = result.into() };
+    Ok(0)
+}
@@ -1,5 +1,17 @@
-use arceos_posix_api as api;
-use axerrno::LinuxResult;
+use alloc::{vec, vec::Vec};
+use core::{slice, time::Duration};
+
+use arceos_posix_api::{
+    self as api,
+    ctypes::{RLIMIT_NICE, RLIMIT_RTPRIO},
+};
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::NANOS_PER_SEC;
+use axtask::{AxCpuMask, AxTaskRef, NICE_MAX, NICE_MIN, SchedPolicy, TaskExtRef, current};
+use starry_core::{
+    cred::CAP_SYS_NICE,
+    process::{find_thread, process_group, processes},
+};
 
 use crate::ptr::{PtrWrapper, UserConstPtr, UserPtr};
 
@@ -13,3 +25,278 @@ pub fn sys_nanosleep(
 ) -> LinuxResult<isize> {
     unsafe { Ok(api::sys_nanosleep(req.get()?, rem.get()?) as _) }
 }
+
+/// Returns the CPU the current task is running on. There is a single NUMA
+/// node.
+pub fn sys_getcpu(cpu: UserPtr<u32>, node: UserPtr<u32>) -> LinuxResult<isize> {
+    if let Some(cpu) = cpu.nullable(UserPtr::get)? {
+        unsafe { *cpu = axhal::cpu::this_cpu_id() as u32 };
+    }
+    if let Some(node) = node.nullable(UserPtr::get)? {
+        unsafe { *node = 0 };
+    }
+    Ok(0)
+}
+
+/// The size (in bytes) of the CPU masks of the kernel, in whole words. The C
+/// libraries pass larger masks, e.g. 128 bytes.
+const CPU_MASK_SIZE: usize = axconfig::SMP.div_ceil(usize::BITS as usize) * size_of::<usize>();
+
+/// Sets the CPUs the thread `pid` may run on. A running thread is migrated
+/// if its CPU is no longer allowed.
+///
+/// The CPUs beyond `cpusetsize` are not allowed, and those that do not exist
+/// are ignored, so that a mask sized for more CPUs can be passed.
+pub fn sys_sched_setaffinity(
+    pid: i32,
+    cpusetsize: usize,
+    mask: UserConstPtr<u8>,
+) -> LinuxResult<isize> {
+    let len = cpusetsize.min(CPU_MASK_SIZE);
+    let bytes = unsafe { slice::from_raw_parts(mask.get_as_bytes(len)?, len) };
+    let task = sched_target(pid)?;
+    let cred = current().task_ext().cred();
+    if !cred.capable(CAP_SYS_NICE) {
+        let tcred = task.task_ext().cred();
+        if cred.euid != tcred.uid && cred.euid != tcred.euid {
+            return Err(LinuxError::EPERM);
+        }
+    }
+
+    let mut cpumask = AxCpuMask::new();
+    for cpu in 0..axconfig::SMP.min(len * 8) {
+        if bytes[cpu / 8] & (1 << (cpu % 8)) != 0 {
+            cpumask.set(cpu, true);
+        }
+    }
+    if !axtask::set_task_affinity(&task, cpumask) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(0)
+}
+
+/// Gets the CPUs the thread `pid` may run on, and returns the size of the
+/// mask written, at most [`CPU_MASK_SIZE`].
+pub fn sys_sched_getaffinity(pid: i32, cpusetsize: usize, mask: UserPtr<u8>) -> LinuxResult<isize> {
+    if cpusetsize < axconfig::SMP.div_ceil(8) || cpusetsize % size_of::<usize>() != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let cpumask = sched_target(pid)?.cpumask();
+    let len = cpusetsize.min(CPU_MASK_SIZE);
+    let bytes = unsafe { slice::from_raw_parts_mut(mask.get_as_bytes(len)?, len) };
+    bytes.fill(0);
+    for cpu in (0..axconfig::SMP).filter(|&cpu| cpumask.get(cpu)) {
+        bytes[cpu / 8] |= 1 << (cpu % 8);
+    }
+    Ok(len as _)
+}
+
+/// The default time-sharing policy.
+const SCHED_OTHER: i32 = 0;
+/// The real-time first-in first-out policy.
+const SCHED_FIFO: i32 = 1;
+/// The real-time round-robin policy.
+const SCHED_RR: i32 = 2;
+
+/// The scheduling parameters, as `struct sched_param` of
+/// `sched_setscheduler`.
+#[repr(C)]
+pub struct SchedParam {
+    sched_priority: i32,
+}
+
+fn sched_policy(policy: i32) -> LinuxResult<SchedPolicy> {
+    match policy {
+        SCHED_OTHER => Ok(SchedPolicy::Normal),
+        SCHED_FIFO => Ok(SchedPolicy::Fifo),
+        SCHED_RR => Ok(SchedPolicy::RoundRobin),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Returns the thread `pid`, or the calling thread if `pid` is 0.
+fn sched_target(pid: i32) -> LinuxResult<AxTaskRef> {
+    match pid {
+        0 => Ok(current().as_task_ref().clone()),
+        pid if pid > 0 => find_thread(pid as usize).ok_or(LinuxError::ESRCH),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Sets the scheduling policy and priority of `task`.
+///
+/// Without `CAP_SYS_NICE`, only the threads of the same user can be changed,
+/// and a real-time priority can only be raised up to `RLIMIT_RTPRIO`.
+fn set_scheduler(task: &AxTaskRef, policy: SchedPolicy, priority: i32) -> LinuxResult<isize> {
+    let (min, max) = policy.priority_range();
+    if !(min as i32..=max as i32).contains(&priority) {
+        return Err(LinuxError::EINVAL);
+    }
+    let priority = priority as u8;
+    let cred = current().task_ext().cred();
+    if !cred.capable(CAP_SYS_NICE) {
+        let tcred = task.task_ext().cred();
+        if cred.euid != tcred.uid && cred.euid != tcred.euid {
+            return Err(LinuxError::EPERM);
+        }
+        if policy.is_realtime() {
+            let (old_policy, old_priority) = task.sched_params();
+            let limit = task.task_ext().process.rlimit(RLIMIT_RTPRIO).rlim_cur;
+            if policy != old_policy && limit == 0 {
+                return Err(LinuxError::EPERM);
+            }
+            if priority > old_priority && priority as u64 > limit {
+                return Err(LinuxError::EPERM);
+            }
+        }
+    }
+    axtask::set_sched_params(task, policy, priority);
+    Ok(0)
+}
+
+fn read_sched_param(param: UserConstPtr<SchedParam>) -> LinuxResult<i32> {
+    let param = param
+        .nullable(UserConstPtr::get)?
+        .ok_or(LinuxError::EINVAL)?;
+    Ok(unsafe { (*param).sched_priority })
+}
+
+pub fn sys_sched_setscheduler(
+    pid: i32,
+    policy: i32,
+    param: UserConstPtr<SchedParam>,
+) -> LinuxResult<isize> {
+    let priority = read_sched_param(param)?;
+    let policy = sched_policy(policy)?;
+    set_scheduler(&sched_target(pid)?, policy, priority)
+}
+
+pub fn sys_sched_getscheduler(pid: i32) -> LinuxResult<isize> {
+    let (policy, _) = sched_target(pid)?.sched_params();
+    Ok(policy as _)
+}
+
+/// Sets the priority of a thread, keeping its policy.
+pub fn sys_sched_setparam(pid: i32, param: UserConstPtr<SchedParam>) -> LinuxResult<isize> {
+    let priority = read_sched_param(param)?;
+    let task = sched_target(pid)?;
+    let (policy, _) = task.sched_params();
+    set_scheduler(&task, policy, priority)
+}
+
+pub fn sys_sched_getparam(pid: i32, param: UserPtr<SchedParam>) -> LinuxResult<isize> {
+    let (_, priority) = sched_target(pid)?.sched_params();
+    unsafe {
+        *param.get()? = SchedParam {
+            sched_priority: priority as _,
+        }
+    };
+    Ok(0)
+}
+
+pub fn sys_sched_get_priority_max(policy: i32) -> LinuxResult<isize> {
+    Ok(sched_policy(policy)?.priority_range().1 as _)
+}
+
+pub fn sys_sched_get_priority_min(policy: i32) -> LinuxResult<isize> {
+    Ok(sched_policy(policy)?.priority_range().0 as _)
+}
+
+/// Returns the time slice of a thread. It is 0 for `SCHED_FIFO`, which runs
+/// until it blocks or yields. A normal thread runs for at least
+/// a timer tick before it is preempted by another one.
+pub fn sys_sched_rr_get_interval(
+    pid: i32,
+    interval: UserPtr<api::ctypes::timespec>,
+) -> LinuxResult<isize> {
+    let ns = match sched_target(pid)?.sched_params().0 {
+        SchedPolicy::Normal => NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64,
+        SchedPolicy::Fifo => 0,
+        SchedPolicy::RoundRobin => axtask::rr_interval_nanos(),
+    };
+    unsafe { *interval.get()? = Duration::from_nanos(ns).into() };
+    Ok(0)
+}
+
+/// `who` is a thread ID.
+const PRIO_PROCESS: i32 = 0;
+/// `who` is a process group ID.
+const PRIO_PGRP: i32 = 1;
+/// `who` is a real user ID.
+const PRIO_USER: i32 = 2;
+
+/// Returns the threads selected by `which` and `who`, where 0 means the
+/// calling thread, its process group or its real user.
+fn prio_targets(which: i32, who: i32) -> LinuxResult<Vec<AxTaskRef>> {
+    let curr = current();
+    Ok(match which {
+        PRIO_PROCESS if who == 0 => vec![curr.as_task_ref().clone()],
+        PRIO_PROCESS => find_thread(who as usize).into_iter().collect(),
+        PRIO_PGRP => {
+            let pgid = match who {
+                0 => curr.task_ext().process.pgid(),
+                pgid => pgid as usize,
+            };
+            process_group(pgid)
+                .iter()
+                .flat_map(|process| process.threads())
+                .collect()
+        }
+        PRIO_USER => {
+            let uid = match who {
+                0 => curr.task_ext().cred().uid,
+                uid => uid as u32,
+            };
+            processes()
+                .iter()
+                .flat_map(|process| process.threads())
+                .filter(|task| task.task_ext().cred().uid == uid)
+                .collect()
+        }
+        _ => return Err(LinuxError::EINVAL),
+    })
+}
+
+/// Sets the nice value of threads. The value is per thread, even if `which`
+/// selects a process, and it is clamped to the valid range.
+///
+/// Without `CAP_SYS_NICE`, only the threads of the same user can be changed,
+/// and a nice value can only be lowered down to `20 - RLIMIT_NICE`.
+pub fn sys_setpriority(which: i32, who: i32, niceval: i32) -> LinuxResult<isize> {
+    let targets = prio_targets(which, who)?;
+    let nice = niceval.clamp(NICE_MIN as i32, NICE_MAX as i32) as i8;
+    let curr = current();
+    let cred = curr.task_ext().cred();
+    let capable = cred.capable(CAP_SYS_NICE);
+    let nice_limit = curr.task_ext().process.rlimit(RLIMIT_NICE).rlim_cur;
+
+    // An error on any thread is reported, but the others are still changed.
+    let mut result = Err(LinuxError::ESRCH);
+    for task in targets {
+        let tcred = task.task_ext().cred();
+        if !capable && cred.euid != tcred.uid && cred.euid != tcred.euid {
+            result = Err(LinuxError::EPERM);
+            continue;
+        }
+        if nice < task.nice() && !capable && (20 - nice as i64) as u64 > nice_limit {
+            result = Err(LinuxError::EACCES);
+            continue;
+        }
+        axtask::set_task_priority(&task, nice as isize);
+        if matches!(result, Err(LinuxError::ESRCH)) {
+            result = Ok(0);
+        }
+    }
+    result
+}
+
+/// Returns the highest priority, i.e. the lowest nice value, of the threads.
+/// It is returned as `20 - nice`, from 1 to 40, so that it cannot be taken
+/// for an error code, and the C library converts it back.
+pub fn sys_getpriority(which: i32, who: i32) -> LinuxResult<isize> {
+    prio_targets(which, who)?
+        .iter()
+        .map(|task| 20 - task.nice() as isize)
+        .max()
+        .ok_or(LinuxError::ESRCH)
+}
@@ -1,16 +1,24 @@
-use core::{ffi::c_char, ptr};
+use core::ffi::{c_char, c_int};
 
-use alloc::vec::Vec;
-use arceos_posix_api::ctypes::RLIMIT_NOFILE;
-use axerrno::{LinuxError, LinuxResult};
-use axtask::{TaskExtRef, current, yield_now};
+use alloc::{string::String, sync::Arc, vec::Vec};
+use arceos_posix_api::{
+    AT_FDCWD, File, HARDLINK_MANAGER, ctypes::RLIMIT_STACK, get_file_like, handle_file_path,
+};
+use axerrno::{AxError, LinuxError, LinuxResult};
+use axhal::time::nanos_to_ticks;
+use axtask::{TaskExtRef, current};
 use macro_rules_attribute::apply;
+use memory_addr::PAGE_SIZE_4K;
 use num_enum::TryFromPrimitive;
 use starry_core::{
-    ctypes::{WaitFlags, WaitStatus},
-    task::{exec, wait_pid},
+    ctypes::{CloneFlags, Rusage, WaitFlags, WaitIdType, WaitStatus},
+    process::{ProcessData, WaitTarget, find_process, process_group},
+    resource::total_usage,
+    signal::{SIGCHLD, SIGNAL_MAX, SigInfo},
+    task::{CloneArgs, do_exit, exec, exit_group},
 };
-use starry_core::task::Rlimit;
+
+use super::PidFd;
 use crate::{
     ptr::{PtrWrapper, UserConstPtr, UserPtr},
     syscall_instrument,
@@ -39,37 +47,118 @@ enum ArchPrctlCode {
 
 #[apply(syscall_instrument)]
 pub fn sys_getpid() -> LinuxResult<isize> {
-    Ok(axtask::current().task_ext().proc_id as _)
+    Ok(axtask::current().task_ext().process.pid as _)
 }
 
 #[apply(syscall_instrument)]
 pub fn sys_getppid() -> LinuxResult<isize> {
-    Ok(axtask::current().task_ext().get_parent() as _)
+    Ok(axtask::current().task_ext().process.ppid() as _)
 }
 
-// TODO: [dummy]
 #[apply(syscall_instrument)]
 pub fn sys_gettid() -> LinuxResult<isize> {
-    Ok(current().task_ext().proc_id as _)
+    Ok(current().task_ext().tid as _)
 }
 
-pub fn sys_exit(status: i32) -> ! {
-    let curr = current();
-    let clear_child_tid = curr.task_ext().clear_child_tid() as *mut i32;
-    if !clear_child_tid.is_null() {
-        // TODO: check whether the address is valid
-        unsafe {
-            // TODO: Encapsulate all operations that access user-mode memory into a unified function
-            *(clear_child_tid) = 0;
+#[apply(syscall_instrument)]
+pub fn sys_setpgid(pid: i32, pgid: i32) -> LinuxResult<isize> {
+    if pid < 0 || pgid < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let curr = current().task_ext().process.clone();
+    let target = if pid == 0 || pid as usize == curr.pid {
+        curr.clone()
+    } else {
+        let child = curr
+            .children
+            .lock()
+            .iter()
+            .find(|child| child.pid == pid as usize)
+            .cloned()
+            .ok_or(LinuxError::ESRCH)?;
+        if child.exec_done() {
+            return Err(LinuxError::EACCES);
         }
-        // TODO: wake up threads, which are blocked by futex, and waiting for the address pointed by clear_child_tid
+        child
+    };
+    if target.is_session_leader() || target.sid() != curr.sid() {
+        return Err(LinuxError::EPERM);
+    }
+
+    let pgid = if pgid == 0 { target.pid } else { pgid as usize };
+    // A process can only join an existing process group in its session.
+    if pgid != target.pid
+        && !process_group(pgid)
+            .iter()
+            .any(|process| process.sid() == curr.sid())
+    {
+        return Err(LinuxError::EPERM);
+    }
+    target.set_pgid(pgid);
+    Ok(0)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_getpgid(pid: i32) -> LinuxResult<isize> {
+    Ok(find_process_or_current(pid)?.pgid() as _)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_getpgrp() -> LinuxResult<isize> {
+    Ok(current().task_ext().process.pgid() as _)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_setsid() -> LinuxResult<isize> {
+    let curr = current().task_ext().process.clone();
+    // The new session would share its ID with an existing process group.
+    if !process_group(curr.pid).is_empty() {
+        return Err(LinuxError::EPERM);
+    }
+    curr.set_sid();
+    Ok(curr.pid as _)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_getsid(pid: i32) -> LinuxResult<isize> {
+    Ok(find_process_or_current(pid)?.sid() as _)
+}
+
+/// Options of sys_prctl
+const PR_SET_CHILD_SUBREAPER: i32 = 36;
+const PR_GET_CHILD_SUBREAPER: i32 = 37;
+
+#[apply(syscall_instrument)]
+pub fn sys_prctl(option: i32, arg2: usize) -> LinuxResult<isize> {
+    let process = &current().task_ext().process;
+    match option {
+        PR_SET_CHILD_SUBREAPER => process.set_child_subreaper(arg2 != 0),
+        PR_GET_CHILD_SUBREAPER => unsafe {
+            *UserPtr::<i32>::from(arg2).get()? = process.is_child_subreaper() as i32;
+        },
+        _ => {
+            warn!("sys_prctl: option {} is not supported", option);
+            return Err(LinuxError::EINVAL);
+        }
+    }
+    Ok(0)
+}
+
+/// Finds the process with the given PID, where 0 means the current process.
+fn find_process_or_current(pid: i32) -> LinuxResult<Arc<ProcessData>> {
+    match pid {
+        0 => Ok(current().task_ext().process.clone()),
+        pid if pid > 0 => find_process(pid as usize).ok_or(LinuxError::ESRCH),
+        _ => Err(LinuxError::ESRCH),
     }
-    axtask::exit(status);
+}
+
+pub fn sys_exit(status: i32) -> ! {
+    do_exit(status);
 }
 
 pub fn sys_exit_group(status: i32) -> ! {
-    warn!("Temporarily replace sys_exit_group with sys_exit");
-    axtask::exit(status);
+    exit_group(WaitStatus::Exited(status));
 }
 
 /// To set the clear_child_tid field in the task extended data.
@@ -80,7 +169,7 @@ pub fn sys_set_tid_address(tid_ptd: UserConstPtr<i32>) -> LinuxResult<isize> {
     let curr = current();
     curr.task_ext()
         .set_clear_child_tid(tid_ptd.address().as_ptr() as _);
-    Ok(curr.id().as_u64() as isize)
+    Ok(curr.task_ext().tid as isize)
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -120,6 +209,118 @@ pub fn sys_arch_prctl(code: i32, addr: UserPtr<u64>) -> LinuxResult<isize> {
     }
 }
 
+/// Bits of the `clone` flags holding the exit signal.
+const CSIGNAL: usize = 0xff;
+
+/// The size of the first published version of `struct clone_args`.
+const CLONE_ARGS_SIZE_VER0: usize = 64;
+
+/// The `struct clone_args` of `clone3`.
+///
+/// New fields are appended in later versions, and user space passes the size
+/// of the version it was built against.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct UserCloneArgs {
+    flags: u64,
+    pidfd: u64,
+    child_tid: u64,
+    parent_tid: u64,
+    exit_signal: u64,
+    stack: u64,
+    stack_size: u64,
+    tls: u64,
+    set_tid: u64,
+    set_tid_size: u64,
+    cgroup: u64,
+}
+
+impl UserCloneArgs {
+    /// Reads a `struct clone_args` of `size` bytes from user space.
+    ///
+    /// Fields unknown to user space are zero, and an extended structure is
+    /// only accepted if the fields unknown to the kernel are zero.
+    fn read(uargs: UserConstPtr<u8>, size: usize) -> LinuxResult<Self> {
+        Self::check_size(size)?;
+        let bytes = unsafe { core::slice::from_raw_parts(uargs.get_as_bytes(size)?, size) };
+        Self::from_bytes(bytes)
+    }
+
+    /// Checks the size of a `struct clone_args` passed by user space.
+    fn check_size(size: usize) -> LinuxResult {
+        if size < CLONE_ARGS_SIZE_VER0 {
+            return Err(LinuxError::EINVAL);
+        }
+        if size > PAGE_SIZE_4K {
+            return Err(LinuxError::E2BIG);
+        }
+        Ok(())
+    }
+
+    /// Decodes a `struct clone_args` of any version from `bytes`.
+    fn from_bytes(bytes: &[u8]) -> LinuxResult<Self> {
+        Self::check_size(bytes.len())?;
+        let known = bytes.len().min(size_of::<Self>());
+        if bytes[known..].iter().any(|&b| b != 0) {
+            return Err(LinuxError::E2BIG);
+        }
+        let mut args = Self::default();
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                &mut args as *mut Self as *mut u8,
+                known,
+            );
+        }
+        Ok(args)
+    }
+
+    /// Reads the TID requested by `set_tid`, if any.
+    ///
+    /// Only one PID namespace exists, so at most one TID can be given.
+    fn set_tid(&self) -> LinuxResult<Option<usize>> {
+        match (self.set_tid, self.set_tid_size) {
+            (0, 0) => Ok(None),
+            (set_tid, 1) if set_tid != 0 => {
+                let tid = unsafe { *UserConstPtr::<i32>::from(set_tid as usize).get()? };
+                if tid <= 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                Ok(Some(tid as usize))
+            }
+            _ => Err(LinuxError::EINVAL),
+        }
+    }
+}
+
+/// Creates a thread or process, the common path of `sys_clone` and
+/// `sys_clone3`.
+///
+/// If `CLONE_PIDFD` is set, a pidfd referring to the child is stored at
+/// `pidfd`.
+fn do_clone(args: CloneArgs, pidfd: usize) -> LinuxResult<isize> {
+    let pidfd = if args.flags.contains(CloneFlags::CLONE_PIDFD) {
+        // A pidfd refers to a process, not a thread.
+        if args.flags.contains(CloneFlags::CLONE_THREAD) {
+            return Err(LinuxError::EINVAL);
+        }
+        Some(UserPtr::<c_int>::from(pidfd).get()?)
+    } else {
+        None
+    };
+
+    let curr_task = current();
+    let new_task = curr_task.task_ext().clone_task(&args)?;
+    let new_task_ext = new_task.task_ext();
+    if let Some(pidfd) = pidfd {
+        let fd = PidFd::add_to_fd_table(new_task_ext.process.clone())?;
+        unsafe { *pidfd = fd };
+    }
+    Ok(new_task_ext.tid as isize)
+}
+
+/// The arguments after `ptid` are `ctid, tls` on x86_64, and `tls, ctid` on
+/// the other architectures (`CONFIG_CLONE_BACKWARDS` in Linux).
 #[apply(syscall_instrument)]
 pub fn sys_clone(
     flags: usize,
@@ -128,25 +329,78 @@ pub fn sys_clone(
     arg3: usize,
     arg4: usize,
 ) -> LinuxResult<isize> {
-    let tls = arg3;
-    let ctid = arg4;
+    #[cfg(target_arch = "x86_64")]
+    let (ctid, tls) = (arg3, arg4);
+    #[cfg(not(target_arch = "x86_64"))]
+    let (tls, ctid) = (arg3, arg4);
+
+    let clone_flags = CloneFlags::from_bits_truncate((flags & !CSIGNAL) as u32);
+    // The pidfd is stored at `ptid`, so it cannot be used for the TID too.
+    if clone_flags.contains(CloneFlags::CLONE_PIDFD)
+        && clone_flags.intersects(CloneFlags::CLONE_DETACHED | CloneFlags::CLONE_PARENT_SETTID)
+    {
+        return Err(LinuxError::EINVAL);
+    }
 
     let stack = if user_stack == 0 {
         None
     } else {
         Some(user_stack)
     };
+    let args = CloneArgs {
+        flags: clone_flags,
+        exit_signal: (flags & CSIGNAL) as u32,
+        stack,
+        ptid,
+        tls,
+        ctid,
+        set_tid: None,
+    };
+    do_clone(args, ptid)
+}
 
-    let curr_task = current();
+#[apply(syscall_instrument)]
+pub fn sys_clone3(uargs: UserConstPtr<u8>, size: usize) -> LinuxResult<isize> {
+    let uargs = UserCloneArgs::read(uargs, size)?;
 
-    if let Ok(new_task_id) = curr_task
-        .task_ext()
-        .clone_task(flags, stack, ptid, tls, ctid)
+    // Flags above the legacy 32 bits (`CLONE_CLEAR_SIGHAND` and
+    // `CLONE_INTO_CGROUP`) are not supported, and the exit signal has its own
+    // field.
+    let flags = u32::try_from(uargs.flags).map_err(|_| LinuxError::EINVAL)? as usize;
+    if flags & CSIGNAL != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let clone_flags = CloneFlags::from_bits_truncate(flags as u32);
+    if clone_flags.contains(CloneFlags::CLONE_DETACHED) {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let exit_signal = uargs.exit_signal;
+    if exit_signal >= SIGNAL_MAX as u64
+        || (exit_signal != 0
+            && clone_flags.intersects(CloneFlags::CLONE_THREAD | CloneFlags::CLONE_PARENT))
     {
-        Ok(new_task_id as isize)
-    } else {
-        Err(LinuxError::ENOMEM)
+        return Err(LinuxError::EINVAL);
     }
+
+    // Unlike `clone`, the stack is given as the lowest address and the size
+    // of the stack area.
+    let stack = match (uargs.stack as usize, uargs.stack_size as usize) {
+        (0, 0) => None,
+        (0, _) | (_, 0) => return Err(LinuxError::EINVAL),
+        (stack, stack_size) => Some(stack.checked_add(stack_size).ok_or(LinuxError::EINVAL)?),
+    };
+
+    let args = CloneArgs {
+        flags: clone_flags,
+        exit_signal: exit_signal as u32,
+        stack,
+        ptid: uargs.parent_tid as usize,
+        tls: uargs.tls as usize,
+        ctid: uargs.child_tid as usize,
+        set_tid: uargs.set_tid()?,
+    };
+    do_clone(args, uargs.pidfd as usize)
 }
 
 // TODO: [incomplete]
@@ -156,65 +410,178 @@ pub fn sys_fork() -> LinuxResult<isize> {
     sys_clone(17, 0, 0, 0, 0)
 }
 
-// TODO: [stub] The method signature is not correct yet
+#[cfg(target_arch = "x86_64")]
 #[apply(syscall_instrument)]
-pub fn sys_prlimit64(
+pub fn sys_vfork() -> LinuxResult<isize> {
+    let flags = CloneFlags::CLONE_VM | CloneFlags::CLONE_VFORK;
+    sys_clone(flags.bits() as usize | SIGCHLD as usize, 0, 0, 0, 0)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_wait4(
     pid: i32,
-    resource: u32,
-    new_limit: UserConstPtr<Rlimit>,
-    old_limit: UserPtr<Rlimit>,
+    exit_code_ptr: UserPtr<i32>,
+    option: u32,
+    rusage: UserPtr<Rusage>,
 ) -> LinuxResult<isize> {
-    if pid != 0 {
-        return Err(LinuxError::ESRCH);
-    }
-    if resource != RLIMIT_NOFILE {
+    let option_flag = WaitFlags::from_bits(option).ok_or(LinuxError::EINVAL)?;
+    if option_flag.intersects(WaitFlags::WEXITED | WaitFlags::WNOWAIT) {
         return Err(LinuxError::EINVAL);
     }
-    let curr = current();
-    let task = curr.task_ext();
-    let old_num:Rlimit = task.get_rlimit_nofile();
-    let new_limit = new_limit.nullable(UserConstPtr::get)?;
-    if let Some(new_limit) = new_limit {
-        unsafe { task.set_rlimit_nofile(*new_limit); }
+    let target = match pid {
+        pid if pid > 0 => WaitTarget::Pid(pid as usize),
+        -1 => WaitTarget::Any,
+        0 => WaitTarget::Pgid(current().task_ext().process.pgid()),
+        pid => WaitTarget::Pgid(-(pid as isize) as usize),
+    };
+
+    let exit_code_ptr = exit_code_ptr.nullable(UserPtr::get)?;
+    let rusage = rusage.nullable(UserPtr::get)?;
+    let process = current().task_ext().process.clone();
+    let Some((child, status)) = process
+        .wait_child(target, option_flag | WaitFlags::WEXITED)
+        .map_err(wait_error)?
+    else {
+        return Ok(0);
+    };
+    info!("wait pid _{}_ with status _{:?}_", child.pid, status);
+    if let Some(exit_code_ptr) = exit_code_ptr {
+        unsafe { *exit_code_ptr = status.to_wstatus() };
+    }
+    if let Some(rusage) = rusage {
+        unsafe { *rusage = total_usage(&child).into() };
     }
-    
-    let old_limit = old_limit.nullable(UserPtr::get)?;
-    if let Some(old_limit) = old_limit {
+    Ok(child.pid as isize)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_waitid(
+    idtype: u32,
+    id: i32,
+    infop: UserPtr<SigInfo>,
+    options: u32,
+    rusage: UserPtr<Rusage>,
+) -> LinuxResult<isize> {
+    let option_flag = WaitFlags::from_bits(options).ok_or(LinuxError::EINVAL)?;
+    if !option_flag.intersects(WaitFlags::WEXITED | WaitFlags::WUNTRACED | WaitFlags::WCONTINUED) {
+        return Err(LinuxError::EINVAL);
+    }
+    let target = match WaitIdType::try_from(idtype).map_err(|_| LinuxError::EINVAL)? {
+        WaitIdType::P_ALL => WaitTarget::Any,
+        WaitIdType::P_PID if id > 0 => WaitTarget::Pid(id as usize),
+        WaitIdType::P_PID => return Err(LinuxError::EINVAL),
+        WaitIdType::P_PGID if id > 0 => WaitTarget::Pgid(id as usize),
+        WaitIdType::P_PGID if id == 0 => WaitTarget::Pgid(current().task_ext().process.pgid()),
+        WaitIdType::P_PGID => return Err(LinuxError::EINVAL),
+    };
+
+    let infop = infop.nullable(UserPtr::get)?;
+    let rusage = rusage.nullable(UserPtr::get)?;
+    let process = current().task_ext().process.clone();
+    let result = process
+        .wait_child(target, option_flag)
+        .map_err(wait_error)?;
+    if let Some(infop) = infop {
         unsafe {
-            *old_limit = old_num;
+            *infop = match &result {
+                Some((child, status)) => {
+                    let usage = total_usage(child);
+                    SigInfo::child(
+                        SIGCHLD,
+                        child.pid,
+                        child.uid(),
+                        *status,
+                        nanos_to_ticks(usage.utime_ns as u64),
+                        nanos_to_ticks(usage.stime_ns as u64),
+                    )
+                }
+                // With WNOHANG, `si_pid` is zero if no child has changed state.
+                None => SigInfo::default(),
+            };
         }
     }
-
+    if let (Some((child, _)), Some(rusage)) = (&result, rusage) {
+        unsafe { *rusage = total_usage(child).into() };
+    }
     Ok(0)
 }
 
-#[apply(syscall_instrument)]
-pub fn sys_wait4(pid: i32, exit_code_ptr: UserPtr<i32>, option: u32) -> LinuxResult<isize> {
-    let option_flag = WaitFlags::from_bits(option).unwrap();
-    let exit_code_ptr = exit_code_ptr.nullable(UserPtr::get)?;
-    loop {
-        let answer = unsafe { wait_pid(pid, exit_code_ptr.unwrap_or_else(ptr::null_mut)) };
-        match answer {
-            Ok(pid) => {
-                return Ok(pid as isize);
-            }
-            Err(status) => match status {
-                WaitStatus::NotExist => {
-                    return Err(LinuxError::ECHILD);
-                }
-                WaitStatus::Running => {
-                    if option_flag.contains(WaitFlags::WNOHANG) {
-                        return Ok(0);
-                    } else {
-                        yield_now();
-                    }
-                }
-                _ => {
-                    panic!("Shouldn't reach here!");
-                }
-            },
+fn wait_error(err: AxError) -> LinuxError {
+    match err {
+        AxError::NotFound => LinuxError::ECHILD,
+        err => err.into(),
+    }
+}
+
+/// Flags of `execveat`
+const AT_SYMLINK_NOFOLLOW: u32 = 0x100;
+const AT_EMPTY_PATH: u32 = 0x1000;
+
+/// The maximum size of a single argument or environment string, including
+/// the terminating NUL. Longer strings fail `execve` with `E2BIG`.
+const MAX_ARG_STRLEN: usize = 32 * PAGE_SIZE_4K;
+
+/// Returns the maximum total size of the arguments and environment strings
+/// and their pointers. It is a quarter of the `RLIMIT_STACK` soft limit of the
+/// current process, which leaves the rest of the stack to the program, but at
+/// least 32 pages.
+fn arg_max() -> usize {
+    let stack_limit = current().task_ext().process.rlimit(RLIMIT_STACK).rlim_cur;
+    usize::try_from(stack_limit / 4)
+        .unwrap_or(usize::MAX)
+        .max(32 * PAGE_SIZE_4K)
+}
+
+/// Resolves the program to execute for `execveat`.
+fn exec_path(dirfd: c_int, path: UserConstPtr<c_char>, flags: u32) -> LinuxResult<String> {
+    if flags & !(AT_EMPTY_PATH | AT_SYMLINK_NOFOLLOW) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let path = path.get_as_str()?;
+    if path.is_empty() {
+        if flags & AT_EMPTY_PATH == 0 {
+            return Err(LinuxError::ENOENT);
         }
+        // Executes the file referred to by `dirfd`, as `fexecve` does.
+        let file = get_file_like(dirfd)?
+            .into_any()
+            .downcast::<File>()
+            .map_err(|_| LinuxError::EACCES)?;
+        return Ok(file.path().into());
+    }
+
+    let path = handle_file_path(dirfd as isize, Some(path.as_ptr()), false)?;
+    // `axfs::api::metadata` resolves links, and the file systems do not
+    // return link nodes, so the final component is looked up in the link
+    // table without being followed.
+    if flags & AT_SYMLINK_NOFOLLOW != 0 && HARDLINK_MANAGER.is_link(path.as_str()) {
+        return Err(LinuxError::ELOOP);
     }
+    Ok(path.as_str().into())
+}
+
+/// Reads the NULL-terminated string array `argv` or `envp` of `execve`. A
+/// NULL array is treated as empty.
+///
+/// `space` is the space left for the strings and their pointers, and fails
+/// with `E2BIG` when exhausted.
+fn exec_strings(array: UserConstPtr<usize>, space: &mut usize) -> LinuxResult<Vec<String>> {
+    let Some(ptrs) = array.nullable(UserConstPtr::get_as_null_terminated)? else {
+        return Ok(Vec::new());
+    };
+    ptrs.iter()
+        .map(|&ptr| {
+            let s = UserConstPtr::<c_char>::from(ptr).get_as_str()?;
+            let size = s.len() + 1;
+            if size > MAX_ARG_STRLEN {
+                return Err(LinuxError::E2BIG);
+            }
+            *space = space
+                .checked_sub(size + size_of::<usize>())
+                .ok_or(LinuxError::E2BIG)?;
+            Ok(s.into())
+        })
+        .collect()
 }
 
 #[apply(syscall_instrument)]
@@ -223,36 +590,32 @@ pub fn sys_execve(
     argv: UserConstPtr<usize>,
     envp: UserConstPtr<usize>,
 ) -> LinuxResult<isize> {
-    let path_str = path.get_as_str()?;
-
-    let args = argv
-        .get_as_null_terminated()?
-        .iter()
-        .map(|arg| {
-            UserConstPtr::<c_char>::from(*arg)
-                .get_as_str()
-                .map(Into::into)
-        })
-        .collect::<Result<Vec<_>, _>>()?;
-    let envs = envp
-        .get_as_null_terminated()?
-        .iter()
-        .map(|env| {
-            UserConstPtr::<c_char>::from(*env)
-                .get_as_str()
-                .map(Into::into)
-        })
-        .collect::<Result<Vec<_>, _>>()?;
+    sys_execveat(AT_FDCWD as _, path, argv, envp, 0)
+}
+
+#[apply(syscall_instrument)]
+pub fn sys_execveat(
+    dirfd: c_int,
+    path: UserConstPtr<c_char>,
+    argv: UserConstPtr<usize>,
+    envp: UserConstPtr<usize>,
+    flags: u32,
+) -> LinuxResult<isize> {
+    let path = exec_path(dirfd, path, flags)?;
+    let mut space = arg_max();
+    let mut args = exec_strings(argv, &mut space)?;
+    let envs = exec_strings(envp, &mut space)?;
+    if args.is_empty() {
+        // An empty argument list gets an empty `argv[0]`, as programs
+        // commonly assume that it exists.
+        args.push(String::new());
+    }
 
     info!(
         "execve: path: {:?}, args: {:?}, envs: {:?}",
-        path_str, args, envs
+        path, args, envs
     );
 
-    if let Err(e) = exec(path_str, &args, &envs) {
-        error!("Failed to exec: {:?}", e);
-        return Err::<isize, _>(LinuxError::ENOSYS);
-    }
-
+    exec(&path, &args, &envs)?;
     unreachable!("execve should never return");
 }
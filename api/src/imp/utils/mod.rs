@@ -1,3 +1,5 @@
 mod time;
+mod timer;
 
 pub use self::time::*;
+pub use self::timer::*;
@@ -1,26 +1,153 @@
-use arceos_posix_api::{self as api, ctypes::timeval};
-use axerrno::LinuxResult;
-use axhal::time::{monotonic_time_nanos, nanos_to_ticks};
-use starry_core::{ctypes::Tms, task::time_stat_output};
+use core::time::Duration;
 
-use crate::ptr::{PtrWrapper, UserPtr};
+use arceos_posix_api::ctypes::{timespec, timeval};
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::{NANOS_PER_MICROS, monotonic_time_nanos, nanos_to_ticks};
+use axtask::{TaskExtRef, current};
+use starry_core::{
+    cred::CAP_SYS_TIME,
+    ctypes::Tms,
+    resource::process_usage,
+    time::{
+        CLOCK_BOOTTIME, CLOCK_MONOTONIC, CLOCK_REALTIME, Clock, TIMER_ABSTIME,
+        clock_resolution_nanos, realtime_nanos, set_realtime_nanos,
+    },
+};
 
-pub fn sys_clock_gettime(clock_id: i32, tp: UserPtr<api::ctypes::timespec>) -> LinuxResult<isize> {
-    unsafe { Ok(api::sys_clock_gettime(clock_id, tp.get()?) as _) }
+use super::timer::{timespec_to_nanos, timeval_to_nanos};
+use crate::ptr::{PtrWrapper, UserConstPtr, UserPtr};
+
+/// The time zone of `gettimeofday`, as `struct timezone`.
+#[repr(C)]
+pub struct TimeZone {
+    tz_minuteswest: i32,
+    tz_dsttime: i32,
+}
+
+fn clock_from_id(clock_id: i32) -> LinuxResult<Clock> {
+    Clock::from_id(clock_id).ok_or(LinuxError::EINVAL)
+}
+
+pub fn sys_clock_gettime(clock_id: i32, tp: UserPtr<timespec>) -> LinuxResult<isize> {
+    let now = clock_from_id(clock_id)?.now_nanos();
+    unsafe { *tp.get()? = Duration::from_nanos(now).into() };
+    Ok(0)
+}
+
+pub fn sys_clock_getres(clock_id: i32, res: UserPtr<timespec>) -> LinuxResult<isize> {
+    clock_from_id(clock_id)?;
+    if let Some(res) = res.nullable(UserPtr::get)? {
+        unsafe { *res = Duration::from_nanos(clock_resolution_nanos()).into() };
+    }
+    Ok(0)
+}
+
+/// Sets the real-time clock, which needs `CAP_SYS_TIME`. The other clocks
+/// cannot be set.
+pub fn sys_clock_settime(clock_id: i32, tp: UserConstPtr<timespec>) -> LinuxResult<isize> {
+    if clock_from_id(clock_id)? != Clock::Realtime {
+        return Err(LinuxError::EINVAL);
+    }
+    let ns = timespec_to_nanos(unsafe { *tp.get()? })?;
+    if !current().task_ext().cred().capable(CAP_SYS_TIME) {
+        return Err(LinuxError::EPERM);
+    }
+    set_realtime_nanos(ns);
+    Ok(0)
+}
+
+/// Sleeps for the time in `req`, or until the clock reaches it with
+/// `TIMER_ABSTIME`.
+///
+/// The coarse clocks and the raw monotonic clock cannot be slept on, nor can
+/// the CPU-time clocks of processes here, and the CPU-time clocks of threads
+/// are invalid for sleeping. An absolute deadline on the real-time clock is converted to a relative one
+/// when the sleep starts, so it is not affected by later `clock_settime`.
+pub fn sys_clock_nanosleep(
+    clock_id: i32,
+    flags: u32,
+    req: UserConstPtr<timespec>,
+    rem: UserPtr<timespec>,
+) -> LinuxResult<isize> {
+    let clock = match clock_id as u32 {
+        CLOCK_REALTIME => Clock::Realtime,
+        CLOCK_MONOTONIC | CLOCK_BOOTTIME => Clock::Monotonic,
+        _ => match clock_from_id(clock_id)? {
+            Clock::ThreadCpu(..) => return Err(LinuxError::EINVAL),
+            _ => return Err(LinuxError::EOPNOTSUPP),
+        },
+    };
+    let absolute = flags & TIMER_ABSTIME != 0;
+    let req = timespec_to_nanos(unsafe { *req.get()? })?;
+    let dur = if absolute {
+        req.saturating_sub(clock.now_nanos())
+    } else {
+        req
+    };
+
+    let deadline = monotonic_time_nanos() + dur;
+    axtask::sleep(Duration::from_nanos(dur));
+    let remaining = deadline.saturating_sub(monotonic_time_nanos());
+    if remaining > 0 {
+        // The remaining time is only reported for relative sleeps, as an
+        // absolute sleep is simply restarted with the same deadline.
+        if !absolute {
+            if let Some(rem) = rem.nullable(UserPtr::get)? {
+                unsafe { *rem = Duration::from_nanos(remaining).into() };
+            }
+        }
+        return Err(LinuxError::EINTR);
+    }
+    Ok(0)
 }
 
-pub fn sys_get_time_of_day(ts: UserPtr<timeval>) -> LinuxResult<isize> {
-    unsafe { Ok(api::sys_get_time_of_day(ts.get()?) as _) }
+pub fn sys_get_time_of_day(tv: UserPtr<timeval>, tz: UserPtr<TimeZone>) -> LinuxResult<isize> {
+    if let Some(tv) = tv.nullable(UserPtr::get)? {
+        unsafe { *tv = Duration::from_nanos(realtime_nanos()).into() };
+    }
+    // The kernel keeps no time zone, which is up to the C library, so UTC
+    // is reported.
+    if let Some(tz) = tz.nullable(UserPtr::get)? {
+        unsafe {
+            *tz = TimeZone {
+                tz_minuteswest: 0,
+                tz_dsttime: 0,
+            }
+        };
+    }
+    Ok(0)
+}
+
+/// Sets the real-time clock, which needs `CAP_SYS_TIME`. The time zone is
+/// ignored.
+pub fn sys_settimeofday(
+    tv: UserConstPtr<timeval>,
+    _tz: UserConstPtr<TimeZone>,
+) -> LinuxResult<isize> {
+    let Some(tv) = tv.nullable(UserConstPtr::get)? else {
+        return Ok(0);
+    };
+    let ns = timeval_to_nanos(unsafe { *tv })?;
+    if !current().task_ext().cred().capable(CAP_SYS_TIME) {
+        return Err(LinuxError::EPERM);
+    }
+    set_realtime_nanos(ns);
+    Ok(0)
 }
 
+/// Reports the CPU times of all the threads of the current process, and
+/// those of its reaped children.
 pub fn sys_times(tms: UserPtr<Tms>) -> LinuxResult<isize> {
-    let (_, utime_us, _, stime_us) = time_stat_output();
+    let process = &current().task_ext().process;
+    let usage = process_usage(process);
+    let children = process.children_usage();
+    let micros = |ns: usize| ns / NANOS_PER_MICROS as usize;
     unsafe {
         *tms.get()? = Tms {
-            tms_utime: utime_us,
-            tms_stime: stime_us,
-            tms_cutime: utime_us,
-            tms_cstime: stime_us,
+            tms_utime: micros(usage.utime_ns),
+            tms_stime: micros(usage.stime_ns),
+            tms_cutime: micros(children.utime_ns),
+            tms_cstime: micros(children.stime_ns),
         }
     }
     Ok(nanos_to_ticks(monotonic_time_nanos()) as _)
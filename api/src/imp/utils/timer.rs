@@ -0,0 +1,285 @@
+use core::time::Duration;
+
+use arceos_posix_api::ctypes::{timespec, timeval};
+use axerrno::{AxError, LinuxError, LinuxResult};
+use axhal::time::{NANOS_PER_MICROS, NANOS_PER_SEC};
+use axtask::{TaskExtRef, current};
+use starry_core::{
+    ctypes::TimerType,
+    process::{ProcessData, find_thread},
+    signal::{SIGALRM, is_valid_signal},
+    time::{
+        CLOCK_BOOTTIME, CLOCK_MONOTONIC, CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME,
+        CLOCK_THREAD_CPUTIME_ID, Clock, CpuTime, TIMER_ABSTIME,
+    },
+    timer::{ITimerValue, TimerClock, TimerEvent, set_itimer, set_posix_timer},
+};
+
+use crate::ptr::{PtrWrapper, UserConstPtr, UserPtr};
+
+/// The value of an interval timer, as `struct itimerval` of `setitimer`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ITimerVal {
+    it_interval: timeval,
+    it_value: timeval,
+}
+
+pub(super) fn timeval_to_nanos(tv: timeval) -> LinuxResult<u64> {
+    if tv.tv_sec < 0 || !(0..1_000_000).contains(&tv.tv_usec) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(Duration::from(tv).as_nanos() as u64)
+}
+
+/// Converts a time to a `timeval`, rounding up so that an armed timer is
+/// never reported as disarmed.
+fn nanos_to_timeval(ns: u64) -> timeval {
+    Duration::from_nanos(ns.div_ceil(NANOS_PER_MICROS) * NANOS_PER_MICROS).into()
+}
+
+impl From<ITimerValue> for ITimerVal {
+    fn from(value: ITimerValue) -> Self {
+        Self {
+            it_interval: nanos_to_timeval(value.interval_ns),
+            it_value: nanos_to_timeval(value.value_ns),
+        }
+    }
+}
+
+fn timer_type(which: i32) -> LinuxResult<TimerType> {
+    match TimerType::try_from(which) {
+        Ok(TimerType::NONE) | Err(_) => Err(LinuxError::EINVAL),
+        Ok(which) => Ok(which),
+    }
+}
+
+pub fn sys_getitimer(which: i32, value: UserPtr<ITimerVal>) -> LinuxResult<isize> {
+    let which = timer_type(which)?;
+    let value = value.get()?;
+    let itimer = current().task_ext().process.itimers().get(which);
+    unsafe { *value = itimer.into() };
+    Ok(0)
+}
+
+/// Sets an interval timer. A null `new_value` is taken as a zero value,
+/// which disarms the timer.
+pub fn sys_setitimer(
+    which: i32,
+    new_value: UserConstPtr<ITimerVal>,
+    old_value: UserPtr<ITimerVal>,
+) -> LinuxResult<isize> {
+    let which = timer_type(which)?;
+    let new = match new_value.nullable(UserConstPtr::get)? {
+        Some(new_value) => {
+            let new_value = unsafe { *new_value };
+            ITimerValue {
+                interval_ns: timeval_to_nanos(new_value.it_interval)?,
+                value_ns: timeval_to_nanos(new_value.it_value)?,
+            }
+        }
+        None => ITimerValue::default(),
+    };
+    let old_value = old_value.nullable(UserPtr::get)?;
+    let old = set_itimer(&current().task_ext().process, which, new);
+    if let Some(old_value) = old_value {
+        unsafe { *old_value = old.into() };
+    }
+    Ok(0)
+}
+
+/// Sets `ITIMER_REAL` to expire once after `seconds`, or disarms it if
+/// `seconds` is 0. Returns the seconds left of the previous setting,
+/// rounded to the nearest second but at least 1 if it was armed.
+pub fn sys_alarm(seconds: u32) -> LinuxResult<isize> {
+    let new = ITimerValue {
+        interval_ns: 0,
+        value_ns: seconds as u64 * NANOS_PER_SEC,
+    };
+    let old = set_itimer(&current().task_ext().process, TimerType::REAL, new);
+    let secs = match old.value_ns {
+        0 => 0,
+        ns => ((ns + NANOS_PER_SEC / 2) / NANOS_PER_SEC).max(1),
+    };
+    Ok(secs as _)
+}
+
+/// Notifies by sending a signal.
+const SIGEV_SIGNAL: i32 = 0;
+/// Does not notify.
+const SIGEV_NONE: i32 = 1;
+/// Notifies by sending a signal to a thread, which is used by libc to
+/// implement `SIGEV_THREAD`.
+const SIGEV_THREAD_ID: i32 = 4;
+
+/// How a timer notifies its expiry, as `struct sigevent` of `timer_create`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SigEvent {
+    sigev_value: u64,
+    sigev_signo: i32,
+    sigev_notify: i32,
+    sigev_notify_thread_id: i32,
+    _pad: [i32; 11],
+}
+
+/// The value of a POSIX timer, as `struct itimerspec` of `timer_settime`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ITimerSpec {
+    it_interval: timespec,
+    it_value: timespec,
+}
+
+pub(super) fn timespec_to_nanos(ts: timespec) -> LinuxResult<u64> {
+    if ts.tv_sec < 0 || !(0..NANOS_PER_SEC as _).contains(&ts.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(Duration::from(ts).as_nanos() as u64)
+}
+
+impl From<ITimerValue> for ITimerSpec {
+    fn from(value: ITimerValue) -> Self {
+        Self {
+            it_interval: Duration::from_nanos(value.interval_ns).into(),
+            it_value: Duration::from_nanos(value.value_ns).into(),
+        }
+    }
+}
+
+/// Converts a timer ID not found to `EINVAL`, the error of an invalid
+/// `timerid` in the timer calls.
+fn timer_error(err: AxError) -> LinuxError {
+    match err {
+        AxError::NotFound => LinuxError::EINVAL,
+        err => err.into(),
+    }
+}
+
+/// Returns the signal sent by a timer notifying as `sevp`, or `None` if it
+/// does not notify.
+fn timer_event(sevp: &SigEvent, process: &ProcessData) -> LinuxResult<Option<TimerEvent>> {
+    let thread = match sevp.sigev_notify {
+        SIGEV_NONE => return Ok(None),
+        SIGEV_SIGNAL => None,
+        SIGEV_THREAD_ID => {
+            let tid = sevp.sigev_notify_thread_id;
+            let in_process = tid > 0
+                && find_thread(tid as usize)
+                    .is_some_and(|thread| thread.task_ext().process.pid == process.pid);
+            if !in_process {
+                return Err(LinuxError::EINVAL);
+            }
+            Some(tid as usize)
+        }
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let signo = sevp.sigev_signo as u32;
+    if !is_valid_signal(signo) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(Some(TimerEvent {
+        signo,
+        value: Some(sevp.sigev_value),
+        thread,
+    }))
+}
+
+/// Creates a POSIX timer. Without `sevp`, it sends `SIGALRM` to the process
+/// with the timer ID as `si_value`.
+///
+/// With `SIGEV_THREAD_ID`, the target thread must be in the current process,
+/// and the signal is queued to that thread.
+///
+/// The CPU-time clock of any thread of the current process can be used, as
+/// returned by `pthread_getcpuclockid`, but timers on the CPU-time clock of
+/// another process are not supported.
+pub fn sys_timer_create(
+    clock_id: i32,
+    sevp: UserConstPtr<SigEvent>,
+    timer_id: UserPtr<i32>,
+) -> LinuxResult<isize> {
+    let curr = current();
+    let process = &curr.task_ext().process;
+    let clock = match clock_id as u32 {
+        CLOCK_REALTIME => TimerClock::Realtime,
+        CLOCK_MONOTONIC | CLOCK_BOOTTIME => TimerClock::Monotonic,
+        CLOCK_PROCESS_CPUTIME_ID => TimerClock::ProcessCpu(CpuTime::Total),
+        CLOCK_THREAD_CPUTIME_ID => TimerClock::ThreadCpu(curr.task_ext().tid, CpuTime::Total),
+        _ if clock_id < 0 => match Clock::from_id(clock_id).ok_or(LinuxError::EINVAL)? {
+            Clock::ProcessCpu(pid, cpu_time) if pid == process.pid => {
+                TimerClock::ProcessCpu(cpu_time)
+            }
+            Clock::ThreadCpu(tid, cpu_time) => TimerClock::ThreadCpu(tid, cpu_time),
+            _ => return Err(LinuxError::EOPNOTSUPP),
+        },
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let event = match sevp.nullable(UserConstPtr::get)? {
+        Some(sevp) => timer_event(unsafe { &*sevp }, process)?,
+        None => Some(TimerEvent {
+            signo: SIGALRM,
+            value: None,
+            thread: None,
+        }),
+    };
+    let timer_id = timer_id.get()?;
+    let id = process.posix_timers().create(clock, event)?;
+    unsafe { *timer_id = id };
+    Ok(0)
+}
+
+pub fn sys_timer_settime(
+    timer_id: i32,
+    flags: u32,
+    new_value: UserConstPtr<ITimerSpec>,
+    old_value: UserPtr<ITimerSpec>,
+) -> LinuxResult<isize> {
+    let new_value = unsafe { *new_value.get()? };
+    let new = ITimerValue {
+        interval_ns: timespec_to_nanos(new_value.it_interval)?,
+        value_ns: timespec_to_nanos(new_value.it_value)?,
+    };
+    let old_value = old_value.nullable(UserPtr::get)?;
+    let process = &current().task_ext().process;
+    let old =
+        set_posix_timer(process, timer_id, new, flags & TIMER_ABSTIME != 0).map_err(timer_error)?;
+    if let Some(old_value) = old_value {
+        unsafe { *old_value = old.into() };
+    }
+    Ok(0)
+}
+
+pub fn sys_timer_gettime(timer_id: i32, curr_value: UserPtr<ITimerSpec>) -> LinuxResult<isize> {
+    let curr_value = curr_value.get()?;
+    let value = current()
+        .task_ext()
+        .process
+        .posix_timers()
+        .get(timer_id)
+        .map_err(timer_error)?;
+    unsafe { *curr_value = value.into() };
+    Ok(0)
+}
+
+/// Returns the overrun count of the last signal sent by the timer, capped
+/// at `DELAYTIMER_MAX` so that it fits in the return value.
+pub fn sys_timer_getoverrun(timer_id: i32) -> LinuxResult<isize> {
+    let overrun = current()
+        .task_ext()
+        .process
+        .posix_timers()
+        .overrun(timer_id)
+        .map_err(timer_error)?;
+    Ok(overrun.min(i32::MAX as u64) as _)
+}
+
+pub fn sys_timer_delete(timer_id: i32) -> LinuxResult<isize> {
+    current()
+        .task_ext()
+        .process
+        .posix_timers()
+        .delete(timer_id)
+        .map_err(timer_error)?;
+    Ok(0)
+}
@@ -0,0 +1,84 @@
+#include <errno.h>
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#ifndef SYS_clone3
+#define SYS_clone3 435
+#endif
+
+// The size of the first published version of `struct clone_args`.
+#define CLONE_ARGS_SIZE_VER0 64
+
+// `struct clone_args` up to `cgroup`, followed by room for the fields of
+// future versions.
+static union {
+    struct {
+        uint64_t flags;
+        uint64_t pidfd;
+        uint64_t child_tid;
+        uint64_t parent_tid;
+        uint64_t exit_signal;
+        uint64_t stack;
+        uint64_t stack_size;
+        uint64_t tls;
+        uint64_t set_tid;
+        uint64_t set_tid_size;
+        uint64_t cgroup;
+    } args;
+    unsigned char bytes[4096 + 8];
+} u;
+
+// Calls clone3 with the first `size` bytes of `u`, and reaps the child.
+// Returns the PID of the child, or the negated error number.
+static long spawn(size_t size)
+{
+    long pid = syscall(SYS_clone3, &u, size);
+    if (pid == 0)
+        _exit(0);
+    if (pid < 0)
+        return -errno;
+    waitpid(pid, NULL, 0);
+    return pid;
+}
+
+static int check(const char *what, long ret, long expected)
+{
+    if (ret == expected)
+        return 0;
+    printf("clone3 %s: returned %ld instead of %ld\n", what, ret, expected);
+    return 1;
+}
+
+int main()
+{
+    int failed = 0;
+    u.args.exit_signal = SIGCHLD;
+
+    failed |= check("of version 0", spawn(CLONE_ARGS_SIZE_VER0) > 0, 1);
+    failed |= check("of the latest version", spawn(sizeof(u.args)) > 0, 1);
+    failed |= check("below version 0", spawn(CLONE_ARGS_SIZE_VER0 - 8), -EINVAL);
+    failed |= check("above a page", spawn(sizeof(u.bytes)), -E2BIG);
+
+    // A newer version is accepted if its unknown fields are zero.
+    failed |= check("of a newer version", spawn(sizeof(u.args) + 8) > 0, 1);
+    u.bytes[sizeof(u.args) + 7] = 1;
+    failed |= check("with unknown fields", spawn(sizeof(u.args) + 8), -E2BIG);
+    u.bytes[sizeof(u.args) + 7] = 0;
+
+    // A freed PID can be requested with set_tid, but one in use cannot.
+    pid_t tid = spawn(sizeof(u.args));
+    u.args.set_tid = (uintptr_t)&tid;
+    u.args.set_tid_size = 1;
+    failed |= check("with a free set_tid", spawn(sizeof(u.args)), tid);
+    tid = getpid();
+    failed |= check("with a used set_tid", spawn(sizeof(u.args)), -EEXIST);
+
+    if (!failed)
+        printf("clone3 accepts every version of struct clone_args\n");
+    return failed;
+}
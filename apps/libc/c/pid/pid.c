@@ -0,0 +1,46 @@
+#include <stdio.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+// PIDs below this value are not reused after PIDs wrap around at pid_max.
+#define RESERVED_PIDS 300
+
+static pid_t spawn()
+{
+    pid_t pid = fork();
+    if (pid == 0)
+        _exit(0);
+    if (pid > 0)
+        waitpid(pid, NULL, 0);
+    return pid;
+}
+
+// Expects a small pid_max (built with PID_MAX=400), so that PIDs wrap around
+// after a few hundred forks.
+int main()
+{
+    pid_t prev = spawn();
+    for (int i = 0; i < 1000; i++) {
+        pid_t pid = spawn();
+        if (pid < 0) {
+            printf("fork failed\n");
+            return 1;
+        }
+        if (pid < prev) {
+            if (pid < RESERVED_PIDS) {
+                printf("PID %d reused after wrapping around\n", pid);
+                return 1;
+            }
+            printf("PIDs wrap around at pid_max\n");
+            return 0;
+        }
+        // A freed PID is not reused before wrapping around.
+        if (pid != prev + 1) {
+            printf("PID %d allocated after %d\n", pid, prev);
+            return 1;
+        }
+        prev = pid;
+    }
+    printf("PIDs do not wrap around\n");
+    return 1;
+}
@@ -0,0 +1,110 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <limits.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+// Scripts are run with this program as their interpreter, which checks the
+// arguments it gets against this environment variable.
+#define EXPECT_ENV "SHEBANG_EXPECT"
+
+static char self[PATH_MAX];
+
+// Writes a script of `len` bytes, which may contain NUL bytes.
+static void write_script(const char *path, const char *data, size_t len)
+{
+    int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0755);
+    write(fd, data, len);
+    close(fd);
+}
+
+// Runs the script at `path` with the argument "a". Returns 0 if the
+// interpreter gets `expected`, its arguments after argv[0] joined by '|', or
+// 100 plus the error number if execve fails.
+static int run(const char *path, const char *expected)
+{
+    pid_t pid = fork();
+    if (pid == 0) {
+        static char env[2 * PATH_MAX];
+        snprintf(env, sizeof(env), "%s=%s", EXPECT_ENV, expected);
+        char *argv[] = {(char *)path, "a", NULL};
+        char *envp[] = {env, NULL};
+        execve(path, argv, envp);
+        _exit(100 + errno);
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    return WIFEXITED(status) ? WEXITSTATUS(status) : -1;
+}
+
+static int check(const char *what, int ret, int expected)
+{
+    if (ret == expected)
+        return 0;
+    printf("shebang %s: exited with %d instead of %d\n", what, ret, expected);
+    return 1;
+}
+
+int main(int argc, char *argv[])
+{
+    const char *expect = getenv(EXPECT_ENV);
+    if (expect) {
+        static char args[2 * PATH_MAX];
+        for (int i = 1; i < argc; i++) {
+            if (i > 1)
+                strcat(args, "|");
+            strcat(args, argv[i]);
+        }
+        return strcmp(args, expect) != 0;
+    }
+
+    char cwd[PATH_MAX], script[PATH_MAX], nested[PATH_MAX];
+    char data[PATH_MAX + 16], expected[2 * PATH_MAX];
+    getcwd(cwd, sizeof(cwd));
+    if (argv[0][0] == '/')
+        snprintf(self, sizeof(self), "%s", argv[0]);
+    else
+        snprintf(self, sizeof(self), "%s/%s", cwd, argv[0]);
+    snprintf(script, sizeof(script), "%s/shebang_script", cwd);
+    snprintf(nested, sizeof(nested), "%s/shebang_nested", cwd);
+    int failed = 0;
+
+    snprintf(data, sizeof(data), "#!%s\necho", self);
+    write_script(script, data, strlen(data));
+    snprintf(expected, sizeof(expected), "%s|a", script);
+    failed |= check("without an argument", run(script, expected), 0);
+
+    // The rest of the line is a single argument with blanks trimmed.
+    snprintf(data, sizeof(data), "#! \t%s  -x -y \t\n", self);
+    write_script(script, data, strlen(data));
+    snprintf(expected, sizeof(expected), "-x -y|%s|a", script);
+    failed |= check("with an argument", run(script, expected), 0);
+
+    // The line ends at a NUL byte.
+    snprintf(data, sizeof(data), "#!%s -e", self);
+    size_t len = strlen(data);
+    memcpy(data + len, "\0-x\n", 4);
+    write_script(script, data, len + 4);
+    snprintf(expected, sizeof(expected), "-e|%s|a", script);
+    failed |= check("with a NUL byte", run(script, expected), 0);
+
+    // An interpreter can be a script itself.
+    snprintf(data, sizeof(data), "#!%s -n\n", self);
+    write_script(script, data, strlen(data));
+    snprintf(data, sizeof(data), "#!%s\n", script);
+    write_script(nested, data, strlen(data));
+    snprintf(expected, sizeof(expected), "-n|%s|%s|a", script, nested);
+    failed |= check("with a script interpreter", run(nested, expected), 0);
+
+    write_script(script, "#!\n", 3);
+    failed |= check("without an interpreter", run(script, ""), 100 + ENOEXEC);
+
+    unlink(script);
+    unlink(nested);
+    if (!failed)
+        printf("shebang scripts run with their interpreters\n");
+    return failed;
+}
@@ -0,0 +1,66 @@
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/resource.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define PAGE_SIZE 4096
+
+// Returns whether reading `addr` succeeds, in a child to survive a fault.
+static int readable(uintptr_t addr)
+{
+    pid_t pid = fork();
+    if (pid == 0) {
+        (void)*(volatile char *)addr;
+        _exit(0);
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    return WIFEXITED(status) && WEXITSTATUS(status) == 0;
+}
+
+// Checks that the stack of this process has `size` bytes.
+static int check_stack(size_t size)
+{
+    char local;
+    uintptr_t top = (uintptr_t)&local & ~(uintptr_t)(PAGE_SIZE - 1);
+    while (readable(top))
+        top += PAGE_SIZE;
+    if (readable(top - size) && !readable(top - size - PAGE_SIZE))
+        return 0;
+    printf("stack_limit: the stack below %#lx has not %zu bytes\n", (unsigned long)top, size);
+    return 1;
+}
+
+// Runs this program with a `limit` of RLIMIT_STACK, and checks that its stack
+// has `size` bytes.
+static int run(const char *self, rlim_t limit, size_t size)
+{
+    pid_t pid = fork();
+    if (pid == 0) {
+        struct rlimit rlim = {limit, RLIM_INFINITY};
+        char arg[32];
+        snprintf(arg, sizeof(arg), "%zu", size);
+        setrlimit(RLIMIT_STACK, &rlim);
+        execl(self, self, arg, NULL);
+        _exit(127);
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    return !WIFEXITED(status) || WEXITSTATUS(status) != 0;
+}
+
+int main(int argc, char *argv[])
+{
+    if (argc > 1)
+        return check_stack(strtoul(argv[1], NULL, 0));
+
+    int failed = 0;
+    // The stack can use up to the soft limit, and no more.
+    failed |= run(argv[0], 8 * PAGE_SIZE, 8 * PAGE_SIZE);
+    failed |= run(argv[0], 12 * PAGE_SIZE, 12 * PAGE_SIZE);
+    if (!failed)
+        printf("the user stack follows RLIMIT_STACK\n");
+    return failed;
+}
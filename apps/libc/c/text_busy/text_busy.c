@@ -0,0 +1,60 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define COPY "text_busy_copy"
+
+// Returns the error number of executing `path`, or 0 if it runs.
+static int exec_error(const char *path)
+{
+    pid_t pid = fork();
+    if (pid == 0) {
+        execl(path, path, "child", NULL);
+        _exit(100 + errno);
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    return WIFEXITED(status) && WEXITSTATUS(status) >= 100 ? WEXITSTATUS(status) - 100 : 0;
+}
+
+int main(int argc, char *argv[])
+{
+    if (argc > 1)
+        return 0;
+    int failed = 0;
+
+    // A running program cannot be opened for writing or truncated.
+    if (open(argv[0], O_WRONLY) >= 0 || errno != ETXTBSY) {
+        printf("text_busy: opening the running program for writing did not fail\n");
+        failed = 1;
+    }
+    if (open(argv[0], O_RDONLY | O_TRUNC) >= 0 || errno != ETXTBSY) {
+        printf("text_busy: truncating the running program did not fail\n");
+        failed = 1;
+    }
+
+    // A program open for writing cannot be executed.
+    int src = open(argv[0], O_RDONLY);
+    int dst = open(COPY, O_WRONLY | O_CREAT | O_TRUNC, 0755);
+    char buf[4096];
+    ssize_t n;
+    while ((n = read(src, buf, sizeof(buf))) > 0)
+        write(dst, buf, n);
+    close(src);
+    if (exec_error("./" COPY) != ETXTBSY) {
+        printf("text_busy: executing a program open for writing did not fail\n");
+        failed = 1;
+    }
+    close(dst);
+    if (exec_error("./" COPY) != 0) {
+        printf("text_busy: executing a closed program failed\n");
+        failed = 1;
+    }
+    unlink(COPY);
+
+    if (!failed)
+        printf("running programs cannot be written\n");
+    return failed;
+}
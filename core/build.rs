@@ -0,0 +1,86 @@
+//! Builds the vDSO image for the target architecture, if the `vdso` feature
+//! is enabled.
+//!
+//! The vDSO is compiled with the musl cross compiler of the target, e.g.
+//! `riscv64-linux-musl-gcc`. Set `VDSO_CC` to use another compiler.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+fn main() {
+    println!("cargo:rerun-if-changed=vdso");
+    println!("cargo:rerun-if-env-changed=VDSO_CC");
+    // Without the vDSO, the kernel maps only a signal return trampoline.
+    if env::var_os("CARGO_FEATURE_VDSO").is_none() {
+        return;
+    }
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap();
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    // The symbol version that libcs look up for the architecture.
+    let version = match arch.as_str() {
+        "x86_64" => "LINUX_2.6",
+        "aarch64" => "LINUX_2.6.39",
+        "riscv64" => "LINUX_4.15",
+        "loongarch64" => "LINUX_5.10",
+        _ => panic!("unsupported architecture {arch}"),
+    };
+    let mut symbols = vec![
+        "__vdso_clock_gettime",
+        "__vdso_gettimeofday",
+        "__vdso_clock_getres",
+        "__vdso_getcpu",
+        "__vdso_rt_sigreturn",
+    ];
+    if arch == "aarch64" {
+        symbols.extend([
+            "__kernel_clock_gettime",
+            "__kernel_gettimeofday",
+            "__kernel_clock_getres",
+            "__kernel_getcpu",
+            "__kernel_rt_sigreturn",
+        ]);
+    }
+    let version_script = out_dir.join("vdso.map");
+    fs::write(
+        &version_script,
+        format!(
+            "{version} {{\n    global:\n        {};\n    local: *;\n}};\n",
+            symbols.join(";\n        ")
+        ),
+    )
+    .unwrap();
+
+    let vdso_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("vdso");
+    let cc = env::var("VDSO_CC").unwrap_or_else(|_| format!("{arch}-linux-musl-gcc"));
+    let mut cmd = Command::new(cc);
+    cmd.args([
+        "-O2",
+        "-fPIC",
+        "-ffreestanding",
+        "-fno-builtin",
+        "-fno-stack-protector",
+        "-fno-asynchronous-unwind-tables",
+        "-nostdlib",
+        "-shared",
+        "-Wl,-soname=linux-vdso.so.1",
+        "-Wl,--hash-style=both",
+        "-Wl,--build-id=none",
+        "-Wl,-z,max-page-size=4096",
+        "-Wl,--no-undefined",
+        "-Wl,-Bsymbolic",
+    ])
+    .arg(format!("-Wl,-T,{}", vdso_dir.join("vdso.lds").display()))
+    .arg(format!("-Wl,--version-script={}", version_script.display()))
+    .arg(vdso_dir.join("vdso.c"))
+    .arg("-o")
+    .arg(out_dir.join("vdso.so"));
+
+    let status = cmd
+        .status()
+        .unwrap_or_else(|err| panic!("failed to run the vDSO compiler: {err}"));
+    assert!(status.success(), "failed to build the vDSO");
+}
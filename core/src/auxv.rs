@@ -0,0 +1,246 @@
+//! The auxiliary vector and the initial stack of a user program.
+//!
+//! The stack is laid out as the C libraries and dynamic linkers expect it,
+//! from high to low addresses:
+//!
+//! ```text
+//! stack top -> execfn string
+//!              environment strings
+//!              argument strings
+//!              platform string
+//!              16 random bytes (AT_RANDOM)
+//!              padding to 16 bytes
+//!              auxv pairs, ending with AT_NULL
+//!              envp pointers, ending with NULL
+//!              argv pointers, ending with NULL
+//! sp        -> argc
+//! ```
+
+use alloc::{string::String, vec, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use memory_addr::PAGE_SIZE_4K;
+
+use crate::{cred::Credentials, signal::MINSIGSTKSZ};
+
+numeric_enum_macro::numeric_enum! {
+    #[repr(usize)]
+    #[allow(non_camel_case_types)]
+    #[derive(Eq, PartialEq, Debug, Clone, Copy)]
+    /// The type of an auxiliary vector entry.
+    pub enum AuxvType {
+        /// End of the vector.
+        AT_NULL = 0,
+        /// Address of the program headers.
+        AT_PHDR = 3,
+        /// Size of a program header entry.
+        AT_PHENT = 4,
+        /// Number of program headers.
+        AT_PHNUM = 5,
+        /// System page size.
+        AT_PAGESZ = 6,
+        /// Load base of the dynamic linker.
+        AT_BASE = 7,
+        /// Flags, always 0.
+        AT_FLAGS = 8,
+        /// Entry point of the program.
+        AT_ENTRY = 9,
+        /// Real user id.
+        AT_UID = 11,
+        /// Effective user id.
+        AT_EUID = 12,
+        /// Real group id.
+        AT_GID = 13,
+        /// Effective group id.
+        AT_EGID = 14,
+        /// Address of a string identifying the platform.
+        AT_PLATFORM = 15,
+        /// Hardware capabilities.
+        AT_HWCAP = 16,
+        /// Frequency of `times()`.
+        AT_CLKTCK = 17,
+        /// Whether the program runs with elevated privileges.
+        AT_SECURE = 23,
+        /// Address of 16 random bytes.
+        AT_RANDOM = 25,
+        /// More hardware capabilities.
+        AT_HWCAP2 = 26,
+        /// Address of the path the program was executed with.
+        AT_EXECFN = 31,
+        /// Address of the vDSO.
+        AT_SYSINFO_EHDR = 33,
+        /// Minimal stack size for signal delivery.
+        AT_MINSIGSTKSZ = 51,
+    }
+}
+
+/// The frequency of `times()`, in ticks per second.
+pub const CLOCKS_PER_SEC: usize = 100;
+
+/// The platform string advertised through `AT_PLATFORM`. RISC-V defines
+/// none, and `AT_PLATFORM` is left out there.
+const PLATFORM: Option<&str> = if cfg!(target_arch = "x86_64") {
+    Some("x86_64")
+} else if cfg!(target_arch = "aarch64") {
+    Some("aarch64")
+} else if cfg!(target_arch = "loongarch64") {
+    Some("loongarch")
+} else {
+    None
+};
+
+/// Returns the hardware capabilities of the current architecture, in the
+/// bit layout that user space expects in `AT_HWCAP`.
+fn hwcap() -> usize {
+    #[cfg(target_arch = "x86_64")]
+    {
+        // The feature bits of CPUID leaf 1 in EDX.
+        unsafe { core::arch::x86_64::__cpuid(1).edx as usize }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        const HWCAP_FP: usize = 1 << 0;
+        const HWCAP_ASIMD: usize = 1 << 1;
+        HWCAP_FP | HWCAP_ASIMD
+    }
+    #[cfg(target_arch = "riscv64")]
+    {
+        // One bit per single-letter ISA extension, `imafdc` here.
+        b"imafdc"
+            .iter()
+            .fold(0, |hwcap, ext| hwcap | 1 << (ext - b'a'))
+    }
+    #[cfg(target_arch = "loongarch64")]
+    {
+        const HWCAP_LOONGARCH_CPUCFG: usize = 1 << 0;
+        const HWCAP_LOONGARCH_LAM: usize = 1 << 1;
+        const HWCAP_LOONGARCH_UAL: usize = 1 << 2;
+        const HWCAP_LOONGARCH_FPU: usize = 1 << 3;
+        HWCAP_LOONGARCH_CPUCFG | HWCAP_LOONGARCH_LAM | HWCAP_LOONGARCH_UAL | HWCAP_LOONGARCH_FPU
+    }
+}
+
+/// Information about a loaded program needed by the auxiliary vector.
+#[derive(Debug, Clone, Copy)]
+pub struct ProgramInfo {
+    /// Address of the program headers.
+    pub phdr: usize,
+    /// Size of a program header entry.
+    pub phent: usize,
+    /// Number of program headers.
+    pub phnum: usize,
+    /// Entry point of the program.
+    pub entry: usize,
+    /// Load base of the dynamic linker, or 0 for a static program.
+    pub interp_base: usize,
+    /// Address of the vDSO, if one is mapped.
+    pub vdso_base: Option<usize>,
+}
+
+/// Builds the auxiliary vector of a program running with `cred`, except for
+/// the entries that point into the stack, which are added by [`init_stack`].
+pub fn auxv_vector(program: &ProgramInfo, cred: &Credentials) -> Vec<(AuxvType, usize)> {
+    let mut auxv = vec![
+        (AuxvType::AT_PHDR, program.phdr),
+        (AuxvType::AT_PHENT, program.phent),
+        (AuxvType::AT_PHNUM, program.phnum),
+        (AuxvType::AT_PAGESZ, PAGE_SIZE_4K),
+        (AuxvType::AT_BASE, program.interp_base),
+        (AuxvType::AT_FLAGS, 0),
+        (AuxvType::AT_ENTRY, program.entry),
+        (AuxvType::AT_HWCAP, hwcap()),
+        (AuxvType::AT_HWCAP2, 0),
+        (AuxvType::AT_CLKTCK, CLOCKS_PER_SEC),
+        (AuxvType::AT_MINSIGSTKSZ, MINSIGSTKSZ),
+        (AuxvType::AT_UID, cred.uid as usize),
+        (AuxvType::AT_EUID, cred.euid as usize),
+        (AuxvType::AT_GID, cred.gid as usize),
+        (AuxvType::AT_EGID, cred.egid as usize),
+        (AuxvType::AT_SECURE, cred.is_secure_exec() as usize),
+    ];
+    if let Some(vdso_base) = program.vdso_base {
+        auxv.push((AuxvType::AT_SYSINFO_EHDR, vdso_base));
+    }
+    auxv
+}
+
+/// Returns 16 bytes for `AT_RANDOM`.
+///
+/// There is no hardware entropy source, so the bytes come from a SplitMix64
+/// generator mixed with the current time. They are unpredictable enough for
+/// stack protectors, but not for cryptography.
+fn random_bytes() -> [u8; 16] {
+    static STATE: AtomicU64 = AtomicU64::new(0);
+    let mut next = || {
+        let seed = axhal::time::monotonic_time_nanos();
+        let mut z = STATE
+            .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed)
+            .wrapping_add(seed);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&next().to_ne_bytes());
+    bytes[8..].copy_from_slice(&next().to_ne_bytes());
+    bytes
+}
+
+/// Builds the initial stack of a user program, which ends at `stack_top`.
+///
+/// `AT_PLATFORM`, `AT_RANDOM`, `AT_EXECFN` and the terminating `AT_NULL` are
+/// appended to `auxv`.
+///
+/// # Returns
+/// - The contents of the stack, from the initial stack pointer up to
+///   `stack_top`.
+pub fn init_stack(
+    args: &[String],
+    envs: &[String],
+    execfn: &str,
+    mut auxv: Vec<(AuxvType, usize)>,
+    stack_top: usize,
+) -> Vec<u8> {
+    // The strings, from low to high addresses.
+    let mut info = Vec::new();
+    let mut push_str = |s: &str| {
+        let offset = info.len();
+        info.extend_from_slice(s.as_bytes());
+        info.push(0);
+        offset
+    };
+    let platform = PLATFORM.map(&mut push_str);
+    let arg_offsets: Vec<_> = args.iter().map(|arg| push_str(arg)).collect();
+    let env_offsets: Vec<_> = envs.iter().map(|env| push_str(env)).collect();
+    let execfn = push_str(execfn);
+
+    let info_start = stack_top - info.len();
+    let random = info_start - 16;
+    if let Some(platform) = platform {
+        auxv.push((AuxvType::AT_PLATFORM, info_start + platform));
+    }
+    auxv.push((AuxvType::AT_RANDOM, random));
+    auxv.push((AuxvType::AT_EXECFN, info_start + execfn));
+    auxv.push((AuxvType::AT_NULL, 0));
+
+    let mut table = Vec::with_capacity(3 + args.len() + envs.len() + auxv.len() * 2);
+    table.push(args.len());
+    table.extend(arg_offsets.iter().map(|offset| info_start + offset));
+    table.push(0);
+    table.extend(env_offsets.iter().map(|offset| info_start + offset));
+    table.push(0);
+    for (ty, value) in auxv {
+        table.push(ty as usize);
+        table.push(value);
+    }
+
+    let sp = (random - table.len() * size_of::<usize>()) & !0xf;
+    let mut stack = Vec::with_capacity(stack_top - sp);
+    for word in table {
+        stack.extend_from_slice(&word.to_ne_bytes());
+    }
+    stack.resize(random - sp, 0);
+    stack.extend_from_slice(&random_bytes());
+    stack.extend_from_slice(&info);
+    stack
+}
@@ -0,0 +1,420 @@
+//! User and group credentials, and the file permission checks based on them.
+//!
+//! Every process has real, effective, saved and filesystem user and group
+//! IDs, supplementary groups and capability sets. The effective IDs and
+//! capabilities decide what a process may do, the filesystem IDs are used
+//! for file access and equal the effective ones (there is no `setfsuid`).
+
+use alloc::vec::Vec;
+
+use arceos_posix_api::{FileMeta, S_ISVTX, file_meta};
+use axerrno::{LinuxError, LinuxResult};
+
+/// The maximum number of supplementary groups. `setgroups` fails with
+/// `EINVAL` beyond it.
+pub const NGROUPS_MAX: usize = 65536;
+
+/// Make arbitrary changes to file owners.
+pub const CAP_CHOWN: u32 = 0;
+/// Bypass file read, write and execute permission checks.
+pub const CAP_DAC_OVERRIDE: u32 = 1;
+/// Bypass file read and directory search permission checks.
+pub const CAP_DAC_READ_SEARCH: u32 = 2;
+/// Bypass the checks that require the file owner, e.g. for the sticky bit.
+pub const CAP_FOWNER: u32 = 3;
+/// Send signals to any process.
+pub const CAP_KILL: u32 = 5;
+/// Make arbitrary changes to the group IDs.
+pub const CAP_SETGID: u32 = 6;
+/// Make arbitrary changes to the user IDs.
+pub const CAP_SETUID: u32 = 7;
+/// Add any capability to the inheritable set.
+pub const CAP_SETPCAP: u32 = 8;
+/// Perform various system administration operations.
+pub const CAP_SYS_ADMIN: u32 = 21;
+/// Raise the priority of processes and use real-time scheduling.
+pub const CAP_SYS_NICE: u32 = 23;
+/// Override resource limits.
+pub const CAP_SYS_RESOURCE: u32 = 24;
+/// Set the system clock.
+pub const CAP_SYS_TIME: u32 = 25;
+/// The highest capability known. Capability bits above it are ignored.
+pub const CAP_LAST_CAP: u32 = 40;
+/// All capabilities.
+pub const CAP_FULL_SET: u64 = (1 << (CAP_LAST_CAP + 1)) - 1;
+
+bitflags::bitflags! {
+    /// The access requested to a file, for [`Credentials::may_access`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct AccessMode: u32 {
+        /// Execute a file, or search a directory.
+        const EXEC = 1;
+        /// Write a file, or create and remove entries in a directory.
+        const WRITE = 2;
+        /// Read a file, or list a directory.
+        const READ = 4;
+    }
+}
+
+/// The credentials of a process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    /// The real user ID.
+    pub uid: u32,
+    /// The effective user ID.
+    pub euid: u32,
+    /// The saved set-user-ID.
+    pub suid: u32,
+    /// The filesystem user ID.
+    pub fsuid: u32,
+    /// The real group ID.
+    pub gid: u32,
+    /// The effective group ID.
+    pub egid: u32,
+    /// The saved set-group-ID.
+    pub sgid: u32,
+    /// The filesystem group ID.
+    pub fsgid: u32,
+    /// The supplementary groups, sorted.
+    pub groups: Vec<u32>,
+    /// The capabilities preserved across `execve`.
+    pub cap_inheritable: u64,
+    /// The capabilities that may be made effective.
+    pub cap_permitted: u64,
+    /// The capabilities used for permission checks.
+    pub cap_effective: u64,
+}
+
+impl Credentials {
+    /// Returns the credentials of root, with all capabilities.
+    pub const fn root() -> Self {
+        Self {
+            uid: 0,
+            euid: 0,
+            suid: 0,
+            fsuid: 0,
+            gid: 0,
+            egid: 0,
+            sgid: 0,
+            fsgid: 0,
+            groups: Vec::new(),
+            cap_inheritable: 0,
+            cap_permitted: CAP_FULL_SET,
+            cap_effective: CAP_FULL_SET,
+        }
+    }
+
+    /// Whether the capability `cap` is effective.
+    pub fn capable(&self, cap: u32) -> bool {
+        self.cap_effective & (1 << cap) != 0
+    }
+
+    /// Whether `gid` is the filesystem group ID or a supplementary group.
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.fsgid == gid || self.groups.binary_search(&gid).is_ok()
+    }
+
+    /// Sets the user IDs like `setuid`: all of them with `CAP_SETUID`,
+    /// otherwise only the effective one, to the real or saved user ID.
+    pub fn set_uid(&mut self, uid: u32) -> LinuxResult<()> {
+        let old = self.clone();
+        if self.capable(CAP_SETUID) {
+            self.uid = uid;
+            self.suid = uid;
+        } else if uid != self.uid && uid != self.suid {
+            return Err(LinuxError::EPERM);
+        }
+        self.euid = uid;
+        self.fsuid = uid;
+        self.fix_capabilities(&old);
+        Ok(())
+    }
+
+    /// Sets the real and effective user IDs like `setreuid`. `None` leaves
+    /// an ID unchanged.
+    ///
+    /// Without `CAP_SETUID`, the real user ID may only be set to the real or
+    /// effective one, and the effective user ID to the real, effective or
+    /// saved one. The saved user ID follows the new effective one if the
+    /// real user ID is set, or the effective one differs from it.
+    pub fn set_reuid(&mut self, ruid: Option<u32>, euid: Option<u32>) -> LinuxResult<()> {
+        let old = self.clone();
+        let privileged = self.capable(CAP_SETUID);
+        if let Some(ruid) = ruid {
+            if !privileged && ruid != old.uid && ruid != old.euid {
+                return Err(LinuxError::EPERM);
+            }
+            self.uid = ruid;
+        }
+        if let Some(euid) = euid {
+            if !privileged && euid != old.uid && euid != old.euid && euid != old.suid {
+                return Err(LinuxError::EPERM);
+            }
+            self.euid = euid;
+        }
+        if ruid.is_some() || euid.is_some_and(|euid| euid != old.uid) {
+            self.suid = self.euid;
+        }
+        self.fsuid = self.euid;
+        self.fix_capabilities(&old);
+        Ok(())
+    }
+
+    /// Sets the real, effective and saved user IDs like `setresuid`. `None`
+    /// leaves an ID unchanged.
+    ///
+    /// Without `CAP_SETUID`, each ID may only be set to the current real,
+    /// effective or saved user ID.
+    pub fn set_resuid(
+        &mut self,
+        ruid: Option<u32>,
+        euid: Option<u32>,
+        suid: Option<u32>,
+    ) -> LinuxResult<()> {
+        let old = self.clone();
+        let allowed = |id: &u32| *id == old.uid || *id == old.euid || *id == old.suid;
+        if !self.capable(CAP_SETUID) && ![ruid, euid, suid].iter().flatten().all(allowed) {
+            return Err(LinuxError::EPERM);
+        }
+        self.uid = ruid.unwrap_or(self.uid);
+        self.euid = euid.unwrap_or(self.euid);
+        self.suid = suid.unwrap_or(self.suid);
+        self.fsuid = self.euid;
+        self.fix_capabilities(&old);
+        Ok(())
+    }
+
+    /// Sets the group IDs like `setgid`. See [`Credentials::set_uid`].
+    pub fn set_gid(&mut self, gid: u32) -> LinuxResult<()> {
+        if self.capable(CAP_SETGID) {
+            self.gid = gid;
+            self.sgid = gid;
+        } else if gid != self.gid && gid != self.sgid {
+            return Err(LinuxError::EPERM);
+        }
+        self.egid = gid;
+        self.fsgid = gid;
+        Ok(())
+    }
+
+    /// Sets the real and effective group IDs like `setregid`. See
+    /// [`Credentials::set_reuid`].
+    pub fn set_regid(&mut self, rgid: Option<u32>, egid: Option<u32>) -> LinuxResult<()> {
+        let old = self.clone();
+        let privileged = self.capable(CAP_SETGID);
+        if let Some(rgid) = rgid {
+            if !privileged && rgid != old.gid && rgid != old.egid {
+                return Err(LinuxError::EPERM);
+            }
+            self.gid = rgid;
+        }
+        if let Some(egid) = egid {
+            if !privileged && egid != old.gid && egid != old.egid && egid != old.sgid {
+                return Err(LinuxError::EPERM);
+            }
+            self.egid = egid;
+        }
+        if rgid.is_some() || egid.is_some_and(|egid| egid != old.gid) {
+            self.sgid = self.egid;
+        }
+        self.fsgid = self.egid;
+        Ok(())
+    }
+
+    /// Sets the real, effective and saved group IDs like `setresgid`. See
+    /// [`Credentials::set_resuid`].
+    pub fn set_resgid(
+        &mut self,
+        rgid: Option<u32>,
+        egid: Option<u32>,
+        sgid: Option<u32>,
+    ) -> LinuxResult<()> {
+        let allowed = |id: &u32| *id == self.gid || *id == self.egid || *id == self.sgid;
+        if !self.capable(CAP_SETGID) && ![rgid, egid, sgid].iter().flatten().all(allowed) {
+            return Err(LinuxError::EPERM);
+        }
+        self.gid = rgid.unwrap_or(self.gid);
+        self.egid = egid.unwrap_or(self.egid);
+        self.sgid = sgid.unwrap_or(self.sgid);
+        self.fsgid = self.egid;
+        Ok(())
+    }
+
+    /// Replaces the supplementary groups, which requires `CAP_SETGID`.
+    pub fn set_groups(&mut self, mut groups: Vec<u32>) -> LinuxResult<()> {
+        if !self.capable(CAP_SETGID) {
+            return Err(LinuxError::EPERM);
+        }
+        if groups.len() > NGROUPS_MAX {
+            return Err(LinuxError::EINVAL);
+        }
+        groups.sort_unstable();
+        groups.dedup();
+        self.groups = groups;
+        Ok(())
+    }
+
+    /// Sets the capability sets like `capset`.
+    ///
+    /// The permitted set can only shrink, the effective set must be a subset
+    /// of the new permitted set, and without `CAP_SETPCAP` the inheritable
+    /// set may only gain permitted capabilities.
+    pub fn set_capabilities(
+        &mut self,
+        effective: u64,
+        permitted: u64,
+        inheritable: u64,
+    ) -> LinuxResult<()> {
+        let inheritable_limit = if self.capable(CAP_SETPCAP) {
+            CAP_FULL_SET
+        } else {
+            self.cap_inheritable | self.cap_permitted
+        };
+        if inheritable & !inheritable_limit != 0
+            || permitted & !self.cap_permitted != 0
+            || effective & !permitted != 0
+        {
+            return Err(LinuxError::EPERM);
+        }
+        self.cap_effective = effective;
+        self.cap_permitted = permitted;
+        self.cap_inheritable = inheritable;
+        Ok(())
+    }
+
+    /// Adjusts the capabilities after the user IDs changed from `old`. The
+    /// securebits are not supported, so capabilities are never kept.
+    ///
+    /// Dropping root in all of the real, effective and saved user IDs clears
+    /// the permitted and effective sets. Changing the effective user ID from
+    /// root clears the effective set, and changing it to root restores it
+    /// from the permitted set.
+    fn fix_capabilities(&mut self, old: &Self) {
+        let was_root = old.uid == 0 || old.euid == 0 || old.suid == 0;
+        let is_root = self.uid == 0 || self.euid == 0 || self.suid == 0;
+        if was_root && !is_root {
+            self.cap_permitted = 0;
+            self.cap_effective = 0;
+        }
+        if old.euid == 0 && self.euid != 0 {
+            self.cap_effective = 0;
+        }
+        if old.euid != 0 && self.euid == 0 {
+            self.cap_effective = self.cap_permitted;
+        }
+    }
+
+    /// Updates the credentials for `execve`, which does not support
+    /// set-user-ID programs or file capabilities.
+    ///
+    /// Root gets all capabilities, effective only if the
+    /// effective user ID is root, and other users lose them. The saved and
+    /// filesystem IDs are set to the effective ones.
+    pub fn exec(&mut self) {
+        if self.uid == 0 || self.euid == 0 {
+            self.cap_permitted = CAP_FULL_SET;
+            self.cap_effective = if self.euid == 0 { CAP_FULL_SET } else { 0 };
+        } else {
+            self.cap_permitted = 0;
+            self.cap_effective = 0;
+        }
+        self.suid = self.euid;
+        self.fsuid = self.euid;
+        self.sgid = self.egid;
+        self.fsgid = self.egid;
+    }
+
+    /// Whether a program started with these credentials must be treated as
+    /// running with elevated privileges (`AT_SECURE`).
+    pub fn is_secure_exec(&self) -> bool {
+        self.uid != self.euid || self.gid != self.egid
+    }
+
+    /// Checks the permission bits of a file, or the capabilities that
+    /// override them.
+    ///
+    /// Root may read and write any file, and execute any file that has an
+    /// execute bit set.
+    pub fn may_access(&self, meta: &FileMeta, mask: AccessMode) -> bool {
+        let perm = if self.fsuid == meta.uid {
+            meta.mode >> 6
+        } else if self.in_group(meta.gid) {
+            meta.mode >> 3
+        } else {
+            meta.mode
+        };
+        if AccessMode::from_bits_truncate(perm & 0o7).contains(mask) {
+            return true;
+        }
+        let executable = meta.is_dir || meta.mode & 0o111 != 0;
+        if self.capable(CAP_DAC_OVERRIDE) && (!mask.contains(AccessMode::EXEC) || executable) {
+            return true;
+        }
+        let read_search = if meta.is_dir {
+            AccessMode::READ | AccessMode::EXEC
+        } else {
+            AccessMode::READ
+        };
+        self.capable(CAP_DAC_READ_SEARCH) && read_search.contains(mask)
+    }
+}
+
+/// Returns the parent directory of the canonical path `path`, or `None` for
+/// the root directory.
+fn parent_dir(path: &str) -> Option<&str> {
+    let path = path.trim_end_matches('/');
+    path.rfind('/')
+        .map(|pos| if pos == 0 { "/" } else { &path[..pos] })
+}
+
+/// Checks that the directories leading to the canonical path `path` can be
+/// searched.
+pub fn may_lookup(cred: &Credentials, path: &str) -> LinuxResult<()> {
+    let Some(parent) = parent_dir(path) else {
+        return Ok(());
+    };
+    may_lookup(cred, parent)?;
+    let meta = file_meta(parent)?;
+    if !meta.is_dir {
+        return Err(LinuxError::ENOTDIR);
+    }
+    if !cred.may_access(&meta, AccessMode::EXEC) {
+        return Err(LinuxError::EACCES);
+    }
+    Ok(())
+}
+
+/// Checks that the file at the canonical path `path` can be accessed with
+/// `mask`.
+pub fn may_open(cred: &Credentials, path: &str, mask: AccessMode) -> LinuxResult<()> {
+    may_lookup(cred, path)?;
+    if !cred.may_access(&file_meta(path)?, mask) {
+        return Err(LinuxError::EACCES);
+    }
+    Ok(())
+}
+
+/// Checks that a file can be created at the canonical path `path`, which
+/// requires write and search permission on its directory.
+pub fn may_create(cred: &Credentials, path: &str) -> LinuxResult<()> {
+    let parent = parent_dir(path).ok_or(LinuxError::EEXIST)?;
+    may_open(cred, parent, AccessMode::WRITE | AccessMode::EXEC)
+}
+
+/// Checks that the file at the canonical path `path` can be removed.
+///
+/// Besides the permissions of [`may_create`], only the owner of the file or
+/// of the directory may remove it from a directory with the sticky bit.
+pub fn may_delete(cred: &Credentials, path: &str) -> LinuxResult<()> {
+    may_create(cred, path)?;
+    let parent = file_meta(parent_dir(path).ok_or(LinuxError::EBUSY)?)?;
+    let meta = file_meta(path)?;
+    if parent.mode & S_ISVTX != 0
+        && cred.fsuid != meta.uid
+        && cred.fsuid != parent.uid
+        && !cred.capable(CAP_FOWNER)
+    {
+        return Err(LinuxError::EPERM);
+    }
+    Ok(())
+}
@@ -1,7 +1,11 @@
 //! clone 任务时指定的参数。
 
+use arceos_posix_api::ctypes::timeval;
+use axhal::time::{NANOS_PER_MICROS, NANOS_PER_SEC};
 use bitflags::*;
 
+use crate::signal::SIGCONT;
+
 bitflags! {
     /// 用于 sys_clone 的选项
     #[derive(Debug, Clone, Copy)]
@@ -49,10 +53,14 @@ bitflags! {
     pub struct WaitFlags: u32 {
         /// 不挂起当前进程，直接返回
         const WNOHANG = 1 << 0;
-        /// 报告已执行结束的用户进程的状态
-        const WIMTRACED = 1 << 1;
-        /// 报告还未结束的用户进程的状态
+        /// 报告已停止的子进程的状态
+        const WUNTRACED = 1 << 1;
+        /// 报告已退出的子进程的状态，用于 sys_waitid
+        const WEXITED = 1 << 2;
+        /// 报告已被 SIGCONT 恢复的子进程的状态
         const WCONTINUED = 1 << 3;
+        /// 只查询状态，不回收子进程，用于 sys_waitid
+        const WNOWAIT = 1 << 24;
         /// Wait for any child
         const WALL = 1 << 30;
         /// Wait for cloned process
@@ -61,16 +69,124 @@ bitflags! {
 
 }
 
-/// sys_wait4 的返回值
+/// 子进程的状态变化，由 sys_wait4 和 sys_waitid 报告给父进程
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WaitStatus {
-    /// 子任务正常退出
-    Exited,
-    /// 子任务正在运行
-    Running,
-    /// 找不到对应的子任务
-    NotExist,
+    /// 子进程正常退出，附带退出码
+    Exited(i32),
+    /// 子进程被信号终止
+    Signaled {
+        /// 终止子进程的信号
+        signo: u32,
+        /// 是否产生了 core dump
+        core_dumped: bool,
+    },
+    /// 子进程被信号暂停
+    Stopped(u32),
+    /// 子进程被 SIGCONT 恢复运行
+    Continued,
+}
+
+/// `siginfo_t` 中 SIGCHLD 的 si_code
+const CLD_EXITED: i32 = 1;
+const CLD_KILLED: i32 = 2;
+const CLD_DUMPED: i32 = 3;
+const CLD_STOPPED: i32 = 5;
+const CLD_CONTINUED: i32 = 6;
+
+impl WaitStatus {
+    /// 按照 sys_wait4 中 `wstatus` 的格式编码
+    pub fn to_wstatus(self) -> i32 {
+        match self {
+            Self::Exited(code) => (code & 0xff) << 8,
+            Self::Signaled { signo, core_dumped } => {
+                signo as i32 | if core_dumped { 0x80 } else { 0 }
+            }
+            Self::Stopped(signo) => ((signo as i32) << 8) | 0x7f,
+            Self::Continued => 0xffff,
+        }
+    }
+
+    /// 返回 sys_waitid 中 `siginfo_t` 的 si_code 和 si_status
+    pub fn to_siginfo_code(self) -> (i32, i32) {
+        match self {
+            Self::Exited(code) => (CLD_EXITED, code & 0xff),
+            Self::Signaled {
+                signo,
+                core_dumped: false,
+            } => (CLD_KILLED, signo as i32),
+            Self::Signaled {
+                signo,
+                core_dumped: true,
+            } => (CLD_DUMPED, signo as i32),
+            Self::Stopped(signo) => (CLD_STOPPED, signo as i32),
+            Self::Continued => (CLD_CONTINUED, SIGCONT as i32),
+        }
+    }
+}
+
+numeric_enum_macro::numeric_enum! {
+    #[repr(u32)]
+    #[allow(non_camel_case_types)]
+    #[derive(Eq, PartialEq, Debug, Clone, Copy)]
+    /// sys_waitid 的 idtype
+    pub enum WaitIdType {
+        /// 等待任意子进程
+        P_ALL = 0,
+        /// 等待指定 pid 的子进程
+        P_PID = 1,
+        /// 等待指定进程组中的子进程
+        P_PGID = 2,
+    }
+}
+
+/// 资源使用统计，用于 sys_wait4 和 sys_getrusage
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Rusage {
+    /// 用户态执行时间
+    pub ru_utime: timeval,
+    /// 内核态执行时间
+    pub ru_stime: timeval,
+    /// 最大常驻内存，单位为 KB
+    pub ru_maxrss: isize,
+    pub ru_ixrss: isize,
+    pub ru_idrss: isize,
+    pub ru_isrss: isize,
+    /// 不需要 I/O 的缺页次数
+    pub ru_minflt: isize,
+    /// 需要 I/O 的缺页次数
+    pub ru_majflt: isize,
+    pub ru_nswap: isize,
+    pub ru_inblock: isize,
+    pub ru_oublock: isize,
+    pub ru_msgsnd: isize,
+    pub ru_msgrcv: isize,
+    pub ru_nsignals: isize,
+    /// 主动上下文切换次数
+    pub ru_nvcsw: isize,
+    /// 被动上下文切换次数
+    pub ru_nivcsw: isize,
+}
+
+impl Rusage {
+    /// 根据用户态和内核态时间（单位为ns）创建
+    pub fn from_times(utime_ns: usize, stime_ns: usize) -> Self {
+        Self {
+            ru_utime: nanos_to_timeval(utime_ns),
+            ru_stime: nanos_to_timeval(stime_ns),
+            ..Default::default()
+        }
+    }
+}
+
+fn nanos_to_timeval(ns: usize) -> timeval {
+    timeval {
+        tv_sec: (ns / NANOS_PER_SEC as usize) as _,
+        tv_usec: (ns % NANOS_PER_SEC as usize / NANOS_PER_MICROS as usize) as _,
+    }
 }
+
 #[repr(C)]
 pub struct Tms {
     /// 进程用户态执行时间，单位为us
@@ -107,14 +223,15 @@ impl From<usize> for TimerType {
         }
     }
 }
+/// 线程的 CPU 时间统计
+///
+/// 时间戳取自线程自己的 CPU 时钟（即 `TaskInner::cpu_time_ns`），因此线程
+/// 睡眠或被切换出去的时间不会被统计。
 pub struct TimeStat {
     utime_ns: usize,
     stime_ns: usize,
     user_timestamp: usize,
     kernel_timestamp: usize,
-    timer_type: TimerType,
-    timer_interval_ns: usize,
-    timer_remained_ns: usize,
 }
 
 impl Default for TimeStat {
@@ -130,9 +247,6 @@ impl TimeStat {
             stime_ns: 0,
             user_timestamp: 0,
             kernel_timestamp: 0,
-            timer_type: TimerType::NONE,
-            timer_interval_ns: 0,
-            timer_remained_ns: 0,
         }
     }
 
@@ -147,63 +261,19 @@ impl TimeStat {
         self.kernel_timestamp = current_timestamp;
     }
 
-    pub fn switch_into_kernel_mode(&mut self, current_timestamp: usize) {
-        let now_time_ns = current_timestamp;
-        let delta = now_time_ns - self.kernel_timestamp;
+    /// 从用户态进入内核态，返回这段用户态的运行时间
+    pub fn switch_into_kernel_mode(&mut self, current_timestamp: usize) -> usize {
+        let delta = current_timestamp.saturating_sub(self.user_timestamp);
         self.utime_ns += delta;
-        self.kernel_timestamp = now_time_ns;
-        if self.timer_type != TimerType::NONE {
-            self.update_timer(delta);
-        };
-    }
-
-    pub fn switch_into_user_mode(&mut self, current_timestamp: usize) {
-        let now_time_ns = current_timestamp;
-        let delta = now_time_ns - self.kernel_timestamp;
-        self.stime_ns += delta;
-        self.user_timestamp = now_time_ns;
-        if self.timer_type == TimerType::REAL || self.timer_type == TimerType::PROF {
-            self.update_timer(delta);
-        }
+        self.kernel_timestamp = current_timestamp;
+        delta
     }
 
-    pub fn switch_from_old_task(&mut self, current_timestamp: usize) {
-        let now_time_ns = current_timestamp;
-        let delta = now_time_ns - self.kernel_timestamp;
+    /// 从内核态返回用户态，返回这段内核态的运行时间
+    pub fn switch_into_user_mode(&mut self, current_timestamp: usize) -> usize {
+        let delta = current_timestamp.saturating_sub(self.kernel_timestamp);
         self.stime_ns += delta;
-        self.kernel_timestamp = now_time_ns;
-        if self.timer_type == TimerType::REAL || self.timer_type == TimerType::PROF {
-            self.update_timer(delta);
-        }
-    }
-
-    pub fn switch_to_new_task(&mut self, current_timestamp: usize) {
-        let now_time_ns = current_timestamp;
-        let delta = now_time_ns - self.kernel_timestamp;
-        self.kernel_timestamp = now_time_ns;
-        if self.timer_type == TimerType::REAL {
-            self.update_timer(delta);
-        }
-    }
-
-    pub fn set_timer(
-        &mut self,
-        timer_interval_ns: usize,
-        timer_remained_ns: usize,
-        timer_type: usize,
-    ) -> bool {
-        self.timer_type = timer_type.into();
-        self.timer_interval_ns = timer_interval_ns;
-        self.timer_remained_ns = timer_remained_ns;
-        self.timer_type != TimerType::NONE
-    }
-
-    pub fn update_timer(&mut self, delta: usize) {
-        if self.timer_remained_ns == 0 {
-            return;
-        }
-        if self.timer_remained_ns > delta {
-            self.timer_remained_ns -= delta;
-        }
+        self.user_timestamp = current_timestamp;
+        delta
     }
 }
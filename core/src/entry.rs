@@ -4,7 +4,9 @@ use axhal::arch::UspaceContext;
 use axsync::Mutex;
 
 use crate::{
+    cred::Credentials,
     mm::{copy_from_kernel, load_user_app, new_user_aspace_empty},
+    resource::Rlimits,
     task::spawn_user_task,
 };
 
@@ -19,8 +21,15 @@ pub fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
     let path = FilePath::new(&args[0]).expect("Invalid file path");
     axfs::api::set_current_dir(path.parent().unwrap()).expect("Failed to set current dir");
 
-    let (entry_vaddr, ustack_top) = load_user_app(&mut uspace, args, envs)
-        .unwrap_or_else(|e| panic!("Failed to load user app: {}", e));
+    let (entry_vaddr, ustack_top) = load_user_app(
+        &mut uspace,
+        &args[0],
+        args,
+        envs,
+        &Credentials::root(),
+        &Rlimits::default(),
+    )
+    .unwrap_or_else(|e| panic!("Failed to load user app: {:?}", e));
     let user_task = spawn_user_task(
         Arc::new(Mutex::new(uspace)),
         UspaceContext::new(entry_vaddr.into(), ustack_top, 2333),
@@ -0,0 +1,196 @@
+//! Fast user-space mutexes (futexes).
+//!
+//! A futex is identified by the address space it lives in and its user
+//! address, so futexes in memory shared between different address spaces
+//! (e.g. `MAP_SHARED` mappings) are not matched with each other.
+//!
+//! All waiters sleep on a single wait queue. A woken waiter is first removed
+//! from the futex table and marked, then the whole queue is notified and
+//! unmarked waiters go back to sleep.
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::Mutex;
+use axtask::{TaskExtRef, WaitQueue, current};
+
+use crate::signal::has_pending_signal;
+
+/// The bitset that matches any waiter.
+pub const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+/// Identifies a futex by its address space and user address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FutexKey {
+    aspace: usize,
+    uaddr: usize,
+}
+
+impl FutexKey {
+    /// Returns the key of the futex at `uaddr` in the address space of the
+    /// current task.
+    pub fn new_current(uaddr: usize) -> Self {
+        Self {
+            aspace: Arc::as_ptr(&current().task_ext().aspace) as usize,
+            uaddr,
+        }
+    }
+}
+
+struct Waiter {
+    bitset: u32,
+    woken: AtomicBool,
+}
+
+static FUTEX_TABLE: Mutex<BTreeMap<FutexKey, VecDeque<Arc<Waiter>>>> = Mutex::new(BTreeMap::new());
+static FUTEX_WQ: WaitQueue = WaitQueue::new();
+
+/// Blocks the current task on the futex `key` until it is woken up by
+/// [`futex_wake`] with a matching `bitset`, or `timeout` expires.
+///
+/// `check` is called with the futex table locked, and the task only sleeps if
+/// it returns `true`, so a concurrent wakeup cannot be missed.
+///
+/// Returns `EAGAIN` if `check` fails, `ETIMEDOUT` if the timeout expires, or
+/// `EINTR` if the process is exiting or a signal is pending.
+pub fn futex_wait(
+    key: FutexKey,
+    bitset: u32,
+    timeout: Option<Duration>,
+    check: impl FnOnce() -> LinuxResult<bool>,
+) -> LinuxResult {
+    if bitset == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let waiter = Arc::new(Waiter {
+        bitset,
+        woken: AtomicBool::new(false),
+    });
+    {
+        let mut table = FUTEX_TABLE.lock();
+        if !check()? {
+            return Err(LinuxError::EAGAIN);
+        }
+        table.entry(key).or_default().push_back(waiter.clone());
+    }
+
+    let process = current().task_ext().process.clone();
+    let condition = || {
+        waiter.woken.load(Ordering::Acquire) || process.is_group_exiting() || has_pending_signal()
+    };
+    let timed_out = match timeout {
+        Some(timeout) => FUTEX_WQ.wait_timeout_until(timeout, condition),
+        None => {
+            FUTEX_WQ.wait_until(condition);
+            false
+        }
+    };
+    if waiter.woken.load(Ordering::Acquire) {
+        return Ok(());
+    }
+
+    // Still in the table, possibly requeued to another futex.
+    let mut table = FUTEX_TABLE.lock();
+    if waiter.woken.load(Ordering::Acquire) {
+        return Ok(());
+    }
+    table.retain(|_, waiters| {
+        waiters.retain(|w| !Arc::ptr_eq(w, &waiter));
+        !waiters.is_empty()
+    });
+    Err(if timed_out {
+        LinuxError::ETIMEDOUT
+    } else {
+        LinuxError::EINTR
+    })
+}
+
+/// Wakes up at most `count` tasks waiting on the futex `key` whose bitset
+/// intersects `bitset`.
+///
+/// Returns the number of tasks woken up.
+pub fn futex_wake(key: FutexKey, count: usize, bitset: u32) -> usize {
+    let mut woken = 0;
+    {
+        let mut table = FUTEX_TABLE.lock();
+        if let Some(waiters) = table.get_mut(&key) {
+            waiters.retain(|waiter| {
+                if woken < count && waiter.bitset & bitset != 0 {
+                    waiter.woken.store(true, Ordering::Release);
+                    woken += 1;
+                    false
+                } else {
+                    true
+                }
+            });
+            if waiters.is_empty() {
+                table.remove(&key);
+            }
+        }
+    }
+    if woken > 0 {
+        FUTEX_WQ.notify_all(false);
+    }
+    woken
+}
+
+/// Wakes up at most `count` tasks waiting on the futex `key`, and moves at
+/// most `requeue_count` of the remaining waiters to the futex `new_key`.
+///
+/// `check` is called with the futex table locked, and nothing is done if it
+/// returns `false`, in which case `EAGAIN` is returned.
+///
+/// Returns the number of tasks woken up or requeued.
+pub fn futex_requeue(
+    key: FutexKey,
+    count: usize,
+    new_key: FutexKey,
+    requeue_count: usize,
+    check: impl FnOnce() -> LinuxResult<bool>,
+) -> LinuxResult<usize> {
+    let mut woken = 0;
+    let mut requeued = 0;
+    {
+        let mut table = FUTEX_TABLE.lock();
+        if !check()? {
+            return Err(LinuxError::EAGAIN);
+        }
+        let Some(mut waiters) = table.remove(&key) else {
+            return Ok(0);
+        };
+        while woken < count {
+            let Some(waiter) = waiters.pop_front() else {
+                break;
+            };
+            waiter.woken.store(true, Ordering::Release);
+            woken += 1;
+        }
+        let moved = waiters.len().min(requeue_count);
+        if moved > 0 {
+            table
+                .entry(new_key)
+                .or_default()
+                .extend(waiters.drain(..moved));
+            requeued = moved;
+        }
+        if !waiters.is_empty() {
+            table.entry(key).or_default().extend(waiters);
+        }
+    }
+    if woken > 0 {
+        FUTEX_WQ.notify_all(false);
+    }
+    Ok(woken + requeued)
+}
+
+/// Wakes up all futex waiters so that those in exiting processes can return.
+pub(crate) fn interrupt_waiters() {
+    FUTEX_WQ.notify_all(false);
+}
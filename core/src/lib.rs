@@ -4,7 +4,17 @@
 extern crate axlog;
 extern crate alloc;
 
+pub mod auxv;
+pub mod cred;
 pub mod ctypes;
 pub mod entry;
+pub mod futex;
 pub mod mm;
+pub mod process;
+pub mod resource;
+pub mod sigframe;
+pub mod signal;
 pub mod task;
+pub mod time;
+pub mod timer;
+pub mod vdso;
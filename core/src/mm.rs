@@ -1,16 +1,34 @@
 use core::ffi::CStr;
 
-use alloc::{string::String, vec};
-use axerrno::{AxError, AxResult};
+use alloc::{
+    boxed::Box,
+    collections::btree_map::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use arceos_posix_api::{FilePath, HARDLINK_MANAGER, ctypes::RLIMIT_STACK};
+use axerrno::{AxError, AxResult, LinuxError, LinuxResult};
+use axfs::fops::{File, OpenOptions};
 use axhal::{
     paging::MappingFlags,
     trap::{PAGE_FAULT, register_trap_handler},
 };
-use axmm::{AddrSpace, kernel_aspace};
+use axmm::{AddrSpace, FileSource, PageCache, kernel_aspace};
+use axsync::Mutex;
 use axtask::TaskExtRef;
-use kernel_elf_parser::{AuxvEntry, ELFParser, app_stack_region};
-use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr};
-use xmas_elf::{ElfFile, program::SegmentData};
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr, VirtAddrRange};
+use xmas_elf::{ElfFile, header, program};
+
+use crate::{
+    auxv::{ProgramInfo, auxv_vector, init_stack},
+    cred::{AccessMode, Credentials, may_open},
+    ctypes::WaitStatus,
+    resource::Rlimits,
+    signal::{SEGV_ACCERR, SEGV_MAPERR, SIGSEGV, SigInfo, force_signal},
+    vdso::map_vdso,
+};
 
 pub fn new_user_aspace_empty() -> AxResult<AddrSpace> {
     AddrSpace::new_empty(
@@ -31,137 +49,646 @@ pub fn copy_from_kernel(aspace: &mut AddrSpace) -> AxResult {
     Ok(())
 }
 
+/// Writes `value` to the user address `addr` in `aspace`, after checking that
+/// the memory is mapped writable. The pages are populated if necessary.
+pub fn write_user_value<T: Copy>(aspace: &mut AddrSpace, addr: usize, value: T) -> AxResult {
+    let start = VirtAddr::from_usize(addr);
+    let size = size_of::<T>();
+    if addr % align_of::<T>() != 0
+        || addr.checked_add(size).is_none()
+        || !aspace.check_region_access(
+            VirtAddrRange::from_start_size(start, size),
+            MappingFlags::WRITE,
+        )
+    {
+        return Err(AxError::BadAddress);
+    }
+    let page_start = start.align_down_4k();
+    let page_end = (start + size).align_up_4k();
+    aspace.populate_area(page_start, page_end - page_start)?;
+    let bytes = unsafe { core::slice::from_raw_parts(&value as *const T as *const u8, size) };
+    aspace.write(start, bytes)
+}
+
+/// Reads a value from the user address `addr` in `aspace`, after checking
+/// that the memory is mapped readable. The pages are populated if necessary.
+///
+/// # Safety
+///
+/// Any bit pattern read must be a valid `T`.
+pub unsafe fn read_user_value<T: Copy>(aspace: &mut AddrSpace, addr: usize) -> AxResult<T> {
+    let start = VirtAddr::from_usize(addr);
+    let size = size_of::<T>();
+    if addr % align_of::<T>() != 0
+        || addr.checked_add(size).is_none()
+        || !aspace.check_region_access(
+            VirtAddrRange::from_start_size(start, size),
+            MappingFlags::READ,
+        )
+    {
+        return Err(AxError::BadAddress);
+    }
+    let page_start = start.align_down_4k();
+    let page_end = (start + size).align_up_4k();
+    aspace.populate_area(page_start, page_end - page_start)?;
+    let mut value = core::mem::MaybeUninit::<T>::zeroed();
+    let bytes = unsafe { core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size) };
+    aspace.read(start, bytes)?;
+    Ok(unsafe { value.assume_init() })
+}
+
+/// An executable file, read by the page cache of its mappings.
+struct ExecFile(File);
+
+impl FileSource for ExecFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        self.0.read_at(offset, buf)
+    }
+}
+
+/// The executables in use and the open files that can write to them, by
+/// [`exec_cache_key`].
+///
+/// A file cannot be opened for writing while it is mapped as an executable,
+/// and cannot be executed while it is open for writing, so the pages read on
+/// demand always belong to the program that was loaded.
+struct ExecFiles {
+    /// The page caches of the executables in use, so that the processes
+    /// running the same program share its read-only pages. A cache is dropped
+    /// with the last mapping of it.
+    ///
+    /// An entry is removed by [`invalidate_exec_cache`] when the file is
+    /// removed, and is only reused if the size of the file has not changed
+    /// since it was created.
+    caches: BTreeMap<String, (u64, Weak<PageCache>)>,
+    /// The open files that can write to each file.
+    writers: BTreeMap<String, Vec<Weak<arceos_posix_api::File>>>,
+}
+
+impl ExecFiles {
+    /// Returns whether the file with `key` is mapped as an executable.
+    fn is_mapped(&self, key: &str) -> bool {
+        self.caches
+            .get(key)
+            .is_some_and(|(_, cache)| cache.strong_count() > 0)
+    }
+
+    /// Returns whether the file with `key` is open for writing.
+    fn is_written(&self, key: &str) -> bool {
+        self.writers
+            .get(key)
+            .is_some_and(|files| files.iter().any(|file| file.strong_count() > 0))
+    }
+}
+
+static EXEC_FILES: Mutex<ExecFiles> = Mutex::new(ExecFiles {
+    caches: BTreeMap::new(),
+    writers: BTreeMap::new(),
+});
+
+/// Returns the key of the file at `path` in [`EXEC_FILES`], which is the
+/// canonical path of the file that hard links to it refer to.
+fn exec_cache_key(path: &str) -> AxResult<String> {
+    let path = axfs::api::canonicalize(path)?;
+    Ok(HARDLINK_MANAGER.real_path(&path))
+}
+
+/// Forgets the cached pages of the file at `path` before it is removed, so
+/// that a new file at the same path is read when it is executed next. Running
+/// programs keep the pages they have mapped.
+pub fn invalidate_exec_cache(path: &str) {
+    if let Ok(key) = exec_cache_key(path) {
+        EXEC_FILES.lock().caches.remove(&key);
+    }
+}
+
+/// Checks that the file at `path` may be opened for writing or truncated.
+///
+/// Returns `ETXTBSY` if it is mapped as an executable.
+pub fn deny_exec_write(path: &str) -> LinuxResult {
+    let key = exec_cache_key(path)?;
+    if EXEC_FILES.lock().is_mapped(&key) {
+        return Err(LinuxError::ETXTBSY);
+    }
+    Ok(())
+}
+
+/// Records `file`, opened for writing at `path`, so that the file cannot be
+/// executed until `file` is closed.
+///
+/// Returns `ETXTBSY` if the file has been mapped as an executable since it
+/// was checked by [`deny_exec_write`].
+pub fn add_exec_writer(path: &str, file: &Arc<arceos_posix_api::File>) -> LinuxResult {
+    let key = exec_cache_key(path)?;
+    let mut files = EXEC_FILES.lock();
+    if files.is_mapped(&key) {
+        return Err(LinuxError::ETXTBSY);
+    }
+    files.writers.retain(|_, writers| {
+        writers.retain(|writer| writer.strong_count() > 0);
+        !writers.is_empty()
+    });
+    files
+        .writers
+        .entry(key)
+        .or_default()
+        .push(Arc::downgrade(file));
+    Ok(())
+}
+
+/// The maximum size of the headers read from an executable, beyond which it
+/// is rejected with `ENOEXEC`.
+const MAX_HEADERS_SIZE: usize = 64 * 1024;
+
+/// An opened executable.
+struct Executable {
+    /// The opened file.
+    file: File,
+    /// The pages of the file.
+    cache: Arc<PageCache>,
+    /// The size of the file.
+    size: u64,
+    /// The beginning of the file, which holds the ELF header and the program
+    /// headers, or the `#!` line of a script.
+    head: Vec<u8>,
+}
+
+/// Reads up to `len` bytes of `file` at `offset`, fewer at the end of the
+/// file.
+fn read_file_at(file: &File, offset: u64, len: usize) -> LinuxResult<Vec<u8>> {
+    let mut buf = vec![0; len];
+    let mut read = 0;
+    while read < len {
+        match file.read_at(offset + read as u64, &mut buf[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    buf.truncate(read);
+    Ok(buf)
+}
+
+/// Opens the executable at `path`, reading only its headers. The rest of the
+/// file is read on demand, when the pages are accessed.
+///
+/// `cred` must have execute permission on the file.
+fn open_executable(path: &str, cred: &Credentials) -> LinuxResult<Executable> {
+    // Only regular files can be executed.
+    if !axfs::api::metadata(path)?.is_file() {
+        return Err(LinuxError::EACCES);
+    }
+    may_open(cred, &FilePath::new(path)?, AccessMode::EXEC)?;
+    let mut opts = OpenOptions::new();
+    opts.read(true);
+    let file = File::open(path, &opts)?;
+    let size = file.get_attr()?.size();
+
+    let mut head = read_file_at(&file, 0, PAGE_SIZE_4K)?;
+    if let Ok(elf) = ElfFile::new(&head) {
+        // The program headers are parsed with the layout of the ELF class.
+        let ph_entry_size = match elf.header.pt1.class() {
+            header::Class::ThirtyTwo => size_of::<program::ProgramHeader32>(),
+            header::Class::SixtyFour => size_of::<program::ProgramHeader64>(),
+            _ => return Err(LinuxError::ENOEXEC),
+        };
+        if elf.header.pt2.ph_entry_size() as usize != ph_entry_size {
+            return Err(LinuxError::ENOEXEC);
+        }
+        // Read the program headers if they are not in the first page.
+        let ph_end = ph_entry_size
+            .checked_mul(elf.header.pt2.ph_count() as usize)
+            .and_then(|size| size.checked_add(elf.header.pt2.ph_offset() as usize))
+            .filter(|&end| end <= MAX_HEADERS_SIZE)
+            .ok_or(LinuxError::ENOEXEC)?;
+        if ph_end > head.len() {
+            head = read_file_at(&file, 0, ph_end)?;
+            // The file ends within the program headers.
+            if head.len() < ph_end {
+                return Err(LinuxError::ENOEXEC);
+            }
+        }
+    }
+
+    let path = exec_cache_key(path)?;
+    let mut files = EXEC_FILES.lock();
+    if files.is_written(&path) {
+        return Err(LinuxError::ETXTBSY);
+    }
+    let caches = &mut files.caches;
+    caches.retain(|_, (_, cache)| cache.strong_count() > 0);
+    let cache = match caches.get(&path) {
+        Some((cached_size, cache)) if *cached_size == size => cache.upgrade(),
+        _ => None,
+    };
+    let cache = match cache {
+        Some(cache) => cache,
+        None => {
+            let source = ExecFile(File::open(&path, &opts)?);
+            let cache = Arc::new(PageCache::new(Box::new(source)));
+            caches.insert(path, (size, Arc::downgrade(&cache)));
+            cache
+        }
+    };
+    Ok(Executable {
+        file,
+        cache,
+        size,
+        head,
+    })
+}
+
+/// An ELF file mapped into a user address space.
+struct LoadedElf {
+    /// The entry point, with the load bias applied.
+    entry: VirtAddr,
+    /// The lowest address of the mapped segments.
+    base: usize,
+    /// The address of the program headers in memory, or 0 if they are not
+    /// mapped.
+    phdr: usize,
+}
+
 /// Map the elf file to the user address space.
 ///
 /// # Arguments
 /// - `uspace`: The address space of the user app.
-/// - `elf`: The elf file.
+/// - `elf`: The elf file, parsed from the headers of `exe`.
+/// - `exe`: The executable, whose pages are mapped on demand.
+/// - `load_base`: Where to load a position-independent (`ET_DYN`) file.
+///   Other files are loaded at the addresses they are linked at.
 ///
 /// # Returns
-/// - The entry point of the user app.
-fn map_elf(uspace: &mut AddrSpace, elf: &ElfFile) -> AxResult<(VirtAddr, [AuxvEntry; 16])> {
-    let uspace_base = uspace.base().as_usize();
-    let elf_parser = ELFParser::new(
-        elf,
-        axconfig::plat::USER_INTERP_BASE,
-        Some(uspace_base as isize),
-        uspace_base,
-    )
-    .map_err(|_| AxError::InvalidData)?;
+/// - The entry point, the load base and the program header address.
+///
+/// Malformed ELF files are rejected with `ENOEXEC`.
+fn map_elf(
+    uspace: &mut AddrSpace,
+    elf: &ElfFile,
+    exe: &Executable,
+    load_base: usize,
+) -> LinuxResult<LoadedElf> {
+    let min_vaddr = elf
+        .program_iter()
+        .filter(|ph| ph.get_type() == Ok(program::Type::Load))
+        .map(|ph| ph.virtual_addr() as usize)
+        .min()
+        .ok_or(LinuxError::ENOEXEC)?;
+    let bias = match elf.header.pt2.type_().as_type() {
+        header::Type::Executable => 0,
+        header::Type::SharedObject => {
+            load_base.wrapping_sub(VirtAddr::from_usize(min_vaddr).align_down_4k().as_usize())
+        }
+        _ => return Err(LinuxError::ENOEXEC),
+    };
 
-    for segement in elf_parser.ph_load() {
+    let ph_offset = elf.header.pt2.ph_offset() as usize;
+    let mut phdr = 0;
+    for ph in elf.program_iter() {
+        let vaddr = (ph.virtual_addr() as usize).wrapping_add(bias);
+        let offset = ph.offset() as usize;
+        let filesz = ph.file_size() as usize;
+        match ph.get_type() {
+            Ok(program::Type::Load) => {}
+            Ok(program::Type::Phdr) => {
+                phdr = vaddr;
+                continue;
+            }
+            _ => continue,
+        }
+        let file_end = offset
+            .checked_add(filesz)
+            .filter(|&end| end as u64 <= exe.size)
+            .ok_or(LinuxError::ENOEXEC)?;
+        // Without `PT_PHDR`, find the program headers in the loaded segments.
+        if phdr == 0 && (offset..file_end).contains(&ph_offset) {
+            phdr = vaddr + (ph_offset - offset);
+        }
+
+        let vaddr = VirtAddr::from_usize(vaddr);
+        let memsz = ph.mem_size() as usize;
+        let mut flags = MappingFlags::USER;
+        if ph.flags().is_read() {
+            flags |= MappingFlags::READ;
+        }
+        if ph.flags().is_write() {
+            flags |= MappingFlags::WRITE;
+        }
+        if ph.flags().is_execute() {
+            flags |= MappingFlags::EXECUTE;
+        }
         debug!(
             "Mapping ELF segment: [{:#x?}, {:#x?}) flags: {:#x?}",
-            segement.vaddr,
-            segement.vaddr + segement.memsz as usize,
-            segement.flags
+            vaddr,
+            vaddr + memsz,
+            flags
         );
-        let seg_pad = segement.vaddr.align_offset_4k();
-        assert_eq!(seg_pad, segement.offset % PAGE_SIZE_4K);
-
-        let seg_align_size =
-            (segement.memsz as usize + seg_pad + PAGE_SIZE_4K - 1) & !(PAGE_SIZE_4K - 1);
-        uspace.map_alloc(
-            segement.vaddr.align_down_4k(),
-            seg_align_size,
-            segement.flags,
-            true,
-        )?;
-        let seg_data = elf
-            .input
-            .get(segement.offset..segement.offset + segement.filesz as usize)
-            .ok_or(AxError::InvalidData)?;
-        uspace.write(segement.vaddr, seg_data)?;
-        // TDOO: flush the I-cache
+        let seg_pad = vaddr.align_offset_4k();
+        if seg_pad != offset % PAGE_SIZE_4K || filesz > memsz {
+            return Err(LinuxError::ENOEXEC);
+        }
+
+        // The pages are read from the file on the first access, and the part
+        // after the file contents (.bss) is zero-filled.
+        let seg_align_size = (memsz + seg_pad + PAGE_SIZE_4K - 1) & !(PAGE_SIZE_4K - 1);
+        uspace
+            .map_file(
+                vaddr.align_down_4k(),
+                seg_align_size,
+                flags,
+                exe.cache.clone(),
+                vaddr.as_usize().wrapping_sub(offset),
+                vaddr + filesz,
+            )
+            .map_err(|err| match err {
+                AxError::NoMemory => LinuxError::ENOMEM,
+                // The segment overlaps another one or lies outside user space.
+                _ => LinuxError::ENOEXEC,
+            })?;
+    }
+
+    Ok(LoadedElf {
+        entry: VirtAddr::from_usize((elf.header.pt2.entry_point() as usize).wrapping_add(bias)),
+        base: min_vaddr.wrapping_add(bias),
+        phdr,
+    })
+}
+
+/// The maximum number of links followed when resolving the path of a
+/// dynamic linker, beyond which it fails with `ELOOP`.
+const MAX_SYMLINKS: usize = 40;
+/// The maximum length of a path, including the terminating NUL. A longer
+/// `PT_INTERP` is not read, and the program fails with `ENOEXEC`.
+const PATH_MAX: usize = 4096;
+
+/// Interpreter aliases used when the image does not provide a dynamic linker
+/// at the path recorded in `PT_INTERP`. The test images keep them under
+/// `/musl/lib` and `/glibc/lib` instead of `/lib`.
+const DEFAULT_INTERP_ALIASES: &[(&str, &str)] = &[
+    ("/lib/ld-musl-x86_64.so.1", "/musl/lib/libc.so"),
+    ("/lib/ld-musl-aarch64.so.1", "/musl/lib/libc.so"),
+    ("/lib/ld-musl-riscv64.so.1", "/musl/lib/libc.so"),
+    ("/lib/ld-musl-loongarch64.so.1", "/musl/lib/libc.so"),
+    (
+        "/lib64/ld-linux-x86-64.so.2",
+        "/glibc/lib/ld-linux-x86-64.so.2",
+    ),
+    (
+        "/lib/ld-linux-aarch64.so.1",
+        "/glibc/lib/ld-linux-aarch64.so.1",
+    ),
+    (
+        "/lib/ld-linux-riscv64-lp64d.so.1",
+        "/glibc/lib/ld-linux-riscv64-lp64d.so.1",
+    ),
+    (
+        "/lib64/ld-linux-loongarch-lp64d.so.1",
+        "/glibc/lib/ld-linux-loongarch-lp64d.so.1",
+    ),
+];
+
+/// Interpreter aliases set by [`set_interp_alias`], which take precedence
+/// over [`DEFAULT_INTERP_ALIASES`].
+static INTERP_ALIASES: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// Makes programs whose `PT_INTERP` is `interp` use the dynamic linker at
+/// `target` if `interp` does not exist. Passing `None` removes the alias.
+///
+/// The aliases in the `AX_INTERP_ALIASES` build-time variable are set at boot.
+pub fn set_interp_alias(interp: &str, target: Option<&str>) {
+    let mut aliases = INTERP_ALIASES.lock();
+    match target {
+        Some(target) => aliases.insert(String::from(interp), String::from(target)),
+        None => aliases.remove(interp),
+    };
+}
+
+fn interp_alias(interp: &str) -> Option<String> {
+    INTERP_ALIASES.lock().get(interp).cloned().or_else(|| {
+        DEFAULT_INTERP_ALIASES
+            .iter()
+            .find(|(from, _)| *from == interp)
+            .map(|(_, to)| String::from(*to))
+    })
+}
+
+/// Resolves the `PT_INTERP` path of a program to a file in the VFS,
+/// following links and falling back to the interpreter aliases.
+fn resolve_interp(interp: &str) -> LinuxResult<String> {
+    let mut path = axfs::api::canonicalize(interp)?;
+    for _ in 0..MAX_SYMLINKS {
+        let real_path = HARDLINK_MANAGER.real_path(&path);
+        if real_path != path {
+            path = axfs::api::canonicalize(&real_path)?;
+            continue;
+        }
+        if axfs::api::absolute_path_exists(&path) {
+            return Ok(path);
+        }
+        match interp_alias(&path) {
+            Some(alias) => path = axfs::api::canonicalize(&alias)?,
+            None => return Err(LinuxError::ENOENT),
+        }
     }
+    Err(LinuxError::ELOOP)
+}
+
+/// The maximum length of the `#!` line of a script that is parsed.
+const BINPRM_BUF_SIZE: usize = 256;
+/// The maximum depth of scripts run by interpreters that are scripts
+/// themselves, beyond which `execve` fails with `ELOOP`.
+const MAX_INTERP_DEPTH: usize = 5;
+
+/// Parses the `#!interpreter [arg]` line at the beginning of a script.
+///
+/// Everything after the interpreter path is passed as a single argument,
+/// spaces included, with surrounding blanks trimmed. A line longer than
+/// [`BINPRM_BUF_SIZE`] is truncated, unless the interpreter path itself
+/// would be cut off.
+fn parse_shebang(data: &[u8]) -> LinuxResult<(String, Option<String>)> {
+    let is_blank = |b: &u8| *b == b' ' || *b == b'\t';
+    let is_terminator = |b: &u8| is_blank(b) || *b == b'\0';
 
-    Ok((
-        elf_parser.entry().into(),
-        elf_parser.auxv_vector(PAGE_SIZE_4K),
-    ))
+    let buf = &data[2..data.len().min(BINPRM_BUF_SIZE)];
+    let newline = buf.iter().position(|&b| b == b'\n');
+    let truncated = newline.is_none() && data.len() > BINPRM_BUF_SIZE;
+    let line = &buf[..newline.unwrap_or(buf.len())];
+    let line = match line.iter().position(|b| !is_blank(b)) {
+        Some(start) => &line[start..],
+        None => return Err(LinuxError::ENOEXEC),
+    };
+    let interp_end = line.iter().position(is_terminator);
+    if interp_end.is_none() && truncated {
+        // The interpreter path does not fit in the buffer.
+        return Err(LinuxError::ENOEXEC);
+    }
+    let (interp, rest) = line.split_at(interp_end.unwrap_or(line.len()));
+    if interp.is_empty() {
+        return Err(LinuxError::ENOEXEC);
+    }
+    // The argument ends at the first NUL, like a C string.
+    let rest = rest.split(|&b| b == b'\0').next().unwrap_or(rest);
+    let arg = match (
+        rest.iter().position(|b| !is_blank(b)),
+        rest.iter().rposition(|b| !is_blank(b)),
+    ) {
+        (Some(start), Some(end)) => Some(&rest[start..=end]),
+        _ => None,
+    };
+
+    let to_string = |bytes: &[u8]| {
+        core::str::from_utf8(bytes)
+            .map(String::from)
+            .map_err(|_| LinuxError::ENOEXEC)
+    };
+    Ok((to_string(interp)?, arg.map(to_string).transpose()?))
+}
+
+/// Returns the size of the user stack allowed by `RLIMIT_STACK`.
+///
+/// The stack does not grow, so it is mapped with the size of the soft limit
+/// at once, within the region reserved for it.
+fn user_stack_size(rlimits: &Rlimits) -> usize {
+    let size = rlimits
+        .get(RLIMIT_STACK)
+        .rlim_cur
+        .min(axconfig::plat::USER_STACK_SIZE as u64) as usize;
+    memory_addr::align_up_4k(size).max(PAGE_SIZE_4K)
 }
 
 /// Load the user app to the user address space.
 ///
+/// The app is either an ELF file, or a script starting with
+/// `#!interpreter [arg]`, in which case the interpreter is loaded with the
+/// arguments `interpreter [arg] path args[1..]`. The dynamic linker of an
+/// ELF file is loaded at `USER_INTERP_BASE` next to it.
+///
 /// # Arguments
 /// - `uspace`: The address space of the user app.
-/// - `args`: The arguments of the user app. The first argument is the path of the user app.
+/// - `path`: The path of the user app.
+/// - `args`: The arguments of the user app.
 /// - `envs`: The environment variables of the user app.
+/// - `cred`: The credentials the user app runs with, which must have execute
+///   permission on the files.
+/// - `rlimits`: The resource limits of the process. `RLIMIT_STACK` bounds the
+///   size of the user stack.
 ///
 /// # Returns
 /// - The entry point of the user app.
 /// - The stack pointer of the user app.
 pub fn load_user_app(
     uspace: &mut AddrSpace,
+    path: &str,
     args: &[String],
     envs: &[String],
-) -> AxResult<(VirtAddr, VirtAddr)> {
-    if args.is_empty() {
-        return Err(AxError::InvalidInput);
-    }
-    let file_data = axfs::api::read(args[0].as_str())?;
-    let elf = ElfFile::new(&file_data).map_err(|_| AxError::InvalidData)?;
+    cred: &Credentials,
+    rlimits: &Rlimits,
+) -> LinuxResult<(VirtAddr, VirtAddr)> {
+    // `execfn` is the path the first program was executed with.
+    let execfn = path;
+    let mut path = String::from(path);
+    let mut args = args.to_vec();
+    let mut depth = 0;
+    let exe = loop {
+        let exe = open_executable(&path, cred)?;
+        if !exe.head.starts_with(b"#!") {
+            break exe;
+        }
+        depth += 1;
+        if depth > MAX_INTERP_DEPTH {
+            return Err(LinuxError::ELOOP);
+        }
+        let (interp, arg) = parse_shebang(&exe.head)?;
+        // The original `argv[0]` is dropped, and the interpreter gets the
+        // path of the script instead, so that it can open it.
+        let mut new_args = vec![interp.clone()];
+        new_args.extend(arg);
+        new_args.push(path);
+        new_args.extend_from_slice(args.get(1..).unwrap_or_default());
+        path = interp;
+        args = new_args;
+    };
+
+    let elf = ElfFile::new(&exe.head).map_err(|_| LinuxError::ENOEXEC)?;
+    let loaded = map_elf(uspace, &elf, &exe, uspace.base().as_usize())?;
 
-    if let Some(interp) = elf
+    // A dynamically linked program is started by its interpreter, which
+    // finds the program through the auxiliary vector.
+    let (entry, interp_base) = match elf
         .program_iter()
-        .find(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Interp))
+        .find(|ph| ph.get_type() == Ok(program::Type::Interp))
     {
-        let interp = match interp.get_data(&elf) {
-            Ok(SegmentData::Undefined(data)) => data,
-            _ => panic!("Invalid data in Interp Elf Program Header"),
-        };
-
-        let mut interp_path = axfs::api::canonicalize(
-            CStr::from_bytes_with_nul(interp)
-                .map_err(|_| AxError::InvalidData)?
+        Some(interp) => {
+            let len = interp.file_size() as usize;
+            if len > PATH_MAX {
+                return Err(LinuxError::ENOEXEC);
+            }
+            let interp = read_file_at(&exe.file, interp.offset(), len)?;
+            let interp = CStr::from_bytes_until_nul(&interp)
+                .map_err(|_| LinuxError::ENOEXEC)?
                 .to_str()
-                .map_err(|_| AxError::InvalidData)?,
-        )?;
-
-        if interp_path == "/lib/ld-linux-riscv64-lp64.so.1"
-            || interp_path == "/lib64/ld-linux-loongarch-lp64d.so.1"
-            || interp_path == "/lib64/ld-linux-x86-64.so.2"
-            || interp_path == "/lib/ld-linux-aarch64.so.1"
-        {
-            // TODO: Use soft link
-            interp_path = String::from("/musl/lib/libc.so");
+                .map_err(|_| LinuxError::ENOEXEC)?;
+            let interp_path = resolve_interp(interp)?;
+            debug!("Loading interpreter {} for {}", interp_path, path);
+            let interp_exe = open_executable(&interp_path, cred)?;
+            let interp_elf = ElfFile::new(&interp_exe.head).map_err(|_| LinuxError::ENOEXEC)?;
+            let interp = map_elf(
+                uspace,
+                &interp_elf,
+                &interp_exe,
+                axconfig::plat::USER_INTERP_BASE,
+            )?;
+            (interp.entry, interp.base)
         }
+        None => (loaded.entry, 0),
+    };
 
-        // Set the first argument to the path of the user app.
-        let mut new_args = vec![interp_path];
-        new_args.extend_from_slice(args);
-        return load_user_app(uspace, &new_args, envs);
-    }
-
-    let (entry, mut auxv) = map_elf(uspace, &elf)?;
+    let vdso_base = map_vdso(uspace)?;
+    let auxv = auxv_vector(
+        &ProgramInfo {
+            phdr: loaded.phdr,
+            phent: elf.header.pt2.ph_entry_size() as usize,
+            phnum: elf.header.pt2.ph_count() as usize,
+            entry: loaded.entry.as_usize(),
+            interp_base,
+            vdso_base,
+        },
+        cred,
+    );
     // The user stack is divided into two parts:
     // `ustack_start` -> `ustack_pointer`: It is the stack space that users actually read and write.
     // `ustack_pointer` -> `ustack_end`: It is the space that contains the arguments, environment variables and auxv passed to the app.
     //  When the app starts running, the stack pointer points to `ustack_pointer`.
     let ustack_end = VirtAddr::from_usize(axconfig::plat::USER_STACK_TOP);
-    let ustack_size = axconfig::plat::USER_STACK_SIZE;
+    let ustack_size = user_stack_size(rlimits);
     let ustack_start = ustack_end - ustack_size;
     debug!(
         "Mapping user stack: {:#x?} -> {:#x?}",
         ustack_start, ustack_end
     );
 
-    let stack_data = app_stack_region(args, envs, &mut auxv, ustack_start, ustack_size);
-    uspace.map_alloc(
-        ustack_start,
-        ustack_size,
-        MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
-        true,
-    )?;
+    let stack_data = init_stack(&args, envs, execfn, auxv, ustack_end.as_usize());
+    if stack_data.len() > ustack_size {
+        return Err(LinuxError::E2BIG);
+    }
+    uspace
+        .map_alloc(
+            ustack_start,
+            ustack_size,
+            MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
+            true,
+        )
+        .map_err(|_| LinuxError::ENOMEM)?;
 
     let heap_start = VirtAddr::from_usize(axconfig::plat::USER_HEAP_BASE);
     let heap_size = axconfig::plat::USER_HEAP_SIZE;
-    uspace.map_alloc(
-        heap_start,
-        heap_size,
-        MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
-        true,
-    )?;
+    uspace
+        .map_alloc(
+            heap_start,
+            heap_size,
+            MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
+            true,
+        )
+        .map_err(|_| LinuxError::ENOMEM)?;
 
     let user_sp = ustack_end - stack_data.len();
 
@@ -194,18 +721,28 @@ fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool)
         return false;
     }
 
-    if !axtask::current()
-        .task_ext()
-        .aspace
-        .lock()
-        .handle_page_fault(vaddr, access_flags)
-    {
-        warn!(
-            "{}: segmentation fault at {:#x}, exit!",
-            axtask::current().id_name(),
-            vaddr
+    let curr = axtask::current();
+    let mut aspace = curr.task_ext().aspace.lock();
+    let major = aspace.is_major_fault(vaddr);
+    if !aspace.handle_page_fault(vaddr, access_flags) {
+        let mapped = aspace.check_region_access(
+            VirtAddrRange::from_start_size(vaddr, 1),
+            MappingFlags::empty(),
         );
-        axtask::exit(-1);
+        drop(aspace);
+        warn!("{}: segmentation fault at {:#x}", curr.id_name(), vaddr);
+        if !is_user {
+            crate::task::exit_group(WaitStatus::Signaled {
+                signo: SIGSEGV,
+                core_dumped: false,
+            });
+        }
+        // The signal is handled before returning to user space.
+        let code = if mapped { SEGV_ACCERR } else { SEGV_MAPERR };
+        force_signal(SigInfo::fault(SIGSEGV, code, vaddr.as_usize()));
+        return true;
     }
+    drop(aspace);
+    curr.task_ext().count_page_fault(major);
     true
 }
@@ -0,0 +1,846 @@
+//! Process (thread group) management.
+//!
+//! A process is a group of threads sharing the same thread group ID (TGID),
+//! which is the user-visible PID. Every thread holds an [`Arc`] to the
+//! [`ProcessData`] of the group it belongs to.
+//!
+//! PIDs and TIDs are allocated from the same number space. All live processes
+//! and threads are registered in global tables so that they can be looked up
+//! by their user-visible IDs.
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+use arceos_posix_api::ctypes::RLIMIT_CPU;
+use axerrno::{AxError, AxResult};
+use axhal::time::nanos_to_ticks;
+use axsync::Mutex;
+use axtask::{AxTaskRef, TaskExtRef, TaskInner, WaitQueue, WeakAxTaskRef};
+
+use crate::{
+    ctypes::{WaitFlags, WaitStatus},
+    resource::{ResourceUsage, Rlimit, Rlimits, process_usage, total_usage},
+    signal::{
+        PendingSignals, SIG_IGN, SIGCHLD, SIGKILL, SharedSignalActions, SigActionFlags, SigInfo,
+        has_pending_signal, send_signal_info, sig_bit,
+    },
+    timer::{ITimers, PosixTimers},
+};
+
+/// The default value of `pid_max`, which keeps PIDs within 15 bits.
+pub const PID_MAX_DEFAULT: usize = 0x8000;
+/// The upper bound of `pid_max`.
+pub const PID_MAX_LIMIT: usize = 0x40_0000;
+/// PIDs below this value are not reused after `pid_max` wraps around.
+const RESERVED_PIDS: usize = 300;
+/// The PID of the init process, which adopts orphaned processes.
+pub const INIT_PID: usize = 1;
+
+/// Allocator of PIDs and TIDs.
+struct PidAllocator {
+    last_pid: usize,
+    pid_max: usize,
+    used: BTreeSet<usize>,
+}
+
+impl PidAllocator {
+    const fn new() -> Self {
+        Self {
+            last_pid: 0,
+            pid_max: PID_MAX_DEFAULT,
+            used: BTreeSet::new(),
+        }
+    }
+
+    fn alloc(&mut self) -> Option<usize> {
+        let mut pid = self.last_pid;
+        for _ in 0..self.pid_max {
+            pid += 1;
+            if pid >= self.pid_max {
+                pid = RESERVED_PIDS;
+            }
+            if self.used.insert(pid) {
+                self.last_pid = pid;
+                return Some(pid);
+            }
+        }
+        None
+    }
+
+    fn alloc_at(&mut self, pid: usize) -> AxResult<usize> {
+        if pid == 0 || pid >= self.pid_max {
+            return Err(AxError::InvalidInput);
+        }
+        if !self.used.insert(pid) {
+            return Err(AxError::AlreadyExists);
+        }
+        Ok(pid)
+    }
+
+    fn free(&mut self, pid: usize) {
+        self.used.remove(&pid);
+    }
+}
+
+static PID_ALLOCATOR: Mutex<PidAllocator> = Mutex::new(PidAllocator::new());
+static PROCESS_TABLE: Mutex<BTreeMap<usize, Weak<ProcessData>>> = Mutex::new(BTreeMap::new());
+static THREAD_TABLE: Mutex<BTreeMap<usize, WeakAxTaskRef>> = Mutex::new(BTreeMap::new());
+
+/// Allocates a new PID (or TID).
+///
+/// Returns [`AxError::WouldBlock`] if all PIDs below `pid_max` are in use.
+pub fn alloc_pid() -> AxResult<usize> {
+    PID_ALLOCATOR.lock().alloc().ok_or(AxError::WouldBlock)
+}
+
+/// Allocates the given PID (or TID), as requested by the `set_tid` argument
+/// of `clone3`.
+///
+/// Returns [`AxError::InvalidInput`] if `pid` is out of range, or
+/// [`AxError::AlreadyExists`] if it is in use.
+pub fn alloc_pid_at(pid: usize) -> AxResult<usize> {
+    PID_ALLOCATOR.lock().alloc_at(pid)
+}
+
+/// Releases a PID (or TID) so that it can be reused.
+pub fn free_pid(pid: usize) {
+    PID_ALLOCATOR.lock().free(pid);
+}
+
+/// Returns the current `pid_max`.
+pub fn pid_max() -> usize {
+    PID_ALLOCATOR.lock().pid_max
+}
+
+/// Sets `pid_max`, the upper bound (exclusive) of allocated PIDs. It is set
+/// at boot from the `AX_PID_MAX` build-time variable, if given.
+///
+/// PIDs already in use are not affected.
+pub fn set_pid_max(pid_max: usize) -> AxResult {
+    if !(RESERVED_PIDS + 1..=PID_MAX_LIMIT).contains(&pid_max) {
+        return Err(AxError::InvalidInput);
+    }
+    PID_ALLOCATOR.lock().pid_max = pid_max;
+    Ok(())
+}
+
+/// Finds a process by its PID.
+pub fn find_process(pid: usize) -> Option<Arc<ProcessData>> {
+    PROCESS_TABLE.lock().get(&pid).and_then(Weak::upgrade)
+}
+
+/// Finds a thread by its TID.
+pub fn find_thread(tid: usize) -> Option<AxTaskRef> {
+    THREAD_TABLE.lock().get(&tid).and_then(Weak::upgrade)
+}
+
+/// Returns all processes in the process group `pgid`.
+pub fn process_group(pgid: usize) -> Vec<Arc<ProcessData>> {
+    processes()
+        .into_iter()
+        .filter(|process| process.pgid() == pgid)
+        .collect()
+}
+
+/// Returns all processes in the system, ordered by PID.
+pub fn processes() -> Vec<Arc<ProcessData>> {
+    PROCESS_TABLE
+        .lock()
+        .values()
+        .filter_map(Weak::upgrade)
+        .collect()
+}
+
+/// Removes an exited thread from the thread table and releases its TID.
+///
+/// The TID of a group leader is the PID of the process, so it is kept until
+/// the process itself is released.
+pub(crate) fn unregister_thread(tid: usize, pid: usize) {
+    THREAD_TABLE.lock().remove(&tid);
+    if tid != pid {
+        free_pid(tid);
+    }
+}
+
+/// Moves a thread from `old_tid` to `pid` in the thread table when it
+/// becomes the leader of its process in `execve`, and releases `old_tid`.
+///
+/// The previous leader must have exited.
+pub(crate) fn take_leader_tid(task: &AxTaskRef, old_tid: usize, pid: usize) {
+    let mut threads = THREAD_TABLE.lock();
+    threads.remove(&old_tid);
+    threads.insert(pid, Arc::downgrade(task));
+    drop(threads);
+    free_pid(old_tid);
+}
+
+/// The children a wait call is waiting for.
+#[derive(Debug, Clone, Copy)]
+pub enum WaitTarget {
+    /// Any child process.
+    Any,
+    /// The child process with the given PID.
+    Pid(usize),
+    /// Any child process in the given process group.
+    Pgid(usize),
+}
+
+impl WaitTarget {
+    fn matches(&self, child: &ProcessData) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Pid(pid) => child.pid == *pid,
+            Self::Pgid(pgid) => child.pgid() == *pgid,
+        }
+    }
+}
+
+/// Data shared by all threads in the same thread group.
+pub struct ProcessData {
+    /// The process ID, which is also the thread group ID.
+    pub pid: usize,
+    /// The parent process.
+    parent: Mutex<Weak<ProcessData>>,
+    /// The process group ID.
+    pgid: AtomicUsize,
+    /// The session ID.
+    sid: AtomicUsize,
+    /// The real user ID, kept in sync with the credentials of the threads so
+    /// that it is known after they have exited.
+    uid: AtomicU32,
+    /// Whether the process has called `execve` since it was forked.
+    exec_done: AtomicBool,
+    /// Whether the process adopts orphaned descendants, set by
+    /// `PR_SET_CHILD_SUBREAPER`.
+    child_subreaper: AtomicBool,
+    /// The child processes.
+    pub children: Mutex<Vec<Arc<ProcessData>>>,
+    /// The threads in this thread group.
+    threads: Mutex<Vec<WeakAxTaskRef>>,
+    /// The signal sent to the parent when this process exits.
+    exit_signal: u32,
+    /// How the process terminated, set once all threads have exited.
+    exit_status: Mutex<Option<WaitStatus>>,
+    /// The status of the process set by `exit_group` or a fatal signal.
+    group_exit: Mutex<Option<WaitStatus>>,
+    /// Whether the threads are being torn down, either all of them because
+    /// `group_exit` is set, or all but `exec_tid`.
+    group_exiting: AtomicBool,
+    /// The thread calling `execve` while the other threads are torn down, or
+    /// 0 if there is none.
+    exec_tid: AtomicUsize,
+    /// Counts exits of threads and group exits, used to detect missed
+    /// wakeups.
+    thread_events: AtomicU64,
+    /// Threads waiting for the other threads to exit.
+    thread_wq: WaitQueue,
+    /// A stop or continue event not yet reported to the parent.
+    state_change: Mutex<Option<WaitStatus>>,
+    /// The resources used by exited threads, and the maximum resident set
+    /// size of the process.
+    exited_usage: Mutex<ResourceUsage>,
+    /// The resources used by reaped children and their reaped descendants.
+    children_usage: Mutex<ResourceUsage>,
+    /// Counts state changes of the children, used to detect missed wakeups.
+    child_events: AtomicU64,
+    /// Threads waiting for a child to change state.
+    child_wq: WaitQueue,
+    /// The signal actions, shared with other processes on `CLONE_SIGHAND`
+    /// until `execve`.
+    signal_actions: Mutex<SharedSignalActions>,
+    /// Signals sent to the process but not yet handled.
+    pending_signals: PendingSignals,
+    /// Whether the process is stopped by a signal.
+    stopped: AtomicBool,
+    /// Threads waiting for the process to be continued.
+    stop_wq: WaitQueue,
+    /// The user heap bottom
+    heap_bottom: AtomicU64,
+    /// The user heap top
+    heap_top: AtomicU64,
+    /// The resource limits.
+    rlimits: Mutex<Rlimits>,
+    /// One more than the CPU time (in seconds) at which `SIGXCPU` was last
+    /// sent, or 0 if it has not been sent.
+    xcpu_sent: AtomicU64,
+    /// The interval timers.
+    itimers: ITimers,
+    /// The POSIX timers.
+    posix_timers: PosixTimers,
+}
+
+impl ProcessData {
+    /// Creates a new process with no threads and registers it in the
+    /// process table.
+    ///
+    /// `pid` must be allocated by [`alloc_pid`], and it is released when the
+    /// process is dropped. The process joins the process group and session of
+    /// `parent`, or becomes a session leader if there is no parent.
+    pub fn new(
+        pid: usize,
+        parent: Weak<ProcessData>,
+        exit_signal: u32,
+        signal_actions: SharedSignalActions,
+        heap_bottom: u64,
+        heap_top: u64,
+        rlimits: Rlimits,
+    ) -> Arc<Self> {
+        let (pgid, sid) = parent
+            .upgrade()
+            .map_or((pid, pid), |parent| (parent.pgid(), parent.sid()));
+        let process = Arc::new(Self {
+            pid,
+            parent: Mutex::new(parent),
+            pgid: AtomicUsize::new(pgid),
+            sid: AtomicUsize::new(sid),
+            uid: AtomicU32::new(0),
+            exec_done: AtomicBool::new(false),
+            child_subreaper: AtomicBool::new(false),
+            children: Mutex::new(Vec::new()),
+            threads: Mutex::new(Vec::new()),
+            exit_signal,
+            exit_status: Mutex::new(None),
+            group_exit: Mutex::new(None),
+            group_exiting: AtomicBool::new(false),
+            exec_tid: AtomicUsize::new(0),
+            thread_events: AtomicU64::new(0),
+            thread_wq: WaitQueue::new(),
+            state_change: Mutex::new(None),
+            exited_usage: Mutex::new(ResourceUsage::default()),
+            children_usage: Mutex::new(ResourceUsage::default()),
+            child_events: AtomicU64::new(0),
+            child_wq: WaitQueue::new(),
+            signal_actions: Mutex::new(signal_actions),
+            pending_signals: PendingSignals::new(),
+            stopped: AtomicBool::new(false),
+            stop_wq: WaitQueue::new(),
+            heap_bottom: AtomicU64::new(heap_bottom),
+            heap_top: AtomicU64::new(heap_top),
+            rlimits: Mutex::new(rlimits),
+            xcpu_sent: AtomicU64::new(0),
+            itimers: ITimers::default(),
+            posix_timers: PosixTimers::default(),
+        });
+        PROCESS_TABLE.lock().insert(pid, Arc::downgrade(&process));
+        process
+    }
+
+    /// Returns the parent process, or `None` if it has no parent.
+    pub fn parent(&self) -> Option<Arc<ProcessData>> {
+        self.parent.lock().upgrade()
+    }
+
+    /// Returns the parent process ID, or 0 if it has no parent.
+    pub fn ppid(&self) -> usize {
+        self.parent().map_or(0, |parent| parent.pid)
+    }
+
+    /// Sets the parent process.
+    pub fn set_parent(&self, parent: &Arc<ProcessData>) {
+        *self.parent.lock() = Arc::downgrade(parent);
+    }
+
+    /// Returns the process group ID.
+    pub fn pgid(&self) -> usize {
+        self.pgid.load(Ordering::Acquire)
+    }
+
+    /// Moves the process into the process group `pgid`.
+    pub fn set_pgid(&self, pgid: usize) {
+        self.pgid.store(pgid, Ordering::Release);
+    }
+
+    /// Returns the session ID.
+    pub fn sid(&self) -> usize {
+        self.sid.load(Ordering::Acquire)
+    }
+
+    /// Returns `true` if the process is the leader of its session.
+    pub fn is_session_leader(&self) -> bool {
+        self.sid() == self.pid
+    }
+
+    /// Makes the process the leader of a new session and a new process
+    /// group, both with the ID of the process.
+    pub fn set_sid(&self) {
+        self.sid.store(self.pid, Ordering::Release);
+        self.pgid.store(self.pid, Ordering::Release);
+    }
+
+    /// Returns the real user ID of the process.
+    pub fn uid(&self) -> u32 {
+        self.uid.load(Ordering::Acquire)
+    }
+
+    /// Sets the real user ID of the process, when the credentials change.
+    pub(crate) fn set_uid(&self, uid: u32) {
+        self.uid.store(uid, Ordering::Release);
+    }
+
+    /// Returns `true` if the process has called `execve` since it was
+    /// forked.
+    pub fn exec_done(&self) -> bool {
+        self.exec_done.load(Ordering::Acquire)
+    }
+
+    /// Records that the process has called `execve`.
+    pub fn set_exec_done(&self) {
+        self.exec_done.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` if the process adopts orphaned descendants.
+    pub fn is_child_subreaper(&self) -> bool {
+        self.child_subreaper.load(Ordering::Acquire)
+    }
+
+    /// Sets whether the process adopts orphaned descendants.
+    pub fn set_child_subreaper(&self, subreaper: bool) {
+        self.child_subreaper.store(subreaper, Ordering::Release);
+    }
+
+    /// Returns the signal actions of the process.
+    pub fn signal_actions(&self) -> SharedSignalActions {
+        self.signal_actions.lock().clone()
+    }
+
+    /// Resets the signal actions on `execve`, as the signal handlers no
+    /// longer exist. Caught signals are reset to their default actions, and
+    /// the actions are no longer shared with other processes.
+    pub fn reset_signal_actions(&self) {
+        let mut shared = self.signal_actions.lock();
+        let mut actions = shared.lock().clone();
+        actions.reset_handlers();
+        *shared = Arc::new(Mutex::new(actions));
+    }
+
+    /// Returns the signals sent to the process but not yet handled.
+    pub fn pending_signals(&self) -> &PendingSignals {
+        &self.pending_signals
+    }
+
+    /// Returns `true` if the process is stopped by a signal.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    /// Stops the process on receipt of `signo` and reports it to the parent.
+    pub fn stop(&self, signo: u32) {
+        if !self.stopped.swap(true, Ordering::AcqRel) {
+            self.report_state_change(WaitStatus::Stopped(signo));
+        }
+    }
+
+    /// Continues the stopped process and reports it to the parent.
+    pub fn resume(&self) {
+        if self.stopped.swap(false, Ordering::AcqRel) {
+            self.stop_wq.notify_all(false);
+            self.report_state_change(WaitStatus::Continued);
+        }
+    }
+
+    /// Wakes up stopped threads so that they can handle `SIGKILL`.
+    pub fn wake_stopped(&self) {
+        self.stop_wq.notify_all(false);
+    }
+
+    /// Blocks the current thread until the process is continued or killed.
+    pub fn wait_for_resume(&self) {
+        self.stop_wq.wait_until(|| {
+            !self.is_stopped()
+                || self.is_group_exiting()
+                || self.pending_signals.get() & sig_bit(SIGKILL) != 0
+        });
+    }
+
+    /// Spawns `task` as a new thread of this thread group.
+    pub fn spawn_thread(&self, task: TaskInner) -> AxTaskRef {
+        // Hold the lock so that the new thread cannot exit before it is
+        // registered in the group.
+        let mut threads = self.threads.lock();
+        let task = axtask::spawn_task(task);
+        threads.push(Arc::downgrade(&task));
+        THREAD_TABLE
+            .lock()
+            .insert(task.task_ext().tid, Arc::downgrade(&task));
+        task
+    }
+
+    /// Returns the threads in this thread group that are still alive.
+    pub fn threads(&self) -> Vec<AxTaskRef> {
+        self.threads
+            .lock()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect()
+    }
+
+    /// Removes an exiting thread from this thread group.
+    ///
+    /// Returns `true` if it was the last thread of the group.
+    pub fn exit_thread(&self, task: &AxTaskRef) -> bool {
+        let mut threads = self.threads.lock();
+        threads.retain(|t| !core::ptr::eq(t.as_ptr(), Arc::as_ptr(task)));
+        let last = threads.is_empty();
+        drop(threads);
+        self.notify_thread_event();
+        last
+    }
+
+    /// Kills all threads of the process but the calling thread `tid`, and
+    /// waits until they have exited, as done by `execve`.
+    ///
+    /// Returns [`AxError::Interrupted`] if the process is exiting, or another
+    /// thread is already calling `execve`.
+    pub fn kill_other_threads(&self, tid: usize) -> AxResult {
+        {
+            let group_exit = self.group_exit.lock();
+            if group_exit.is_some() || self.exec_tid.load(Ordering::Acquire) != 0 {
+                return Err(AxError::Interrupted);
+            }
+            self.exec_tid.store(tid, Ordering::Release);
+            self.group_exiting.store(true, Ordering::Release);
+        }
+        self.interrupt_threads();
+
+        let result = loop {
+            let seen = self.thread_events.load(Ordering::Acquire);
+            if self.group_exit.lock().is_some() {
+                break Err(AxError::Interrupted);
+            }
+            if self.threads.lock().len() == 1 {
+                break Ok(());
+            }
+            self.thread_wq
+                .wait_until(|| self.thread_events.load(Ordering::Acquire) != seen);
+        };
+
+        let group_exit = self.group_exit.lock();
+        self.exec_tid.store(0, Ordering::Release);
+        if group_exit.is_none() {
+            self.group_exiting.store(false, Ordering::Release);
+        }
+        result
+    }
+
+    /// Returns `true` if the thread `tid` should exit because the threads of
+    /// the process are being torn down.
+    pub fn should_exit(&self, tid: usize) -> bool {
+        self.is_group_exiting() && self.exec_tid.load(Ordering::Acquire) != tid
+    }
+
+    /// Returns `true` if all threads of the process have exited.
+    pub fn is_zombie(&self) -> bool {
+        self.exit_status.lock().is_some()
+    }
+
+    /// Returns how the process terminated, or `None` if it is still alive.
+    pub fn exit_status(&self) -> Option<WaitStatus> {
+        *self.exit_status.lock()
+    }
+
+    /// Starts tearing down all threads of the process.
+    ///
+    /// Returns the status the process will exit with, which is the one given
+    /// by the first call. Other threads exit when they next return to user
+    /// space, and threads blocked in the kernel are woken up.
+    pub fn start_group_exit(&self, status: WaitStatus) -> WaitStatus {
+        let status = *self.group_exit.lock().get_or_insert(status);
+        self.group_exiting.store(true, Ordering::Release);
+        self.interrupt_threads();
+        self.notify_thread_event();
+        status
+    }
+
+    /// Wakes up the threads blocked in the kernel, so that they notice that
+    /// they should exit or handle a signal.
+    pub(crate) fn interrupt_threads(&self) {
+        self.stop_wq.notify_all(false);
+        self.child_wq.notify_all(false);
+        crate::futex::interrupt_waiters();
+    }
+
+    /// Wakes up the threads waiting for other threads to exit.
+    fn notify_thread_event(&self) {
+        self.thread_events.fetch_add(1, Ordering::AcqRel);
+        self.thread_wq.notify_all(false);
+    }
+
+    /// Returns `true` if the threads of the process are being torn down.
+    pub fn is_group_exiting(&self) -> bool {
+        self.group_exiting.load(Ordering::Acquire)
+    }
+
+    /// Returns the status set by [`ProcessData::start_group_exit`], if any.
+    pub fn group_exit_status(&self) -> Option<WaitStatus> {
+        *self.group_exit.lock()
+    }
+
+    /// Marks the process as a zombie and notifies its parent.
+    ///
+    /// The children of the process are handed over to a reaper first. If the
+    /// parent ignores `SIGCHLD`, the process is reaped at once.
+    pub fn set_zombie(&self, status: WaitStatus) {
+        self.reparent_children();
+        *self.exit_status.lock() = Some(status);
+        let Some(parent) = self.parent() else {
+            return;
+        };
+        if self.exit_signal == SIGCHLD && parent.reaps_children_automatically() {
+            parent.children.lock().retain(|child| child.pid != self.pid);
+        } else if self.exit_signal != 0 {
+            send_signal_info(&parent, self.child_info(self.exit_signal, status));
+        }
+        parent.notify_child_event();
+    }
+
+    /// Records that the process was stopped or continued and notifies its
+    /// parent.
+    pub fn report_state_change(&self, status: WaitStatus) {
+        *self.state_change.lock() = Some(status);
+        let Some(parent) = self.parent() else {
+            return;
+        };
+        let action = parent.signal_actions().lock().get(SIGCHLD);
+        if !action.flags().contains(SigActionFlags::SA_NOCLDSTOP) {
+            send_signal_info(&parent, self.child_info(SIGCHLD, status));
+        }
+        parent.notify_child_event();
+    }
+
+    /// Returns `true` if exited children are reaped without waiting, i.e.
+    /// `SIGCHLD` is ignored or has `SA_NOCLDWAIT`.
+    fn reaps_children_automatically(&self) -> bool {
+        let action = self.signal_actions().lock().get(SIGCHLD);
+        action.handler == SIG_IGN || action.flags().contains(SigActionFlags::SA_NOCLDWAIT)
+    }
+
+    /// Finds the process that adopts the orphaned children of this process:
+    /// the nearest living subreaper ancestor, or the init process.
+    fn find_reaper(&self) -> Option<Arc<ProcessData>> {
+        let mut ancestor = self.parent();
+        while let Some(process) = ancestor {
+            if process.is_child_subreaper() && !process.is_zombie() {
+                return Some(process);
+            }
+            ancestor = process.parent();
+        }
+        find_process(INIT_PID).filter(|init| init.pid != self.pid && !init.is_zombie())
+    }
+
+    /// Hands over the children of this exiting process to a reaper.
+    fn reparent_children(&self) {
+        let children = core::mem::take(&mut *self.children.lock());
+        if children.is_empty() {
+            return;
+        }
+        let Some(reaper) = self.find_reaper() else {
+            for child in &children {
+                *child.parent.lock() = Weak::new();
+            }
+            return;
+        };
+
+        let auto_reap = reaper.reaps_children_automatically();
+        let mut adopted = Vec::with_capacity(children.len());
+        for child in children {
+            child.set_parent(&reaper);
+            if let Some(status) = child.exit_status() {
+                if auto_reap {
+                    continue;
+                }
+                if child.exit_signal != 0 {
+                    send_signal_info(&reaper, child.child_info(child.exit_signal, status));
+                }
+            }
+            adopted.push(child);
+        }
+        reaper.children.lock().extend(adopted);
+        reaper.notify_child_event();
+    }
+
+    /// Returns the information of the signal `signo` sent to the parent when
+    /// the process changes state to `status`.
+    fn child_info(&self, signo: u32, status: WaitStatus) -> SigInfo {
+        let usage = process_usage(self);
+        SigInfo::child(
+            signo,
+            self.pid,
+            self.uid(),
+            status,
+            nanos_to_ticks(usage.utime_ns as u64),
+            nanos_to_ticks(usage.stime_ns as u64),
+        )
+    }
+
+    /// Returns `true` if the process is a "clone" child, which does not
+    /// notify its parent with `SIGCHLD` on exit.
+    pub fn is_clone_child(&self) -> bool {
+        self.exit_signal != SIGCHLD
+    }
+
+    /// Adds the resources used by an exited thread to the process.
+    pub fn add_thread_usage(&self, usage: &ResourceUsage) {
+        self.exited_usage.lock().add(usage);
+    }
+
+    /// Returns the resources used by exited threads of the process, with
+    /// the maximum resident set size of the process.
+    pub fn exited_usage(&self) -> ResourceUsage {
+        *self.exited_usage.lock()
+    }
+
+    /// Records a sample of the resident set size (in kilobytes) of the
+    /// process.
+    pub fn update_maxrss(&self, rss_kb: usize) {
+        let mut usage = self.exited_usage.lock();
+        usage.maxrss_kb = usage.maxrss_kb.max(rss_kb);
+    }
+
+    /// Returns the resources used by the reaped children of the process and
+    /// their reaped descendants.
+    pub fn children_usage(&self) -> ResourceUsage {
+        *self.children_usage.lock()
+    }
+
+    /// Returns the resource limits of the process.
+    pub fn rlimits(&self) -> Rlimits {
+        *self.rlimits.lock()
+    }
+
+    /// Returns the limit of `resource`, which must be less than
+    /// [`RLIM_NLIMITS`](crate::resource::RLIM_NLIMITS).
+    pub fn rlimit(&self, resource: u32) -> Rlimit {
+        self.rlimits.lock().get(resource)
+    }
+
+    /// Sets the limit of `resource`, which must be less than
+    /// [`RLIM_NLIMITS`](crate::resource::RLIM_NLIMITS).
+    pub fn set_rlimit(&self, resource: u32, limit: Rlimit) {
+        self.rlimits.lock().set(resource, limit);
+        if resource == RLIMIT_CPU {
+            self.xcpu_sent.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns whether `SIGXCPU` is due after `secs` seconds of CPU time,
+    /// which is at most once per second.
+    pub(crate) fn xcpu_due(&self, secs: u64) -> bool {
+        self.xcpu_sent.fetch_max(secs + 1, Ordering::Relaxed) <= secs
+    }
+
+    /// Returns the interval timers of the process.
+    pub fn itimers(&self) -> &ITimers {
+        &self.itimers
+    }
+
+    /// Returns the POSIX timers of the process.
+    pub fn posix_timers(&self) -> &PosixTimers {
+        &self.posix_timers
+    }
+
+    /// Wakes up the threads waiting for a child to change state.
+    fn notify_child_event(&self) {
+        self.child_events.fetch_add(1, Ordering::Release);
+        self.child_wq.notify_all(false);
+    }
+
+    /// Waits for a child matching `target` to change state.
+    ///
+    /// Returns `Ok(None)` if [`WaitFlags::WNOHANG`] is given and no child
+    /// has changed state, [`AxError::NotFound`] if no child matches, or
+    /// [`AxError::Interrupted`] if the process is exiting or a signal is
+    /// pending.
+    /// Exited children are reaped unless [`WaitFlags::WNOWAIT`] is given.
+    pub fn wait_child(
+        &self,
+        target: WaitTarget,
+        options: WaitFlags,
+    ) -> AxResult<Option<(Arc<ProcessData>, WaitStatus)>> {
+        loop {
+            let seen = self.child_events.load(Ordering::Acquire);
+            let mut children = self.children.lock();
+            let mut found = false;
+            for (index, child) in children.iter().enumerate() {
+                if !target.matches(child) {
+                    continue;
+                }
+                if !options.contains(WaitFlags::WALL)
+                    && child.is_clone_child() != options.contains(WaitFlags::WCLONE)
+                {
+                    continue;
+                }
+                found = true;
+
+                if let Some(status) = child.exit_status() {
+                    if !options.contains(WaitFlags::WEXITED) {
+                        continue;
+                    }
+                    let child = child.clone();
+                    if !options.contains(WaitFlags::WNOWAIT) {
+                        children.remove(index);
+                        self.children_usage.lock().add(&total_usage(&child));
+                    }
+                    return Ok(Some((child, status)));
+                }
+
+                let mut state_change = child.state_change.lock();
+                let wanted = match *state_change {
+                    Some(WaitStatus::Stopped(_)) => options.contains(WaitFlags::WUNTRACED),
+                    Some(WaitStatus::Continued) => options.contains(WaitFlags::WCONTINUED),
+                    _ => false,
+                };
+                if wanted {
+                    let status = if options.contains(WaitFlags::WNOWAIT) {
+                        state_change.unwrap()
+                    } else {
+                        state_change.take().unwrap()
+                    };
+                    return Ok(Some((child.clone(), status)));
+                }
+            }
+            drop(children);
+
+            if !found {
+                return Err(AxError::NotFound);
+            }
+            if options.contains(WaitFlags::WNOHANG) {
+                return Ok(None);
+            }
+            if self.is_group_exiting() || has_pending_signal() {
+                return Err(AxError::Interrupted);
+            }
+            self.child_wq.wait_until(|| {
+                self.child_events.load(Ordering::Acquire) != seen
+                    || self.is_group_exiting()
+                    || has_pending_signal()
+            });
+        }
+    }
+
+    pub fn get_heap_bottom(&self) -> u64 {
+        self.heap_bottom.load(Ordering::Acquire)
+    }
+
+    pub fn set_heap_bottom(&self, bottom: u64) {
+        self.heap_bottom.store(bottom, Ordering::Release)
+    }
+
+    pub fn get_heap_top(&self) -> u64 {
+        self.heap_top.load(Ordering::Acquire)
+    }
+
+    pub fn set_heap_top(&self, top: u64) {
+        self.heap_top.store(top, Ordering::Release)
+    }
+}
+
+impl Drop for ProcessData {
+    fn drop(&mut self) {
+        PROCESS_TABLE.lock().remove(&self.pid);
+        free_pid(self.pid);
+    }
+}
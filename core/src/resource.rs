@@ -0,0 +1,249 @@
+//! Resource limits and resource usage of processes.
+//!
+//! Every process has a set of [`Rlimits`], which is inherited by its
+//! children and kept across `execve`. The limits are enforced where the
+//! resources are allocated, e.g. `RLIMIT_NOFILE` by the file descriptor
+//! table and `RLIMIT_CPU` by [`check_cpu_limit`] before returning to user
+//! space.
+//!
+//! The resources used by threads are summed up in [`ResourceUsage`]: those
+//! of exited threads by their process, and those of reaped children by
+//! their parent.
+
+use arceos_posix_api::{
+    AX_FILE_LIMIT,
+    ctypes::{
+        RLIMIT_CORE, RLIMIT_CPU, RLIMIT_MEMLOCK, RLIMIT_MSGQUEUE, RLIMIT_NICE, RLIMIT_NLIMITS,
+        RLIMIT_NOFILE, RLIMIT_NPROC, RLIMIT_RTPRIO, RLIMIT_STACK,
+    },
+};
+use axerrno::{AxError, AxResult};
+use axhal::time::NANOS_PER_SEC;
+use axtask::{AxTaskRef, TaskExtRef, current};
+
+use crate::{
+    cred::{CAP_SYS_ADMIN, CAP_SYS_RESOURCE, Credentials},
+    ctypes::Rusage,
+    process::{ProcessData, processes},
+    signal::{SIGKILL, SIGXCPU, send_signal},
+};
+
+/// The value of a resource limit that is not limited.
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+/// The number of resource limits.
+pub const RLIM_NLIMITS: usize = RLIMIT_NLIMITS as usize;
+
+/// The soft and hard limit of a resource, as `struct rlimit` of
+/// `getrlimit` and `prlimit64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct Rlimit {
+    /// The soft limit, which is enforced.
+    pub rlim_cur: u64,
+    /// The hard limit, the ceiling of the soft limit.
+    pub rlim_max: u64,
+}
+
+impl Rlimit {
+    /// No limit at all.
+    pub const INFINITY: Self = Self::new(RLIM_INFINITY, RLIM_INFINITY);
+
+    /// Creates a limit with the soft limit `cur` and the hard limit `max`.
+    pub const fn new(cur: u64, max: u64) -> Self {
+        Self {
+            rlim_cur: cur,
+            rlim_max: max,
+        }
+    }
+}
+
+/// The resource limits of a process, indexed by the `RLIMIT_*` constants.
+#[derive(Debug, Clone, Copy)]
+pub struct Rlimits([Rlimit; RLIM_NLIMITS]);
+
+impl Rlimits {
+    /// Returns the limit of `resource`, which must be less than
+    /// [`RLIM_NLIMITS`].
+    pub fn get(&self, resource: u32) -> Rlimit {
+        self.0[resource as usize]
+    }
+
+    /// Sets the limit of `resource`, which must be less than
+    /// [`RLIM_NLIMITS`].
+    pub fn set(&mut self, resource: u32, limit: Rlimit) {
+        self.0[resource as usize] = limit;
+    }
+}
+
+impl Default for Rlimits {
+    /// The limits of the first process. Most resources are unlimited, and the
+    /// others have the defaults the C libraries and shells expect.
+    fn default() -> Self {
+        let mut limits = Self([Rlimit::INFINITY; RLIM_NLIMITS]);
+        limits.set(
+            RLIMIT_STACK,
+            Rlimit::new(axconfig::plat::USER_STACK_SIZE as u64, RLIM_INFINITY),
+        );
+        limits.set(RLIMIT_CORE, Rlimit::new(0, RLIM_INFINITY));
+        limits.set(
+            RLIMIT_NOFILE,
+            Rlimit::new(AX_FILE_LIMIT as u64, AX_FILE_LIMIT as u64),
+        );
+        limits.set(RLIMIT_MEMLOCK, Rlimit::new(0x80_0000, 0x80_0000));
+        limits.set(RLIMIT_MSGQUEUE, Rlimit::new(819_200, 819_200));
+        limits.set(RLIMIT_NICE, Rlimit::new(0, 0));
+        limits.set(RLIMIT_RTPRIO, Rlimit::new(0, 0));
+        limits
+    }
+}
+
+/// The resources used by threads, as reported by `getrusage`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ResourceUsage {
+    /// The user CPU time, in nanoseconds.
+    pub utime_ns: usize,
+    /// The system CPU time, in nanoseconds.
+    pub stime_ns: usize,
+    /// The maximum resident set size, in kilobytes.
+    pub maxrss_kb: usize,
+    /// The number of page faults handled without reading a file.
+    pub minflt: u64,
+    /// The number of page faults which read a file.
+    pub majflt: u64,
+    /// The number of voluntary context switches.
+    pub nvcsw: u64,
+    /// The number of involuntary context switches.
+    pub nivcsw: u64,
+}
+
+impl ResourceUsage {
+    /// Adds the resources used by `other`. The maximum resident set size is
+    /// the larger one, not the sum.
+    pub fn add(&mut self, other: &Self) {
+        self.utime_ns += other.utime_ns;
+        self.stime_ns += other.stime_ns;
+        self.maxrss_kb = self.maxrss_kb.max(other.maxrss_kb);
+        self.minflt += other.minflt;
+        self.majflt += other.majflt;
+        self.nvcsw += other.nvcsw;
+        self.nivcsw += other.nivcsw;
+    }
+}
+
+impl From<ResourceUsage> for Rusage {
+    fn from(usage: ResourceUsage) -> Self {
+        Self {
+            ru_maxrss: usage.maxrss_kb as _,
+            ru_minflt: usage.minflt as _,
+            ru_majflt: usage.majflt as _,
+            ru_nvcsw: usage.nvcsw as _,
+            ru_nivcsw: usage.nivcsw as _,
+            ..Rusage::from_times(usage.utime_ns, usage.stime_ns)
+        }
+    }
+}
+
+/// Returns the resources used by the thread `task`, without the resident
+/// set size, which is only tracked per process.
+pub fn thread_usage(task: &AxTaskRef) -> ResourceUsage {
+    let task_ext = task.task_ext();
+    let (utime_ns, stime_ns) = task_ext.time_stat_output();
+    let (minflt, majflt) = task_ext.page_faults();
+    let (nvcsw, nivcsw) = task.context_switches();
+    ResourceUsage {
+        utime_ns,
+        stime_ns,
+        maxrss_kb: 0,
+        minflt,
+        majflt,
+        nvcsw,
+        nivcsw,
+    }
+}
+
+/// Returns the resources used by all the threads of `process`, including
+/// the exited ones.
+pub fn process_usage(process: &ProcessData) -> ResourceUsage {
+    let mut usage = process.exited_usage();
+    for thread in process.threads() {
+        usage.add(&thread_usage(&thread));
+    }
+    usage
+}
+
+/// Returns the resources used by `process` and its reaped descendants,
+/// which are reported to its parent by `wait4`.
+pub fn total_usage(process: &ProcessData) -> ResourceUsage {
+    let mut usage = process_usage(process);
+    usage.add(&process.children_usage());
+    usage
+}
+
+/// Returns the CPU time (in nanoseconds) consumed by all the threads of
+/// `process`, including the exited ones.
+pub fn process_cpu_time_ns(process: &ProcessData) -> u64 {
+    let usage = process_usage(process);
+    (usage.utime_ns + usage.stime_ns) as u64
+}
+
+/// Returns the CPU time (in nanoseconds) consumed by the thread `task`.
+pub fn thread_cpu_time_ns(task: &AxTaskRef) -> u64 {
+    let (utime_ns, stime_ns) = task.task_ext().time_stat_output();
+    (utime_ns + stime_ns) as u64
+}
+
+/// Enforces `RLIMIT_CPU` on the current process.
+///
+/// `SIGXCPU` is sent once the process has used up the soft limit, and then
+/// once per second of CPU time, until the process is killed at the hard
+/// limit. The soft limit thus gives a handler time to clean up.
+pub fn check_cpu_limit() {
+    let curr = current();
+    let process = &curr.task_ext().process;
+    let limit = process.rlimit(RLIMIT_CPU);
+    if limit.rlim_cur == RLIM_INFINITY {
+        return;
+    }
+    let secs = process_cpu_time_ns(process) / NANOS_PER_SEC;
+    if secs >= limit.rlim_max {
+        send_signal(process, SIGKILL);
+    } else if secs >= limit.rlim_cur && process.xcpu_due(secs) {
+        send_signal(process, SIGXCPU);
+    }
+}
+
+/// Returns the number of threads run by the user with the real user ID
+/// `uid`.
+fn user_thread_count(uid: u32) -> usize {
+    processes()
+        .iter()
+        .map(|process| process.threads())
+        .filter(|threads| {
+            threads
+                .first()
+                .is_some_and(|thread| thread.task_ext().cred().uid == uid)
+        })
+        .map(|threads| threads.len())
+        .sum()
+}
+
+/// Enforces `RLIMIT_NPROC` of `process` before it creates a thread or a
+/// process with the credentials `cred`.
+///
+/// The limit counts all the threads of the user, as each is a task of its
+/// own, and does not apply to root or to privileged processes.
+pub fn check_nproc_limit(process: &ProcessData, cred: &Credentials) -> AxResult {
+    let limit = process.rlimit(RLIMIT_NPROC).rlim_cur;
+    if limit == RLIM_INFINITY
+        || cred.uid == 0
+        || cred.capable(CAP_SYS_RESOURCE)
+        || cred.capable(CAP_SYS_ADMIN)
+    {
+        return Ok(());
+    }
+    if user_thread_count(cred.uid) as u64 >= limit {
+        return Err(AxError::WouldBlock);
+    }
+    Ok(())
+}
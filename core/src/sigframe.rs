@@ -0,0 +1,359 @@
+//! Setting up and restoring signal frames
+//!
+//! Before a user signal handler is called, the interrupted user context (`ucontext_t`) and the
+//! signal information (`siginfo_t`) are saved in a signal frame on the user stack. The handler
+//! returns to a trampoline calling `rt_sigreturn`, and [`restore_frame`] restores the context from
+//! the signal frame. `ucontext_t` has the layout of Linux on each architecture, so user programs
+//! can read and modify the interrupted registers in it.
+//!
+//! The trap frame has no floating-point registers, so they are not saved or restored for now.
+
+use core::mem::offset_of;
+
+use axerrno::{AxError, AxResult};
+use axhal::arch::TrapFrame;
+use axtask::{TaskExtRef, current};
+
+use crate::{
+    mm::{read_user_value, write_user_value},
+    signal::{MINSIGSTKSZ, SigAction, SigActionFlags, SigInfo, SignalStack, SignalStackFlags},
+    vdso::sigreturn_trampoline,
+};
+
+/// The upper bound (exclusive) of the user address space
+///
+/// Returning to a non-canonical address makes `sysretq` and `iretq` fault in the kernel.
+#[cfg(target_arch = "x86_64")]
+const USER_SPACE_END: usize = 1 << 47;
+
+/// The rflags bits a signal frame can modify: AC, OF, DF, TF, SF, ZF, AF, PF, CF and RF
+#[cfg(target_arch = "x86_64")]
+const USER_RFLAGS: u64 = 0x50dd5;
+
+/// The pstate bits a signal frame can modify, the NZCV condition flags
+#[cfg(target_arch = "aarch64")]
+const USER_PSTATE: u64 = 0xf000_0000;
+
+/// The `mcontext_t` of `ucontext_t`, the interrupted registers
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct MContext {
+    r8: u64,
+    r9: u64,
+    r10: u64,
+    r11: u64,
+    r12: u64,
+    r13: u64,
+    r14: u64,
+    r15: u64,
+    rdi: u64,
+    rsi: u64,
+    rbp: u64,
+    rbx: u64,
+    rdx: u64,
+    rax: u64,
+    rcx: u64,
+    rsp: u64,
+    rip: u64,
+    eflags: u64,
+    cs: u16,
+    gs: u16,
+    fs: u16,
+    ss: u16,
+    err: u64,
+    trapno: u64,
+    oldmask: u64,
+    cr2: u64,
+    /// The address of the floating-point registers, or 0 if they are not saved
+    fpstate: u64,
+    reserved: [u64; 8],
+}
+
+#[cfg(target_arch = "x86_64")]
+impl MContext {
+    fn save(&mut self, tf: &TrapFrame) {
+        self.r8 = tf.r8;
+        self.r9 = tf.r9;
+        self.r10 = tf.r10;
+        self.r11 = tf.r11;
+        self.r12 = tf.r12;
+        self.r13 = tf.r13;
+        self.r14 = tf.r14;
+        self.r15 = tf.r15;
+        self.rdi = tf.rdi;
+        self.rsi = tf.rsi;
+        self.rbp = tf.rbp;
+        self.rbx = tf.rbx;
+        self.rdx = tf.rdx;
+        self.rax = tf.rax;
+        self.rcx = tf.rcx;
+        self.rsp = tf.rsp;
+        self.rip = tf.rip;
+        self.eflags = tf.rflags;
+        self.cs = tf.cs as u16;
+        self.ss = tf.ss as u16;
+    }
+
+    fn restore(&self, tf: &mut TrapFrame) -> AxResult {
+        if self.rip as usize >= USER_SPACE_END {
+            return Err(AxError::BadAddress);
+        }
+        tf.r8 = self.r8;
+        tf.r9 = self.r9;
+        tf.r10 = self.r10;
+        tf.r11 = self.r11;
+        tf.r12 = self.r12;
+        tf.r13 = self.r13;
+        tf.r14 = self.r14;
+        tf.r15 = self.r15;
+        tf.rdi = self.rdi;
+        tf.rsi = self.rsi;
+        tf.rbp = self.rbp;
+        tf.rbx = self.rbx;
+        tf.rdx = self.rdx;
+        tf.rax = self.rax;
+        tf.rcx = self.rcx;
+        tf.rsp = self.rsp;
+        tf.rip = self.rip;
+        tf.rflags = (tf.rflags & !USER_RFLAGS) | (self.eflags & USER_RFLAGS);
+        Ok(())
+    }
+}
+
+/// The `mcontext_t` of `ucontext_t`, the interrupted registers
+#[cfg(target_arch = "riscv64")]
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct MContext {
+    /// pc, and x1 to x31
+    regs: [usize; 32],
+    /// The floating-point registers
+    fpstate: [u8; 528],
+}
+
+#[cfg(target_arch = "riscv64")]
+impl MContext {
+    fn save(&mut self, tf: &TrapFrame) {
+        let regs: [usize; 31] = unsafe { core::mem::transmute(tf.regs) };
+        self.regs[0] = tf.sepc;
+        self.regs[1..].copy_from_slice(&regs);
+    }
+
+    fn restore(&self, tf: &mut TrapFrame) -> AxResult {
+        let regs: [usize; 31] = self.regs[1..].try_into().unwrap();
+        tf.sepc = self.regs[0];
+        tf.regs = unsafe { core::mem::transmute::<[usize; 31], _>(regs) };
+        Ok(())
+    }
+}
+
+/// The `mcontext_t` of `ucontext_t`, the interrupted registers
+#[cfg(target_arch = "aarch64")]
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct MContext {
+    fault_address: u64,
+    regs: [u64; 31],
+    sp: u64,
+    pc: u64,
+    pstate: u64,
+    _pad: u64,
+    /// Extended context such as the floating-point registers
+    reserved: [u8; 4096],
+}
+
+#[cfg(target_arch = "aarch64")]
+impl MContext {
+    fn save(&mut self, tf: &TrapFrame) {
+        self.regs = tf.r;
+        self.sp = tf.usp;
+        self.pc = tf.elr;
+        self.pstate = tf.spsr;
+    }
+
+    fn restore(&self, tf: &mut TrapFrame) -> AxResult {
+        tf.r = self.regs;
+        tf.usp = self.sp;
+        tf.elr = self.pc;
+        tf.spsr = (tf.spsr & !USER_PSTATE) | (self.pstate & USER_PSTATE);
+        Ok(())
+    }
+}
+
+/// The `mcontext_t` of `ucontext_t`, the interrupted registers
+#[cfg(target_arch = "loongarch64")]
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct MContext {
+    pc: usize,
+    regs: [usize; 32],
+    flags: u32,
+}
+
+#[cfg(target_arch = "loongarch64")]
+impl MContext {
+    fn save(&mut self, tf: &TrapFrame) {
+        self.pc = tf.era;
+        self.regs = unsafe { core::mem::transmute(tf.regs) };
+    }
+
+    fn restore(&self, tf: &mut TrapFrame) -> AxResult {
+        tf.era = self.pc;
+        tf.regs = unsafe { core::mem::transmute::<[usize; 32], _>(self.regs) };
+        tf.regs.zero = 0;
+        Ok(())
+    }
+}
+
+/// `ucontext_t`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UContext {
+    flags: usize,
+    link: usize,
+    /// The alternate signal stack when the signal frame was set up
+    stack: SignalStack,
+    #[cfg(target_arch = "x86_64")]
+    mcontext: MContext,
+    /// The signal mask restored when the handler returns
+    sigmask: u64,
+    /// Room for larger signal sets
+    #[cfg(not(target_arch = "x86_64"))]
+    _unused: [u8; 120],
+    #[cfg(not(target_arch = "x86_64"))]
+    mcontext: MContext,
+}
+
+/// The signal frame on the user stack
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SignalFrame {
+    /// The return address of the handler, popped from the stack by `ret`
+    #[cfg(target_arch = "x86_64")]
+    restorer: usize,
+    ucontext: UContext,
+    info: SigInfo,
+}
+
+/// Returns the return address of the handler
+#[cfg_attr(
+    not(any(target_arch = "x86_64", target_arch = "aarch64")),
+    allow(unused_variables)
+)]
+fn restorer(action: &SigAction) -> usize {
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    if action.flags().contains(SigActionFlags::SA_RESTORER) {
+        return action.restorer;
+    }
+    sigreturn_trampoline()
+}
+
+/// Sets up a signal frame for `info`, so that the current thread enters the handler
+///
+/// `blocked` is the signal mask restored when the handler returns. The signal frame is set up on
+/// the alternate signal stack if `action` has SA_ONSTACK and the stack is usable, and on the
+/// current user stack otherwise.
+///
+/// Returns [`AxError::BadAddress`] if the user stack is not writable, leaving the trap frame
+/// unmodified.
+pub(crate) fn setup_frame(
+    tf: &mut TrapFrame,
+    action: &SigAction,
+    info: &SigInfo,
+    blocked: u64,
+) -> AxResult {
+    let curr = current();
+    let task_ext = curr.task_ext();
+    let altstack = task_ext.signal_stack();
+
+    let mut sp = tf.sp();
+    // Skip the 128-byte red zone below the stack pointer, which x86_64 functions can use
+    #[cfg(target_arch = "x86_64")]
+    {
+        sp = sp.wrapping_sub(128);
+    }
+    if action.flags().contains(SigActionFlags::SA_ONSTACK)
+        && !altstack.flags().contains(SignalStackFlags::SS_DISABLE)
+        && !altstack.contains(tf.sp())
+    {
+        sp = altstack.sp.wrapping_add(altstack.size);
+    }
+    let frame_addr = sp
+        .checked_sub(size_of::<SignalFrame>())
+        .map(|addr| addr & !0xf);
+    // The handler starts with a return address on the stack, so the stack pointer plus 8 is
+    // 16-byte aligned
+    #[cfg(target_arch = "x86_64")]
+    let frame_addr = frame_addr.and_then(|addr| addr.checked_sub(8));
+    let frame_addr = frame_addr.ok_or(AxError::BadAddress)?;
+
+    let restorer = restorer(action);
+    #[cfg(target_arch = "x86_64")]
+    if action.handler >= USER_SPACE_END || restorer >= USER_SPACE_END {
+        return Err(AxError::BadAddress);
+    }
+
+    let mut frame: SignalFrame = unsafe { core::mem::zeroed() };
+    #[cfg(target_arch = "x86_64")]
+    {
+        frame.restorer = restorer;
+    }
+    frame.ucontext.stack = altstack.report(tf.sp());
+    frame.ucontext.sigmask = blocked;
+    frame.ucontext.mcontext.save(tf);
+    frame.info = *info;
+    write_user_value(&mut task_ext.aspace.lock(), frame_addr, frame)?;
+
+    // sigreturn restores the alternate signal stack from `uc_stack` when the handler returns
+    if altstack.flags().contains(SignalStackFlags::SS_AUTODISARM) {
+        task_ext.set_signal_stack(SignalStack::default());
+    }
+
+    tf.set_sp(frame_addr);
+    tf.set_ip(action.handler);
+    tf.set_arg0(info.signo as usize);
+    tf.set_arg1(frame_addr + offset_of!(SignalFrame, info));
+    tf.set_arg2(frame_addr + offset_of!(SignalFrame, ucontext));
+    #[cfg(not(target_arch = "x86_64"))]
+    tf.set_ra(restorer);
+    // Variadic functions read the number of vector registers used from al
+    #[cfg(target_arch = "x86_64")]
+    {
+        tf.rax = 0;
+    }
+    Ok(())
+}
+
+/// Restores the context interrupted by a signal from its frame, for sys_rt_sigreturn
+///
+/// The registers, the signal mask and the alternate signal stack are restored. Returns
+/// [`AxError::BadAddress`] if the signal frame is not readable or its context is invalid, leaving
+/// the trap frame unmodified.
+pub fn restore_frame(tf: &mut TrapFrame) -> AxResult {
+    let curr = current();
+    let task_ext = curr.task_ext();
+    // The handler returning with `ret` has popped the return address from the stack
+    #[cfg(target_arch = "x86_64")]
+    let frame_addr = tf.sp().wrapping_sub(8);
+    #[cfg(not(target_arch = "x86_64"))]
+    let frame_addr = tf.sp();
+    let frame: SignalFrame = unsafe { read_user_value(&mut task_ext.aspace.lock(), frame_addr)? };
+    let ucontext = &frame.ucontext;
+    ucontext.mcontext.restore(tf)?;
+    task_ext.set_blocked_signals(ucontext.sigmask);
+
+    // As with sigaltstack, the alternate signal stack cannot be changed while running on it
+    if !task_ext.signal_stack().contains(tf.sp()) {
+        let flags = ucontext.stack.flags();
+        if flags.contains(SignalStackFlags::SS_DISABLE) {
+            task_ext.set_signal_stack(SignalStack::default());
+        } else if ucontext.stack.size >= MINSIGSTKSZ {
+            task_ext.set_signal_stack(SignalStack {
+                flags: (flags & SignalStackFlags::SS_AUTODISARM).bits(),
+                ..ucontext.stack
+            });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,726 @@
+//! Definitions, sending and default actions of signals.
+
+use alloc::{collections::VecDeque, sync::Arc};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use axhal::{
+    arch::TrapFrame,
+    trap::{USER_RETURN, register_trap_handler},
+};
+use axsync::Mutex;
+use axtask::{AxTaskRef, TaskExtRef, current};
+use bitflags::bitflags;
+
+use crate::{
+    ctypes::WaitStatus,
+    process::ProcessData,
+    resource::check_cpu_limit,
+    sigframe::setup_frame,
+    task::{
+        exit_current, exit_group, time_stat_from_kernel_to_user, time_stat_from_user_to_kernel,
+    },
+};
+
+/// Hangup
+pub const SIGHUP: u32 = 1;
+/// Interrupt
+pub const SIGINT: u32 = 2;
+/// Quit
+pub const SIGQUIT: u32 = 3;
+/// Illegal instruction
+pub const SIGILL: u32 = 4;
+/// Breakpoint
+pub const SIGTRAP: u32 = 5;
+/// Abort
+pub const SIGABRT: u32 = 6;
+/// Bus error
+pub const SIGBUS: u32 = 7;
+/// Floating-point exception
+pub const SIGFPE: u32 = 8;
+/// Kill, which cannot be caught or ignored
+pub const SIGKILL: u32 = 9;
+/// User-defined signal 1
+pub const SIGUSR1: u32 = 10;
+/// Segmentation fault
+pub const SIGSEGV: u32 = 11;
+/// User-defined signal 2
+pub const SIGUSR2: u32 = 12;
+/// Broken pipe
+pub const SIGPIPE: u32 = 13;
+/// Alarm clock
+pub const SIGALRM: u32 = 14;
+/// Termination
+pub const SIGTERM: u32 = 15;
+/// Coprocessor stack fault
+pub const SIGSTKFLT: u32 = 16;
+/// Child stopped, continued or terminated
+pub const SIGCHLD: u32 = 17;
+/// Continue if stopped
+pub const SIGCONT: u32 = 18;
+/// Stop, which cannot be caught or ignored
+pub const SIGSTOP: u32 = 19;
+/// Stop typed at the terminal
+pub const SIGTSTP: u32 = 20;
+/// Terminal read from a background process
+pub const SIGTTIN: u32 = 21;
+/// Terminal write from a background process
+pub const SIGTTOU: u32 = 22;
+/// Urgent data on a socket
+pub const SIGURG: u32 = 23;
+/// CPU time limit exceeded
+pub const SIGXCPU: u32 = 24;
+/// File size limit exceeded
+pub const SIGXFSZ: u32 = 25;
+/// Virtual timer expired
+pub const SIGVTALRM: u32 = 26;
+/// Profiling timer expired
+pub const SIGPROF: u32 = 27;
+/// Terminal window size changed
+pub const SIGWINCH: u32 = 28;
+/// Asynchronous I/O
+pub const SIGIO: u32 = 29;
+/// Power failure
+pub const SIGPWR: u32 = 30;
+/// Bad system call
+pub const SIGSYS: u32 = 31;
+/// The lowest real-time signal number. Lower numbers are standard signals.
+pub const SIGRTMIN: u32 = 32;
+/// The upper bound (exclusive) of signal numbers
+pub const SIGNAL_MAX: u32 = 65;
+
+/// The default action of a signal
+pub const SIG_DFL: usize = 0;
+/// Ignore the signal
+pub const SIG_IGN: usize = 1;
+
+bitflags! {
+    /// The `sa_flags` of sigaction
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct SigActionFlags: usize {
+        /// Do not send SIGCHLD when a child stops or continues
+        const SA_NOCLDSTOP = 1;
+        /// Do not turn children into zombies when they exit
+        const SA_NOCLDWAIT = 2;
+        /// The handler takes three arguments
+        const SA_SIGINFO = 4;
+        /// The libc provides the trampoline that returns from the handler
+        const SA_RESTORER = 0x0400_0000;
+        /// Run the handler on the alternate signal stack
+        const SA_ONSTACK = 0x0800_0000;
+        /// Restart system calls interrupted by the signal
+        const SA_RESTART = 0x1000_0000;
+        /// Do not block the signal while its handler runs
+        const SA_NODEFER = 0x4000_0000;
+        /// Reset to the default action once the handler runs
+        const SA_RESETHAND = 0x8000_0000;
+    }
+}
+
+/// The kernel `struct sigaction`, used by sys_rt_sigaction
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SigAction {
+    /// The address of the handler, or [`SIG_DFL`] or [`SIG_IGN`]
+    pub handler: usize,
+    /// See [`SigActionFlags`]
+    pub flags: usize,
+    /// The trampoline that returns from the handler, only on architectures with SA_RESTORER
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    pub restorer: usize,
+    /// Signals blocked in addition while the handler runs
+    pub mask: u64,
+}
+
+impl SigAction {
+    /// Returns the `sa_flags`
+    pub fn flags(&self) -> SigActionFlags {
+        SigActionFlags::from_bits_truncate(self.flags)
+    }
+}
+
+/// The default action of a signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefaultAction {
+    /// Terminate the process
+    Terminate,
+    /// Terminate the process and dump core
+    CoreDump,
+    /// Ignore the signal
+    Ignore,
+    /// Stop the process
+    Stop,
+    /// Continue the stopped process
+    Continue,
+}
+
+/// Returns the default action of a signal
+pub fn default_action(signo: u32) -> DefaultAction {
+    match signo {
+        SIGCHLD | SIGURG | SIGWINCH => DefaultAction::Ignore,
+        SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => DefaultAction::Stop,
+        SIGCONT => DefaultAction::Continue,
+        SIGQUIT | SIGILL | SIGTRAP | SIGABRT | SIGBUS | SIGFPE | SIGSEGV | SIGXCPU | SIGXFSZ
+        | SIGSYS => DefaultAction::CoreDump,
+        // Real-time signals terminate the process by default too
+        _ => DefaultAction::Terminate,
+    }
+}
+
+/// Returns the bit of a signal in a signal set
+pub const fn sig_bit(signo: u32) -> u64 {
+    1 << (signo - 1)
+}
+
+/// Signals that cannot be caught, ignored or blocked
+pub const UNMASKABLE: u64 = sig_bit(SIGKILL) | sig_bit(SIGSTOP);
+
+/// The signal actions shared by all threads of a process
+pub struct SignalActions([SigAction; SIGNAL_MAX as usize - 1]);
+
+impl SignalActions {
+    /// Creates a table with the default action for every signal
+    pub fn new() -> Self {
+        Self([SigAction::default(); SIGNAL_MAX as usize - 1])
+    }
+
+    /// Returns the action of a signal
+    pub fn get(&self, signo: u32) -> SigAction {
+        self.0[signo as usize - 1]
+    }
+
+    /// Sets the action of a signal
+    pub fn set(&mut self, signo: u32, action: SigAction) {
+        self.0[signo as usize - 1] = action;
+    }
+
+    /// Resets caught signals to the default action, for execve.
+    ///
+    /// Ignored signals stay ignored, but the `sa_flags` and `sa_mask` of every signal are cleared.
+    pub fn reset_handlers(&mut self) {
+        for action in self.0.iter_mut() {
+            let handler = if action.handler == SIG_IGN {
+                SIG_IGN
+            } else {
+                SIG_DFL
+            };
+            *action = SigAction {
+                handler,
+                ..Default::default()
+            };
+        }
+    }
+
+    /// Returns whether a signal is ignored
+    pub fn is_ignored(&self, signo: u32) -> bool {
+        match self.get(signo).handler {
+            SIG_IGN => true,
+            SIG_DFL => default_action(signo) == DefaultAction::Ignore,
+            _ => false,
+        }
+    }
+}
+
+impl Default for SignalActions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for SignalActions {
+    fn clone(&self) -> Self {
+        Self(self.0)
+    }
+}
+
+/// The table of signal actions, shared by processes created with `CLONE_SIGHAND`
+pub type SharedSignalActions = Arc<Mutex<SignalActions>>;
+
+bitflags! {
+    /// The `ss_flags` of sigaltstack
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct SignalStackFlags: i32 {
+        /// Running on the alternate signal stack
+        const SS_ONSTACK = 1;
+        /// The alternate signal stack is disabled
+        const SS_DISABLE = 2;
+        /// Clear the alternate signal stack on entering a handler
+        const SS_AUTODISARM = 1 << 31;
+    }
+}
+
+/// The minimum size of an alternate signal stack
+#[cfg(target_arch = "aarch64")]
+pub const MINSIGSTKSZ: usize = 5120;
+/// The minimum size of an alternate signal stack
+#[cfg(not(target_arch = "aarch64"))]
+pub const MINSIGSTKSZ: usize = 2048;
+
+/// An alternate signal stack, the `stack_t` of sys_sigaltstack
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SignalStack {
+    /// The start address of the stack
+    pub sp: usize,
+    /// See [`SignalStackFlags`]
+    pub flags: i32,
+    /// The size of the stack
+    pub size: usize,
+}
+
+impl SignalStack {
+    /// Returns the `ss_flags`
+    pub fn flags(&self) -> SignalStackFlags {
+        SignalStackFlags::from_bits_retain(self.flags)
+    }
+
+    /// Returns whether the stack pointer `sp` is on the alternate signal stack.
+    ///
+    /// A stack with SS_AUTODISARM was cleared on entering the handler, so this is always `false`,
+    /// and nested signals reuse the stack from its top.
+    pub fn contains(&self, sp: usize) -> bool {
+        !self.flags().contains(SignalStackFlags::SS_AUTODISARM)
+            && sp > self.sp
+            && sp - self.sp <= self.size
+    }
+
+    /// Returns the alternate signal stack reported to the user when the stack pointer is `sp`.
+    ///
+    /// It has SS_ONSTACK if `sp` is on the stack.
+    pub fn report(&self, sp: usize) -> Self {
+        let mut stack = *self;
+        if self.contains(sp) {
+            stack.flags |= SignalStackFlags::SS_ONSTACK.bits();
+        }
+        stack
+    }
+}
+
+impl Default for SignalStack {
+    /// Returns a disabled alternate signal stack
+    fn default() -> Self {
+        Self {
+            sp: 0,
+            flags: SignalStackFlags::SS_DISABLE.bits(),
+            size: 0,
+        }
+    }
+}
+
+/// Signal source: sent by kill
+pub const SI_USER: i32 = 0;
+/// Signal source: sent by the kernel
+pub const SI_KERNEL: i32 = 0x80;
+/// Signal source: sent by a POSIX timer
+pub const SI_TIMER: i32 = -2;
+/// Signal source: sent by tkill or tgkill
+pub const SI_TKILL: i32 = -6;
+/// SIGSEGV cause: the address is not mapped
+pub const SEGV_MAPERR: i32 = 1;
+/// SIGSEGV cause: the mapping does not allow the access
+pub const SEGV_ACCERR: i32 = 2;
+
+/// Additional information of a signal, the `siginfo_t`
+#[repr(C, align(8))]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SigInfo {
+    /// The signal number
+    pub signo: i32,
+    /// The error number, always 0
+    pub errno: i32,
+    /// The source or cause of the signal, such as [`SI_USER`] or [`SEGV_MAPERR`]
+    pub code: i32,
+    _pad: i32,
+    /// Fields depending on the source, such as the PID of the sender or the faulting address
+    fields: [u32; 28],
+}
+
+impl SigInfo {
+    /// Creates information without other fields
+    pub const fn new(signo: u32, code: i32) -> Self {
+        Self {
+            signo: signo as i32,
+            errno: 0,
+            code,
+            _pad: 0,
+            fields: [0; 28],
+        }
+    }
+
+    /// Creates information for a fault accessing `addr`, the `si_addr`
+    pub fn fault(signo: u32, code: i32, addr: usize) -> Self {
+        let mut info = Self::new(signo, code);
+        info.set_u64(0, addr as u64);
+        info
+    }
+
+    /// Creates information for a signal sent by process `pid` of user `uid` (`si_pid`, `si_uid`)
+    pub fn sender(signo: u32, code: i32, pid: usize, uid: u32) -> Self {
+        let mut info = Self::new(signo, code);
+        info.fields[0] = pid as u32;
+        info.fields[1] = uid;
+        info
+    }
+
+    /// Creates information for SIGCHLD and sys_waitid when child `pid` changes to `status`
+    ///
+    /// `uid` is the real user ID of the child, and `utime` and `stime` are its CPU times in clock
+    /// ticks.
+    pub fn child(
+        signo: u32,
+        pid: usize,
+        uid: u32,
+        status: WaitStatus,
+        utime: u64,
+        stime: u64,
+    ) -> Self {
+        let (code, child_status) = status.to_siginfo_code();
+        let mut info = Self::sender(signo, code, pid, uid);
+        info.fields[2] = child_status as u32;
+        info.set_u64(4, utime);
+        info.set_u64(6, stime);
+        info
+    }
+
+    /// Creates information for the expiration of POSIX timer `timer_id`
+    ///
+    /// `overrun` is the number of earlier expirations that sent no signal, the `si_overrun`, and
+    /// `value` is the `sigev_value` of the sigevent, the `si_value`.
+    pub fn timer(signo: u32, timer_id: i32, overrun: u64, value: u64) -> Self {
+        let mut info = Self::new(signo, SI_TIMER);
+        info.fields[0] = timer_id as u32;
+        info.fields[1] = overrun.min(i32::MAX as u64) as u32;
+        info.set_u64(2, value);
+        info
+    }
+
+    /// Writes a 64-bit field starting at `fields[index]`
+    fn set_u64(&mut self, index: usize, value: u64) {
+        self.fields[index] = value as u32;
+        self.fields[index + 1] = (value >> 32) as u32;
+    }
+}
+
+/// The maximum number of real-time signals queued in a thread or process
+const RT_QUEUE_MAX: usize = 1024;
+
+/// Pending signals
+///
+/// At most one instance of a standard signal is pending, while a real-time signal is queued each
+/// time it is sent.
+pub struct PendingSignals {
+    /// The set of pending signals, only modified with the lock of `queue` held
+    set: AtomicU64,
+    /// The information of the pending signals, in the order they were sent
+    queue: Mutex<VecDeque<SigInfo>>,
+}
+
+impl PendingSignals {
+    /// Creates an empty set of pending signals
+    pub const fn new() -> Self {
+        Self {
+            set: AtomicU64::new(0),
+            queue: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Adds a signal
+    ///
+    /// It is discarded and `false` is returned if the same standard signal is already pending, or
+    /// too many real-time signals are queued.
+    pub fn add(&self, info: SigInfo) -> bool {
+        let signo = info.signo as u32;
+        let mut queue = self.queue.lock();
+        let discarded = if signo < SIGRTMIN {
+            self.get() & sig_bit(signo) != 0
+        } else {
+            queue.len() >= RT_QUEUE_MAX
+        };
+        if discarded {
+            return false;
+        }
+        queue.push_back(info);
+        self.set.fetch_or(sig_bit(signo), Ordering::AcqRel);
+        true
+    }
+
+    /// Removes the signals in a signal set
+    pub fn remove(&self, set: u64) {
+        let mut queue = self.queue.lock();
+        queue.retain(|info| sig_bit(info.signo as u32) & set == 0);
+        self.set.fetch_and(!set, Ordering::AcqRel);
+    }
+
+    /// Adds `count` to the `si_overrun` of the pending signal of POSIX timer `timer_id`
+    ///
+    /// Returns whether such a signal is pending.
+    pub fn add_timer_overrun(&self, timer_id: i32, count: u64) -> bool {
+        let mut queue = self.queue.lock();
+        let Some(info) = queue
+            .iter_mut()
+            .find(|info| info.code == SI_TIMER && info.fields[0] == timer_id as u32)
+        else {
+            return false;
+        };
+        info.fields[1] = (info.fields[1] as u64 + count).min(i32::MAX as u64) as u32;
+        true
+    }
+
+    /// Returns the set of pending signals
+    pub fn get(&self) -> u64 {
+        self.set.load(Ordering::Acquire)
+    }
+
+    /// Takes the lowest signal not in `blocked`
+    ///
+    /// Instances of the same real-time signal are taken in the order they were sent.
+    pub fn take(&self, blocked: u64) -> Option<SigInfo> {
+        let mut queue = self.queue.lock();
+        let deliverable = self.get() & !(blocked & !UNMASKABLE);
+        if deliverable == 0 {
+            return None;
+        }
+        let signo = deliverable.trailing_zeros() + 1;
+        let index = queue
+            .iter()
+            .position(|info| info.signo as u32 == signo)
+            .unwrap();
+        let info = queue.remove(index).unwrap();
+        if !queue.iter().any(|info| info.signo as u32 == signo) {
+            self.set.fetch_and(!sig_bit(signo), Ordering::AcqRel);
+        }
+        Some(info)
+    }
+}
+
+impl Default for PendingSignals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns whether a signal number is valid
+pub fn is_valid_signal(signo: u32) -> bool {
+    (1..SIGNAL_MAX).contains(&signo)
+}
+
+/// Removes the signals in a signal set from the pending signals of a process and its threads
+pub fn discard_signals(process: &ProcessData, set: u64) {
+    process.pending_signals().remove(set);
+    for thread in process.threads() {
+        thread.task_ext().pending_signals().remove(set);
+    }
+}
+
+/// Prepares to send a signal, and returns whether it has to be queued
+///
+/// SIGCONT continues the stopped process, while the stop signals discard any pending SIGCONT.
+/// Signals that would be ignored are not queued.
+fn prepare_signal(process: &Arc<ProcessData>, signo: u32) -> bool {
+    if !is_valid_signal(signo) || process.is_zombie() {
+        return false;
+    }
+    match signo {
+        SIGCONT => {
+            discard_signals(
+                process,
+                sig_bit(SIGSTOP) | sig_bit(SIGTSTP) | sig_bit(SIGTTIN) | sig_bit(SIGTTOU),
+            );
+            process.resume();
+        }
+        SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => discard_signals(process, sig_bit(SIGCONT)),
+        _ => {}
+    }
+    signo == SIGKILL || signo == SIGSTOP || !process.signal_actions().lock().is_ignored(signo)
+}
+
+/// Wakes the stopped or blocked threads of a process, so that they handle a new signal
+fn notify_signal(process: &ProcessData, signo: u32) {
+    if signo == SIGKILL {
+        process.wake_stopped();
+    }
+    process.interrupt_threads();
+}
+
+/// Sends a signal to a process
+pub fn send_signal(process: &Arc<ProcessData>, signo: u32) {
+    send_signal_info(process, SigInfo::new(signo, SI_KERNEL));
+}
+
+/// Sends a signal with information to a process, handled by any thread not blocking it
+///
+/// Signals that would be ignored are discarded. SIGCONT continues the stopped process, while the
+/// stop signals discard any pending SIGCONT.
+pub fn send_signal_info(process: &Arc<ProcessData>, info: SigInfo) {
+    let signo = info.signo as u32;
+    if prepare_signal(process, signo) && process.pending_signals().add(info) {
+        notify_signal(process, signo);
+    }
+}
+
+/// Sends a signal to a thread, handled by that thread only
+///
+/// SIGKILL always terminates the whole process, so it is sent to the process of the thread.
+pub fn send_thread_signal(task: &AxTaskRef, info: SigInfo) {
+    let task_ext = task.task_ext();
+    let signo = info.signo as u32;
+    if signo == SIGKILL {
+        return send_signal_info(&task_ext.process, info);
+    }
+    if prepare_signal(&task_ext.process, signo) && task_ext.pending_signals().add(info) {
+        notify_signal(&task_ext.process, signo);
+    }
+}
+
+/// Sends the current thread a signal caused by its own fault, like SIGSEGV for a bad address
+///
+/// A blocked or ignored signal is reset to the default action and unblocked, so that the thread
+/// does not fault again and again on the same instruction.
+pub fn force_signal(info: SigInfo) {
+    let curr = current();
+    let task_ext = curr.task_ext();
+    let signo = info.signo as u32;
+    {
+        let actions = task_ext.process.signal_actions();
+        let mut actions = actions.lock();
+        let blocked = task_ext.blocked_signals();
+        if blocked & sig_bit(signo) != 0 || actions.get(signo).handler == SIG_IGN {
+            actions.set(signo, SigAction::default());
+            task_ext.set_blocked_signals(blocked & !sig_bit(signo));
+        }
+    }
+    send_thread_signal(curr.as_task_ref(), info);
+}
+
+/// Returns whether the current thread has unblocked pending signals, to interrupt syscalls
+pub fn has_pending_signal() -> bool {
+    let curr = current();
+    let task_ext = curr.task_ext();
+    let pending = task_ext.pending_signals().get() | task_ext.process.pending_signals().get();
+    pending & !task_ext.blocked_signals() != 0
+}
+
+/// A system call returning to user space
+#[derive(Debug, Clone, Copy)]
+pub struct SyscallReturn {
+    /// The system call number
+    pub sysno: usize,
+    /// The first argument, in the same register as the return value on architectures but x86_64
+    pub arg0: usize,
+    /// Whether the system call was interrupted by a signal and can be restarted
+    pub restartable: bool,
+}
+
+/// Modifies the trap frame so that the thread runs `syscall` again when it returns to user space
+fn restart_syscall(tf: &mut TrapFrame, syscall: &SyscallReturn) {
+    // The return address is past the system call instruction. Both `syscall` and `int 0x80` are
+    // 2 bytes on x86_64, and the instructions of the other architectures are 4 bytes.
+    #[cfg(target_arch = "x86_64")]
+    {
+        tf.set_ip(tf.ip() - 2);
+        tf.set_retval(syscall.sysno);
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        tf.set_ip(tf.ip() - 4);
+        tf.set_arg0(syscall.arg0);
+    }
+}
+
+/// Handles the pending signals of the current thread, before it returns to user space
+///
+/// Signals sent to the thread are handled before those sent to the process. Default actions run
+/// in the kernel. For a signal with a user handler, a signal frame is set up on the user stack so
+/// that the thread enters the handler when it returns to user space, and at most one such signal
+/// is delivered at a time.
+///
+/// `syscall` is the system call returning to user space. If it was interrupted by a signal, it is
+/// restarted when no handler is delivered or the handler has SA_RESTART, and fails with EINTR
+/// otherwise.
+pub fn handle_pending_signals(tf: &mut TrapFrame, syscall: Option<SyscallReturn>) {
+    let curr = current();
+    let task_ext = curr.task_ext();
+    let process = &task_ext.process;
+    let mut restart = syscall.filter(|syscall| syscall.restartable);
+    loop {
+        if process.should_exit(task_ext.tid) {
+            // The thread calling execve does not exit here, so the exit status is only used
+            // when the whole process exits
+            exit_current(process.group_exit_status().unwrap_or(WaitStatus::Exited(0)));
+        }
+        if process.is_stopped() && process.pending_signals().get() & sig_bit(SIGKILL) == 0 {
+            process.wait_for_resume();
+            continue;
+        }
+        let blocked = task_ext.blocked_signals();
+        let Some(info) = task_ext
+            .pending_signals()
+            .take(blocked)
+            .or_else(|| process.pending_signals().take(blocked))
+        else {
+            break;
+        };
+        let signo = info.signo as u32;
+        let action = process.signal_actions().lock().get(signo);
+        match action.handler {
+            SIG_IGN => {}
+            SIG_DFL => match default_action(signo) {
+                DefaultAction::Ignore | DefaultAction::Continue => {}
+                DefaultAction::Stop => process.stop(signo),
+                DefaultAction::Terminate => exit_group(WaitStatus::Signaled {
+                    signo,
+                    core_dumped: false,
+                }),
+                DefaultAction::CoreDump => exit_group(WaitStatus::Signaled {
+                    signo,
+                    core_dumped: true,
+                }),
+            },
+            _ => {
+                let flags = action.flags();
+                if let Some(syscall) = restart.take() {
+                    if flags.contains(SigActionFlags::SA_RESTART) {
+                        restart_syscall(tf, &syscall);
+                    }
+                }
+                if flags.contains(SigActionFlags::SA_RESETHAND) {
+                    process
+                        .signal_actions()
+                        .lock()
+                        .set(signo, SigAction::default());
+                }
+                if setup_frame(tf, &action, &info, blocked).is_err() {
+                    warn!("failed to set up the frame of signal {}", signo);
+                    exit_group(WaitStatus::Signaled {
+                        signo: SIGSEGV,
+                        core_dumped: true,
+                    });
+                }
+                let mut mask = action.mask;
+                if !flags.contains(SigActionFlags::SA_NODEFER) {
+                    mask |= sig_bit(signo);
+                }
+                task_ext.set_blocked_signals(blocked | mask);
+                return;
+            }
+        }
+    }
+    if let Some(syscall) = restart {
+        restart_syscall(tf, &syscall);
+    }
+}
+
+/// Called before returning to user space, from system calls, interrupts and exceptions
+///
+/// When not returning from a system call, the time since last entering user space counts as user
+/// time, so that programs making no system calls are accounted too. RLIMIT_CPU is checked on every
+/// return, with or without system calls.
+#[register_trap_handler(USER_RETURN)]
+fn handle_user_return(tf: &mut TrapFrame) {
+    let syscall = current().task_ext().take_syscall_return();
+    if syscall.is_none() {
+        time_stat_from_user_to_kernel();
+    }
+    check_cpu_limit();
+    handle_pending_signals(tf, syscall);
+    time_stat_from_kernel_to_user();
+}
@@ -1,123 +1,147 @@
 use core::{
     alloc::Layout,
     cell::UnsafeCell,
-    sync::atomic::{AtomicU64, Ordering},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
 };
 
 use alloc::{
     string::{String, ToString},
-    sync::Arc,
-    vec::Vec,
+    sync::{Arc, Weak},
 };
-use core::cell::Cell;
-use arceos_posix_api::FD_TABLE;
-use axerrno::{AxError, AxResult};
+use arceos_posix_api::{FD_TABLE, close_on_exec};
+use axerrno::{AxError, AxResult, LinuxError, LinuxResult};
 use axfs::{CURRENT_DIR, CURRENT_DIR_PATH};
 use axhal::{
     arch::{TrapFrame, UspaceContext},
-    time::{NANOS_PER_MICROS, NANOS_PER_SEC, monotonic_time_nanos},
+    time::{NANOS_PER_MICROS, NANOS_PER_SEC},
 };
 use axmm::{AddrSpace, kernel_aspace};
 use axns::{AxNamespace, AxNamespaceIf};
 use axsync::Mutex;
-use axtask::{AxTaskRef, TaskExtRef, TaskInner, current};
-use memory_addr::VirtAddrRange;
+use axtask::{AxTaskRef, TaskExtRef, TaskInner, WaitQueue, current};
+use memory_addr::{PhysAddr, VirtAddrRange};
 use spin::Once;
 
 use crate::{
+    cred::Credentials,
     ctypes::{CloneFlags, TimeStat, WaitStatus},
-    mm::copy_from_kernel,
+    futex::{FUTEX_BITSET_MATCH_ANY, FutexKey, futex_wake},
+    mm::{copy_from_kernel, load_user_app, new_user_aspace_empty, write_user_value},
+    process::{ProcessData, alloc_pid, alloc_pid_at, take_leader_tid, unregister_thread},
+    resource::{Rlimits, check_nproc_limit, thread_usage},
+    signal::{PendingSignals, SIGCHLD, SignalActions, SignalStack, SyscallReturn, UNMASKABLE},
+    timer::charge_cpu_time,
 };
-#[derive(Debug, Clone, Copy, Default)]
-#[repr(C)]
-pub struct Rlimit{
-    pub rlim_cur: u32,
-    pub rlim_max: u32,
-}
-
 
+/// Arguments of [`TaskExt::clone_task`], shared by `clone` and `clone3`.
+#[derive(Debug, Clone, Copy)]
+pub struct CloneArgs {
+    /// The clone flags, without the exit signal.
+    pub flags: CloneFlags,
+    /// The signal sent to the parent when the child exits.
+    pub exit_signal: u32,
+    /// The user stack pointer of the child, or `None` to use the stack
+    /// pointer of the parent.
+    pub stack: Option<usize>,
+    /// Where to store the child TID in the parent if `CLONE_PARENT_SETTID`
+    /// is set.
+    pub ptid: usize,
+    /// The thread pointer of the child if `CLONE_SETTLS` is set.
+    pub tls: usize,
+    /// Where to store the child TID in the child if `CLONE_CHILD_SETTID` is
+    /// set, or to clear on exit if `CLONE_CHILD_CLEARTID` is set.
+    pub ctid: usize,
+    /// The TID to use for the child instead of allocating a new one.
+    pub set_tid: Option<usize>,
+}
 
 /// Task extended data for the monolithic kernel.
 pub struct TaskExt {
-    /// The process ID.
-    pub proc_id: usize,
-    /// The parent process ID.
-    pub parent_id: AtomicU64,
-    /// children process
-    pub children: Mutex<Vec<AxTaskRef>>,
+    /// The thread ID.
+    pub tid: usize,
+    /// The process (thread group) this thread belongs to.
+    pub process: Arc<ProcessData>,
     /// The clear thread tid field
     ///
     /// See <https://manpages.debian.org/unstable/manpages-dev/set_tid_address.2.en.html#clear_child_tid>
     ///
     /// When the thread exits, the kernel clears the word at this address if it is not NULL.
     clear_child_tid: AtomicU64,
+    /// The signals blocked by this thread
+    blocked_signals: AtomicU64,
+    /// The signals sent to this thread but not yet handled
+    pending_signals: PendingSignals,
+    /// The alternate signal stack of this thread
+    signal_stack: Mutex<SignalStack>,
+    /// The syscall that is returning to user space, if any
+    syscall_return: Mutex<Option<SyscallReturn>>,
+    /// Set if this thread is a `vfork` child which borrows the address
+    /// space of its parent
+    vfork_done: Option<Arc<VforkDone>>,
     /// The user space context.
     pub uctx: UspaceContext,
     /// The virtual memory address space.
     pub aspace: Arc<Mutex<AddrSpace>>,
     /// The resource namespace
     pub ns: AxNamespace,
+    /// The credentials, shared by the threads of the process
+    cred: Arc<Mutex<Credentials>>,
     /// The time statistics
     pub time: UnsafeCell<TimeStat>,
-    /// The user heap bottom
-    pub heap_bottom: AtomicU64,
-    /// The user heap top
-    pub heap_top: AtomicU64,
-    // The resource limit
-    // RLIMIT_AS：进程的最大虚拟内存大小（字节）。
-    pub rlimit_as: Rlimit,
-    // RLIMIT_CORE：核心转储文件（core dump）的最大大小。
-    pub rlimit_asc: Rlimit,
-    // RLIMIT_CPU：CPU 时间限制（秒）。
-    pub rlimit_cpu: Rlimit,
-    // RLIMIT_DATA：数据段的最大大小。
-    pub rlimit_data: Rlimit,
-    // RLIMIT_FSIZE：创建文件的最大大小。
-    pub rlimit_fsize: Rlimit,
-    // RLIMIT_NOFILE：打开文件描述符的最大数量。
-    pub rlimit_nofile: Cell<Rlimit>,
-    // RLIMIT_STACK：栈的最大大小。
-    pub rlimit_stack: Rlimit,
-
+    /// The number of page faults handled without reading a file
+    minflt: AtomicU64,
+    /// The number of page faults which read a file
+    majflt: AtomicU64,
 }
 
 impl TaskExt {
     pub fn new(
-        proc_id: usize,
+        tid: usize,
+        process: Arc<ProcessData>,
         uctx: UspaceContext,
         aspace: Arc<Mutex<AddrSpace>>,
-        heap_bottom: u64,
     ) -> Self {
         Self {
-            proc_id,
-            parent_id: AtomicU64::new(1),
-            children: Mutex::new(Vec::new()),
+            tid,
+            process,
             uctx,
             clear_child_tid: AtomicU64::new(0),
+            blocked_signals: AtomicU64::new(0),
+            pending_signals: PendingSignals::new(),
+            signal_stack: Mutex::new(SignalStack::default()),
+            syscall_return: Mutex::new(None),
+            vfork_done: None,
             aspace,
             ns: AxNamespace::new_thread_local(),
+            cred: Arc::new(Mutex::new(Credentials::root())),
             time: TimeStat::new().into(),
-            heap_bottom: AtomicU64::new(heap_bottom),
-            heap_top: AtomicU64::new(heap_bottom),
-            rlimit_as: Rlimit::default(),
-            rlimit_asc: Rlimit::default(),
-            rlimit_cpu: Rlimit::default(),
-            rlimit_data: Rlimit::default(),
-            rlimit_fsize: Rlimit::default(),
-            rlimit_stack: Rlimit::default(),
-            rlimit_nofile: Cell::new(Rlimit::default()),
+            minflt: AtomicU64::new(0),
+            majflt: AtomicU64::new(0),
         }
     }
 
-    pub fn clone_task(
-        &self,
-        flags: usize,
-        stack: Option<usize>,
-        _ptid: usize,
-        _tls: usize,
-        _ctid: usize,
-    ) -> AxResult<u64> {
-        let _clone_flags = CloneFlags::from_bits((flags & !0x3f) as u32).unwrap();
+    /// Creates a new thread or process as a copy of the current one.
+    ///
+    /// Returns the new task, which has already been spawned. If
+    /// `CLONE_VFORK` is set, this only returns after the child has called
+    /// `execve` or exited.
+    pub fn clone_task(&self, args: &CloneArgs) -> AxResult<AxTaskRef> {
+        let clone_flags = args.flags;
+        let tls = args.tls;
+        // A thread must share the signal handlers of its group, and signal
+        // handlers can only be shared within the same address space.
+        if (clone_flags.contains(CloneFlags::CLONE_THREAD)
+            && !clone_flags.contains(CloneFlags::CLONE_SIGHAND))
+            || (clone_flags.contains(CloneFlags::CLONE_SIGHAND)
+                && !clone_flags.contains(CloneFlags::CLONE_VM))
+        {
+            return Err(AxError::InvalidInput);
+        }
+        // No new threads while the others are being killed.
+        if clone_flags.contains(CloneFlags::CLONE_THREAD) && self.process.is_group_exiting() {
+            return Err(AxError::Interrupted);
+        }
+        check_nproc_limit(&self.process, &self.cred())?;
 
         let mut new_task = TaskInner::new(
             || {
@@ -134,44 +158,137 @@ impl TaskExt {
             current().id_name(),
             axconfig::plat::KERNEL_STACK_SIZE,
         );
+        // The scheduling policy, priority, nice value and CPU affinity are
+        // inherited, so that a thread or child runs like its creator.
+        let (policy, priority) = current().sched_params();
+        new_task.init_sched_params(policy, priority);
+        new_task.init_nice(current().nice());
+        new_task.set_cpumask(current().cpumask());
+        // The thread pointer is saved in the task context on these
+        // architectures, and in the trap frame on the others.
         #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
         new_task
             .ctx_mut()
-            .set_tls(axhal::arch::read_thread_pointer().into());
+            .set_tls(if clone_flags.contains(CloneFlags::CLONE_SETTLS) {
+                tls.into()
+            } else {
+                axhal::arch::read_thread_pointer().into()
+            });
 
         let current_task = current();
-        let mut current_aspace = current_task.task_ext().aspace.lock();
-        let mut new_aspace = current_aspace.clone_or_err()?;
-        copy_from_kernel(&mut new_aspace)?;
+        let new_aspace = if clone_flags.contains(CloneFlags::CLONE_VM) {
+            self.aspace.clone()
+        } else {
+            let mut current_aspace = self.aspace.lock();
+            let mut new_aspace = current_aspace.clone_or_err()?;
+            copy_from_kernel(&mut new_aspace)?;
+            Arc::new(Mutex::new(new_aspace))
+        };
         new_task
             .ctx_mut()
-            .set_page_table_root(new_aspace.page_table_root());
+            .set_page_table_root(new_aspace.lock().page_table_root());
 
         let trap_frame = read_trapframe_from_kstack(current_task.get_kernel_stack_top().unwrap());
         let mut new_uctx = UspaceContext::from(&trap_frame);
-        if let Some(stack) = stack {
+        if let Some(stack) = args.stack {
             new_uctx.set_sp(stack);
         }
-        // Skip current instruction
+        new_uctx.set_retval(0);
         #[cfg(any(target_arch = "riscv64", target_arch = "loongarch64"))]
+        if clone_flags.contains(CloneFlags::CLONE_SETTLS) {
+            new_uctx.regs.tp = tls;
+        }
+        let parent = if clone_flags.contains(CloneFlags::CLONE_THREAD) {
+            None
+        } else if clone_flags.contains(CloneFlags::CLONE_PARENT) {
+            Some(self.process.parent().ok_or(AxError::InvalidInput)?)
+        } else {
+            Some(self.process.clone())
+        };
+
+        let return_id = match args.set_tid {
+            Some(tid) => alloc_pid_at(tid)?,
+            None => alloc_pid()?,
+        } as u64;
+        // Errors writing the TIDs are ignored. The clone still succeeds, and
+        // the parent gets the TID as the return value anyway.
+        if clone_flags.contains(CloneFlags::CLONE_PARENT_SETTID) {
+            let _ = write_user_value(&mut self.aspace.lock(), args.ptid, return_id as u32);
+        }
+        if clone_flags.contains(CloneFlags::CLONE_CHILD_SETTID) {
+            let _ = write_user_value(&mut new_aspace.lock(), args.ctid, return_id as u32);
+        }
+        let process = match &parent {
+            None => self.process.clone(),
+            Some(parent) => ProcessData::new(
+                return_id as usize,
+                Arc::downgrade(parent),
+                args.exit_signal,
+                if clone_flags.contains(CloneFlags::CLONE_SIGHAND) {
+                    self.process.signal_actions().clone()
+                } else {
+                    Arc::new(Mutex::new(self.process.signal_actions().lock().clone()))
+                },
+                self.process.get_heap_bottom(),
+                self.process.get_heap_top(),
+                self.process.rlimits(),
+            ),
+        };
+
+        let mut new_task_ext =
+            TaskExt::new(return_id as usize, process.clone(), new_uctx, new_aspace);
+        new_task_ext.set_blocked_signals(self.blocked_signals());
+        new_task_ext.cred = if clone_flags.contains(CloneFlags::CLONE_THREAD) {
+            self.cred.clone()
+        } else {
+            let cred = self.cred();
+            process.set_uid(cred.uid);
+            Arc::new(Mutex::new(cred))
+        };
+        // A thread sharing the address space may not use the same stack.
+        if !clone_flags.contains(CloneFlags::CLONE_VM)
+            || clone_flags.contains(CloneFlags::CLONE_VFORK)
         {
-            let current_ip = new_uctx.ip();
-            new_uctx.set_ip(current_ip + 4);
+            new_task_ext.set_signal_stack(self.signal_stack());
         }
-        // new_uctx.set_ip(new_uctx.ip() + 4);
-        new_uctx.set_retval(0);
-        let return_id: u64 = new_task.id().as_u64();
-        let new_task_ext = TaskExt::new(
-            return_id as usize,
-            new_uctx,
-            Arc::new(Mutex::new(new_aspace)),
-            axconfig::plat::USER_HEAP_BASE as _,
-        );
-        new_task_ext.ns_init_new();
+        if clone_flags.contains(CloneFlags::CLONE_CHILD_CLEARTID) {
+            new_task_ext.set_clear_child_tid(args.ctid as u64);
+        }
+        let vfork_done = clone_flags
+            .contains(CloneFlags::CLONE_VFORK)
+            .then(|| Arc::new(VforkDone::new()));
+        new_task_ext.vfork_done = vfork_done.clone();
+        new_task_ext.ns_init_new(clone_flags);
         new_task.init_task_ext(new_task_ext);
-        let new_task_ref = axtask::spawn_task(new_task);
-        current_task.task_ext().children.lock().push(new_task_ref);
-        Ok(return_id)
+        if let Some(parent) = parent {
+            parent.children.lock().push(process.clone());
+        }
+        let new_task = process.spawn_thread(new_task);
+        if let Some(vfork_done) = vfork_done {
+            vfork_done.wait(&self.process);
+        }
+        Ok(new_task)
+    }
+
+    /// Wakes up the `vfork` parent, if any, as this thread no longer uses the
+    /// borrowed address space.
+    fn release_vfork_parent(&mut self) {
+        if let Some(vfork_done) = self.vfork_done.take() {
+            vfork_done.complete();
+        }
+    }
+
+    /// Switches the current thread to a new address space.
+    ///
+    /// Must be called by the thread that owns this [`TaskExt`].
+    fn switch_aspace(&mut self, aspace: Arc<Mutex<AddrSpace>>) {
+        let root = aspace.lock().page_table_root();
+        let old_aspace = core::mem::replace(&mut self.aspace, aspace);
+        unsafe {
+            current().ctx_mut_unchecked().set_page_table_root(root);
+            write_user_page_table_root(root);
+        }
+        release_aspace(&old_aspace);
     }
 
     pub fn clear_child_tid(&self) -> u64 {
@@ -184,46 +301,110 @@ impl TaskExt {
             .store(clear_child_tid, core::sync::atomic::Ordering::Relaxed);
     }
 
-    pub fn get_parent(&self) -> u64 {
-        self.parent_id.load(Ordering::Acquire)
+    /// Clears the TID at `clear_child_tid` and wakes up a futex waiter on it,
+    /// which is how `pthread_join` learns that the thread has exited.
+    fn do_clear_child_tid(&self) {
+        let addr = self.clear_child_tid() as usize;
+        if addr == 0 {
+            return;
+        }
+        self.set_clear_child_tid(0);
+        if write_user_value(&mut self.aspace.lock(), addr, 0u32).is_ok() {
+            futex_wake(FutexKey::new_current(addr), 1, FUTEX_BITSET_MATCH_ANY);
+        }
     }
-    
 
-    pub fn set_parent(&self, parent_id: u64) {
-        self.parent_id.store(parent_id, Ordering::Release);
+    /// Returns the signals blocked by this thread.
+    pub fn blocked_signals(&self) -> u64 {
+        self.blocked_signals
+            .load(core::sync::atomic::Ordering::Acquire)
     }
-    pub fn set_rlimit_nofile(&self, new_value: Rlimit) {
-        self.rlimit_nofile.set(new_value);
+
+    /// Sets the signals blocked by this thread. `SIGKILL` and `SIGSTOP` can
+    /// never be blocked.
+    pub fn set_blocked_signals(&self, blocked: u64) {
+        self.blocked_signals
+            .store(blocked & !UNMASKABLE, core::sync::atomic::Ordering::Release);
     }
 
-    pub fn get_rlimit_nofile(&self) -> Rlimit {
-        self.rlimit_nofile.get()
+    /// Returns the signals sent to this thread but not yet handled.
+    pub fn pending_signals(&self) -> &PendingSignals {
+        &self.pending_signals
     }
-    
-    fn ns_init_new(&self) {
-        FD_TABLE
-            .deref_from(&self.ns)
-            .init_new(FD_TABLE.copy_inner());
-        CURRENT_DIR
-            .deref_from(&self.ns)
-            .init_new(CURRENT_DIR.copy_inner());
-        CURRENT_DIR_PATH
-            .deref_from(&self.ns)
-            .init_new(CURRENT_DIR_PATH.copy_inner());
+
+    /// Returns the alternate signal stack of this thread.
+    pub fn signal_stack(&self) -> SignalStack {
+        *self.signal_stack.lock()
     }
 
-    pub(crate) fn time_stat_from_kernel_to_user(&self, current_tick: usize) {
-        let time = self.time.get();
-        unsafe {
-            (*time).switch_into_user_mode(current_tick);
+    /// Sets the alternate signal stack of this thread.
+    pub fn set_signal_stack(&self, stack: SignalStack) {
+        *self.signal_stack.lock() = stack;
+    }
+
+    /// Records the syscall that is about to return to user space, so that
+    /// it can be restarted after a signal interrupting it is handled.
+    pub fn set_syscall_return(&self, syscall: SyscallReturn) {
+        *self.syscall_return.lock() = Some(syscall);
+    }
+
+    /// Takes the syscall recorded by [`TaskExt::set_syscall_return`].
+    pub(crate) fn take_syscall_return(&self) -> Option<SyscallReturn> {
+        self.syscall_return.lock().take()
+    }
+
+    /// Returns the credentials of the process.
+    pub fn cred(&self) -> Credentials {
+        self.cred.lock().clone()
+    }
+
+    /// Changes the credentials of the process with `f`. They are left
+    /// unchanged if `f` fails.
+    pub fn update_cred<R>(
+        &self,
+        f: impl FnOnce(&mut Credentials) -> LinuxResult<R>,
+    ) -> LinuxResult<R> {
+        let mut cred = self.cred.lock();
+        let mut new_cred = cred.clone();
+        let ret = f(&mut new_cred)?;
+        self.process.set_uid(new_cred.uid);
+        *cred = new_cred;
+        Ok(ret)
+    }
+
+    fn ns_init_new(&self, flags: CloneFlags) {
+        if flags.contains(CloneFlags::CLONE_FILES) {
+            FD_TABLE.deref_from(&self.ns).init_shared(FD_TABLE.share());
+        } else {
+            FD_TABLE
+                .deref_from(&self.ns)
+                .init_new(FD_TABLE.copy_inner());
+        }
+        if flags.contains(CloneFlags::CLONE_FS) {
+            CURRENT_DIR
+                .deref_from(&self.ns)
+                .init_shared(CURRENT_DIR.share());
+            CURRENT_DIR_PATH
+                .deref_from(&self.ns)
+                .init_shared(CURRENT_DIR_PATH.share());
+        } else {
+            CURRENT_DIR
+                .deref_from(&self.ns)
+                .init_new(CURRENT_DIR.copy_inner());
+            CURRENT_DIR_PATH
+                .deref_from(&self.ns)
+                .init_new(CURRENT_DIR_PATH.copy_inner());
         }
     }
 
-    pub(crate) fn time_stat_from_user_to_kernel(&self, current_tick: usize) {
+    pub(crate) fn time_stat_from_kernel_to_user(&self, current_tick: usize) -> usize {
         let time = self.time.get();
-        unsafe {
-            (*time).switch_into_kernel_mode(current_tick);
-        }
+        unsafe { (*time).switch_into_user_mode(current_tick) }
+    }
+
+    pub(crate) fn time_stat_from_user_to_kernel(&self, current_tick: usize) -> usize {
+        let time = self.time.get();
+        unsafe { (*time).switch_into_kernel_mode(current_tick) }
     }
 
     pub(crate) fn time_stat_output(&self) -> (usize, usize) {
@@ -231,20 +412,34 @@ impl TaskExt {
         unsafe { (*time).output() }
     }
 
+    /// Counts a page fault handled for this thread.
+    pub(crate) fn count_page_fault(&self, major: bool) {
+        let counter = if major { &self.majflt } else { &self.minflt };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the numbers of minor and major page faults of this thread.
+    pub(crate) fn page_faults(&self) -> (u64, u64) {
+        (
+            self.minflt.load(Ordering::Relaxed),
+            self.majflt.load(Ordering::Relaxed),
+        )
+    }
+
     pub fn get_heap_bottom(&self) -> u64 {
-        self.heap_bottom.load(Ordering::Acquire)
+        self.process.get_heap_bottom()
     }
 
     pub fn set_heap_bottom(&self, bottom: u64) {
-        self.heap_bottom.store(bottom, Ordering::Release)
+        self.process.set_heap_bottom(bottom)
     }
 
     pub fn get_heap_top(&self) -> u64 {
-        self.heap_top.load(Ordering::Acquire)
+        self.process.get_heap_top()
     }
 
     pub fn set_heap_top(&self, top: u64) {
-        self.heap_top.store(top, Ordering::Release)
+        self.process.set_heap_top(top)
     }
 }
 
@@ -273,14 +468,68 @@ impl AxNamespaceIf for AxNamespaceImpl {
 
 impl Drop for TaskExt {
     fn drop(&mut self) {
-        if !cfg!(target_arch = "aarch64") && !cfg!(target_arch = "loongarch64") {
-            // See [`crate::new_user_aspace`]
-            let kernel = kernel_aspace().lock();
-            self.aspace
-                .lock()
-                .clear_mappings(VirtAddrRange::from_start_size(kernel.base(), kernel.size()));
+        release_aspace(&self.aspace);
+    }
+}
+
+/// Prepares an address space to be dropped with its last reference.
+fn release_aspace(aspace: &Arc<Mutex<AddrSpace>>) {
+    // The address space may still be used by other threads.
+    if !cfg!(target_arch = "aarch64")
+        && !cfg!(target_arch = "loongarch64")
+        && Arc::strong_count(aspace) == 1
+    {
+        // See [`crate::new_user_aspace`]
+        let kernel = kernel_aspace().lock();
+        aspace
+            .lock()
+            .clear_mappings(VirtAddrRange::from_start_size(kernel.base(), kernel.size()));
+    }
+}
+
+/// Writes the root of the user page table of the current CPU.
+///
+/// # Safety
+///
+/// `root` must be the page table root of a valid user address space.
+unsafe fn write_user_page_table_root(root: PhysAddr) {
+    // ARMv8 and LoongArch64 have separate page table roots for user space.
+    #[cfg(any(target_arch = "aarch64", target_arch = "loongarch64"))]
+    unsafe {
+        axhal::arch::write_page_table_root0(root);
+    }
+    #[cfg(not(any(target_arch = "aarch64", target_arch = "loongarch64")))]
+    unsafe {
+        axhal::arch::write_page_table_root(root);
+    }
+}
+
+/// Completion of a `vfork` child, which wakes up the parent once the child
+/// calls `execve` or exits.
+struct VforkDone {
+    done: AtomicBool,
+    wq: WaitQueue,
+}
+
+impl VforkDone {
+    fn new() -> Self {
+        Self {
+            done: AtomicBool::new(false),
+            wq: WaitQueue::new(),
         }
     }
+
+    fn complete(&self) {
+        self.done.store(true, Ordering::Release);
+        self.wq.notify_all(false);
+    }
+
+    /// Blocks the parent until the child completes, or the parent process is
+    /// being torn down.
+    fn wait(&self, parent: &ProcessData) {
+        self.wq
+            .wait_until(|| self.done.load(Ordering::Acquire) || parent.is_group_exiting());
+    }
 }
 
 axtask::def_task_ext!(TaskExt);
@@ -307,14 +556,19 @@ pub fn spawn_user_task(
     );
     task.ctx_mut()
         .set_page_table_root(aspace.lock().page_table_root());
-    task.init_task_ext(TaskExt::new(
-        task.id().as_u64() as usize,
-        uctx,
-        aspace,
+    let pid = alloc_pid().expect("Failed to allocate PID");
+    let process = ProcessData::new(
+        pid,
+        Weak::new(),
+        SIGCHLD,
+        Arc::new(Mutex::new(SignalActions::new())),
+        heap_bottom,
         heap_bottom,
-    ));
-    task.task_ext().ns_init_new();
-    axtask::spawn_task(task)
+        Rlimits::default(),
+    );
+    task.init_task_ext(TaskExt::new(pid, process.clone(), uctx, aspace));
+    task.task_ext().ns_init_new(CloneFlags::empty());
+    process.spawn_thread(task)
 }
 
 #[allow(unused)]
@@ -332,95 +586,101 @@ pub fn read_trapframe_from_kstack(kstack_top: usize) -> TrapFrame {
     unsafe { *trap_frame_ptr }
 }
 
-/// # Safety
+/// Exits the current thread with the given exit code.
 ///
-/// The caller must ensure that the pointer is valid and properly aligned if it's not null.
-pub unsafe fn wait_pid(pid: i32, exit_code_ptr: *mut i32) -> Result<u64, WaitStatus> {
-    let curr_task = current();
-    let mut exit_task_id: usize = 0;
-    let mut answer_id: u64 = 0;
-    let mut answer_status = WaitStatus::NotExist;
-
-    for (index, child) in curr_task.task_ext().children.lock().iter().enumerate() {
-        if pid <= 0 {
-            if pid == 0 {
-                warn!("Don't support for process group.");
-            }
-
-            answer_status = WaitStatus::Running;
-            if child.state() == axtask::TaskState::Exited {
-                let exit_code = child.exit_code();
-                answer_status = WaitStatus::Exited;
-                info!(
-                    "wait pid _{}_ with code _{}_",
-                    child.id().as_u64(),
-                    exit_code
-                );
-                exit_task_id = index;
-                if !exit_code_ptr.is_null() {
-                    unsafe {
-                        *exit_code_ptr = exit_code << 8;
-                    }
-                }
-                answer_id = child.id().as_u64();
-                break;
-            }
-        } else if child.id().as_u64() == pid as u64 {
-            if let Some(exit_code) = child.join() {
-                answer_status = WaitStatus::Exited;
-                info!(
-                    "wait pid _{}_ with code _{:?}_",
-                    child.id().as_u64(),
-                    exit_code
-                );
-                exit_task_id = index;
-                if !exit_code_ptr.is_null() {
-                    unsafe {
-                        *exit_code_ptr = exit_code << 8;
-                    }
-                }
-                answer_id = child.id().as_u64();
-            } else {
-                answer_status = WaitStatus::Running;
-            }
-            break;
-        }
-    }
+/// If it is the last thread of its process, the process becomes a zombie
+/// and stays in its parent's children list until it is reaped.
+pub fn do_exit(exit_code: i32) -> ! {
+    exit_current(WaitStatus::Exited(exit_code))
+}
 
-    if answer_status == WaitStatus::Running {
-        axtask::yield_now();
-    }
+/// Exits all threads of the current process with the given status.
+///
+/// The other threads exit when they next return to user space, and the
+/// status is reported to the parent once the last thread has exited.
+pub fn exit_group(status: WaitStatus) -> ! {
+    let status = current().task_ext().process.start_group_exit(status);
+    exit_current(status)
+}
 
-    if answer_status == WaitStatus::Exited {
-        curr_task.task_ext().children.lock().remove(exit_task_id);
-        return Ok(answer_id);
+/// Exits the current thread, reporting `status` to the parent if it is the
+/// last thread of its process.
+///
+/// If the process is being torn down by [`exit_group`], the status of the
+/// group exit is reported instead.
+pub fn exit_current(status: WaitStatus) -> ! {
+    let curr = current();
+    let task_ext = unsafe { &mut *(curr.task_ext_ptr() as *mut TaskExt) };
+    task_ext.do_clear_child_tid();
+    task_ext.release_vfork_parent();
+    let process = &task_ext.process;
+    let status = process.group_exit_status().unwrap_or(status);
+    process.add_thread_usage(&thread_usage(curr.as_task_ref()));
+    unregister_thread(task_ext.tid, process.pid);
+    if process.exit_thread(curr.as_task_ref()) {
+        process.update_maxrss(task_ext.aspace.lock().resident_size() / 1024);
+        process.set_zombie(status);
     }
-    Err(answer_status)
+    let exit_code = match status {
+        WaitStatus::Exited(code) => code,
+        _ => status.to_wstatus(),
+    };
+    axtask::exit(exit_code);
 }
 
-pub fn exec(name: &str, args: &[String], envs: &[String]) -> AxResult<()> {
+/// Replaces the program of the current process with the one at `path`.
+///
+/// The other threads of the process are killed, and the calling thread
+/// becomes the group leader with the PID as its TID. Files marked
+/// close-on-exec are closed, caught signals are reset to their default
+/// actions, the alternate signal stack is disabled and the credentials are
+/// updated by [`Credentials::exec`].
+///
+/// Only returns if the new program cannot be loaded, or the process is
+/// exiting.
+pub fn exec(path: &str, args: &[String], envs: &[String]) -> LinuxResult<()> {
     let current_task = current();
 
-    let program_name = name.to_string();
-
-    let mut aspace = current_task.task_ext().aspace.lock();
-    if Arc::strong_count(&current_task.task_ext().aspace) != 1 {
-        warn!("Address space is shared by multiple tasks, exec is not supported.");
-        return Err(AxError::Unsupported);
-    }
+    let program_name = path.to_string();
 
-    aspace.unmap_user_areas()?;
-    axhal::arch::flush_tlb(None);
+    let task_ext = unsafe { &mut *(current_task.task_ext_ptr() as *mut TaskExt) };
 
-    let (entry_point, user_stack_base) = crate::mm::load_user_app(&mut aspace, args, envs)
-        .map_err(|_| {
-            error!("Failed to load app {}", program_name);
-            AxError::NotFound
+    // The new program is loaded into a fresh address space, so that the
+    // current one is left intact if loading fails. It may also be borrowed
+    // from the parent (e.g. by `vfork`).
+    let mut aspace = new_user_aspace_empty()?;
+    copy_from_kernel(&mut aspace)?;
+    let mut cred = task_ext.cred();
+    cred.exec();
+    let rlimits = task_ext.process.rlimits();
+    let (entry_point, user_stack_base) =
+        load_user_app(&mut aspace, path, args, envs, &cred, &rlimits).inspect_err(|err| {
+            error!("Failed to load app {}: {:?}", program_name, err);
         })?;
+
+    // Point of no return, unless another thread is exiting the process.
+    let process = task_ext.process.clone();
+    process.kill_other_threads(task_ext.tid)?;
+    if task_ext.tid != process.pid {
+        take_leader_tid(current_task.as_task_ref(), task_ext.tid, process.pid);
+        task_ext.tid = process.pid;
+    }
+
+    // The TID is cleared in the old address space, which may be shared with
+    // a `vfork` parent or with threads waiting on it, before it is replaced.
+    task_ext.do_clear_child_tid();
+    process.update_maxrss(task_ext.aspace.lock().resident_size() / 1024);
+    task_ext.switch_aspace(Arc::new(Mutex::new(aspace)));
     current_task.set_name(&program_name);
-    drop(aspace);
+    process.set_exec_done();
+    task_ext.release_vfork_parent();
+
+    *task_ext.cred.lock() = cred;
+    close_on_exec();
+    process.posix_timers().clear();
+    process.reset_signal_actions();
+    task_ext.set_signal_stack(SignalStack::default());
 
-    let task_ext = unsafe { &mut *(current_task.task_ext_ptr() as *mut TaskExt) };
     task_ext.uctx = UspaceContext::new(entry_point.as_usize(), user_stack_base, 0);
 
     unsafe {
@@ -432,18 +692,20 @@ pub fn exec(name: &str, args: &[String], envs: &[String]) -> AxResult<()> {
     }
 }
 
+/// Accounts the time spent in kernel mode before returning to user mode.
 pub fn time_stat_from_kernel_to_user() {
     let curr_task = current();
-    curr_task
-        .task_ext()
-        .time_stat_from_kernel_to_user(monotonic_time_nanos() as usize);
+    let task_ext = curr_task.task_ext();
+    let stime_ns = task_ext.time_stat_from_kernel_to_user(curr_task.cpu_time_ns() as usize);
+    charge_cpu_time(&task_ext.process, task_ext.tid, 0, stime_ns as u64);
 }
 
+/// Accounts the time spent in user mode after entering kernel mode.
 pub fn time_stat_from_user_to_kernel() {
     let curr_task = current();
-    curr_task
-        .task_ext()
-        .time_stat_from_user_to_kernel(monotonic_time_nanos() as usize);
+    let task_ext = curr_task.task_ext();
+    let utime_ns = task_ext.time_stat_from_user_to_kernel(curr_task.cpu_time_ns() as usize);
+    charge_cpu_time(&task_ext.process, task_ext.tid, utime_ns as u64, 0);
 }
 
 pub fn time_stat_output() -> (usize, usize, usize, usize) {
@@ -0,0 +1,200 @@
+//! The clocks of `clock_gettime` and friends.
+//!
+//! All the clocks are read from the hardware counter: the real-time clock is
+//! the monotonic clock plus an offset, which can be changed by
+//! `clock_settime`, and the CPU-time clocks are summed up from the CPU time
+//! of threads. The coarse clocks are the same as the precise ones, like in
+//! the vDSO.
+//!
+//! Besides the fixed clock IDs, negative IDs name the CPU-time clock of any
+//! process or thread, as returned by `clock_getcpuclockid` and
+//! `pthread_getcpuclockid`. They encode the bitwise NOT of the PID or TID
+//! above the low 3 bits, which select a thread and what is measured.
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicI64, Ordering};
+
+use axhal::time::{epochoffset_nanos, monotonic_time_nanos, ticks_to_nanos};
+use axtask::{AxTaskRef, TaskExtRef, current};
+
+use crate::{
+    process::{ProcessData, find_process, find_thread},
+    resource::{process_cpu_time_ns, process_usage, thread_cpu_time_ns},
+    vdso::update_vvar,
+};
+
+/// The settable wall-clock time.
+pub const CLOCK_REALTIME: u32 = 0;
+/// The time since boot.
+pub const CLOCK_MONOTONIC: u32 = 1;
+/// The CPU time of the calling process.
+pub const CLOCK_PROCESS_CPUTIME_ID: u32 = 2;
+/// The CPU time of the calling thread.
+pub const CLOCK_THREAD_CPUTIME_ID: u32 = 3;
+/// The time since boot, without frequency adjustments.
+pub const CLOCK_MONOTONIC_RAW: u32 = 4;
+/// A faster but less precise [`CLOCK_REALTIME`].
+pub const CLOCK_REALTIME_COARSE: u32 = 5;
+/// A faster but less precise [`CLOCK_MONOTONIC`].
+pub const CLOCK_MONOTONIC_COARSE: u32 = 6;
+/// The time since boot, including the time suspended.
+pub const CLOCK_BOOTTIME: u32 = 7;
+
+/// The bits of a CPU-time clock ID selecting what is measured.
+const CPUCLOCK_CLOCK_MASK: i32 = 3;
+/// The bit of a CPU-time clock ID set for the clock of a thread.
+const CPUCLOCK_PERTHREAD_MASK: i32 = 4;
+/// A CPU-time clock measuring the user and system time.
+const CPUCLOCK_PROF: i32 = 0;
+/// A CPU-time clock measuring the user time only.
+const CPUCLOCK_VIRT: i32 = 1;
+/// A CPU-time clock measuring the scheduled time, which is the user and
+/// system time here.
+const CPUCLOCK_SCHED: i32 = 2;
+
+/// The flag of `clock_nanosleep` and `timer_settime` to use an absolute
+/// time.
+pub const TIMER_ABSTIME: u32 = 1;
+
+/// The adjustment of the real-time clock made by `clock_settime`, on top of
+/// the offset read from the RTC at boot.
+static REALTIME_ADJUST_NS: AtomicI64 = AtomicI64::new(0);
+
+/// Returns the offset (in nanoseconds) from the monotonic clock to the
+/// real-time clock.
+pub fn realtime_offset_nanos() -> u64 {
+    epochoffset_nanos().wrapping_add_signed(REALTIME_ADJUST_NS.load(Ordering::Relaxed))
+}
+
+/// Returns the current time (in nanoseconds) of the real-time clock.
+pub fn realtime_nanos() -> u64 {
+    monotonic_time_nanos().wrapping_add(realtime_offset_nanos())
+}
+
+/// Sets the real-time clock to `ns` nanoseconds since the epoch.
+pub fn set_realtime_nanos(ns: u64) {
+    let offset = ns.wrapping_sub(monotonic_time_nanos());
+    REALTIME_ADJUST_NS.store(
+        offset.wrapping_sub(epochoffset_nanos()) as i64,
+        Ordering::Relaxed,
+    );
+    update_vvar();
+}
+
+/// Returns the resolution (in nanoseconds) of all the clocks.
+pub fn clock_resolution_nanos() -> u64 {
+    ticks_to_nanos(1).max(1)
+}
+
+/// What a CPU-time clock measures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuTime {
+    /// The user and system time.
+    Total,
+    /// The user time only.
+    User,
+}
+
+impl CpuTime {
+    /// Returns the CPU time (in nanoseconds) consumed by all the threads of
+    /// `process`, including the exited ones.
+    pub(crate) fn process_nanos(self, process: &ProcessData) -> u64 {
+        match self {
+            Self::Total => process_cpu_time_ns(process),
+            Self::User => process_usage(process).utime_ns as u64,
+        }
+    }
+
+    /// Returns the CPU time (in nanoseconds) consumed by the thread `task`.
+    pub(crate) fn thread_nanos(self, task: &AxTaskRef) -> u64 {
+        match self {
+            Self::Total => thread_cpu_time_ns(task),
+            Self::User => task.task_ext().time_stat_output().0 as u64,
+        }
+    }
+}
+
+/// A clock that can be read by `clock_gettime`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clock {
+    /// [`CLOCK_REALTIME`] and [`CLOCK_REALTIME_COARSE`].
+    Realtime,
+    /// [`CLOCK_MONOTONIC`], [`CLOCK_MONOTONIC_RAW`],
+    /// [`CLOCK_MONOTONIC_COARSE`] and [`CLOCK_BOOTTIME`], which are the same
+    /// since there is neither frequency adjustment nor suspension.
+    Monotonic,
+    /// The CPU time of the process with the PID, which is the current one
+    /// for [`CLOCK_PROCESS_CPUTIME_ID`].
+    ProcessCpu(usize, CpuTime),
+    /// The CPU time of the thread with the TID, which is the current one for
+    /// [`CLOCK_THREAD_CPUTIME_ID`].
+    ThreadCpu(usize, CpuTime),
+}
+
+impl Clock {
+    /// Returns the clock with the ID `id`, as seen by the current thread, or
+    /// `None` if it is not supported.
+    ///
+    /// The CPU-time clock of a thread can only be used within its process,
+    /// and that of a process or thread which does not exist is rejected.
+    pub fn from_id(id: i32) -> Option<Self> {
+        if id < 0 {
+            return Self::from_cpu_clock_id(id);
+        }
+        let curr = current();
+        let task_ext = curr.task_ext();
+        match id as u32 {
+            CLOCK_REALTIME | CLOCK_REALTIME_COARSE => Some(Self::Realtime),
+            CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_MONOTONIC_COARSE | CLOCK_BOOTTIME => {
+                Some(Self::Monotonic)
+            }
+            CLOCK_PROCESS_CPUTIME_ID => {
+                Some(Self::ProcessCpu(task_ext.process.pid, CpuTime::Total))
+            }
+            CLOCK_THREAD_CPUTIME_ID => Some(Self::ThreadCpu(task_ext.tid, CpuTime::Total)),
+            _ => None,
+        }
+    }
+
+    /// Decodes the negative ID of a CPU-time clock, where a PID or TID of 0
+    /// stands for the current process or thread.
+    fn from_cpu_clock_id(id: i32) -> Option<Self> {
+        let cpu_time = match id & CPUCLOCK_CLOCK_MASK {
+            CPUCLOCK_PROF | CPUCLOCK_SCHED => CpuTime::Total,
+            CPUCLOCK_VIRT => CpuTime::User,
+            _ => return None,
+        };
+        let curr = current();
+        let task_ext = curr.task_ext();
+        let id_num = !(id >> 3) as usize;
+        if id & CPUCLOCK_PERTHREAD_MASK != 0 {
+            let tid = if id_num == 0 { task_ext.tid } else { id_num };
+            find_thread(tid)
+                .filter(|thread| Arc::ptr_eq(&thread.task_ext().process, &task_ext.process))?;
+            Some(Self::ThreadCpu(tid, cpu_time))
+        } else {
+            let pid = if id_num == 0 {
+                task_ext.process.pid
+            } else {
+                id_num
+            };
+            find_process(pid)?;
+            Some(Self::ProcessCpu(pid, cpu_time))
+        }
+    }
+
+    /// Returns the current time (in nanoseconds) of the clock, or 0 for the
+    /// CPU-time clock of a process or thread which has gone.
+    pub fn now_nanos(self) -> u64 {
+        match self {
+            Self::Realtime => realtime_nanos(),
+            Self::Monotonic => monotonic_time_nanos(),
+            Self::ProcessCpu(pid, cpu_time) => {
+                find_process(pid).map_or(0, |process| cpu_time.process_nanos(&process))
+            }
+            Self::ThreadCpu(tid, cpu_time) => {
+                find_thread(tid).map_or(0, |thread| cpu_time.thread_nanos(&thread))
+            }
+        }
+    }
+}
@@ -0,0 +1,546 @@
+//! Timers of processes.
+//!
+//! Kernel timers run their callbacks on a dedicated timer thread at their
+//! deadlines, so they also expire while the process that set them is
+//! sleeping. They are used by `ITIMER_REAL`.
+//!
+//! `ITIMER_VIRTUAL` and `ITIMER_PROF` count down the CPU time of a process,
+//! which is charged by [`charge_cpu_time`] whenever one of its threads
+//! switches between user and kernel mode.
+//!
+//! POSIX timers (`timer_create`) work the same way, on kernel timers for
+//! `CLOCK_REALTIME` and `CLOCK_MONOTONIC`, and on charged CPU time for the
+//! CPU-time clocks.
+
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use axerrno::{AxError, AxResult};
+use axhal::time::monotonic_time_nanos;
+use axsync::Mutex;
+use axtask::{TaskExtRef, WaitQueue};
+use spin::Once;
+
+use crate::{
+    ctypes::TimerType,
+    process::{ProcessData, find_thread},
+    signal::{
+        SIGALRM, SIGPROF, SIGVTALRM, SigInfo, send_signal, send_signal_info, send_thread_signal,
+    },
+    time::{CpuTime, realtime_nanos},
+};
+
+type TimerCallback = Box<dyn FnOnce() + Send>;
+
+/// The pending kernel timers, by deadline and then by the order they were
+/// set.
+static TIMERS: Mutex<BTreeMap<(u64, u64), TimerCallback>> = Mutex::new(BTreeMap::new());
+static TIMER_ID: AtomicU64 = AtomicU64::new(0);
+/// Counts changes of [`TIMERS`], used to detect missed wakeups.
+static TIMER_EVENTS: AtomicU64 = AtomicU64::new(0);
+static TIMER_WQ: WaitQueue = WaitQueue::new();
+static TIMER_THREAD: Once = Once::new();
+
+/// Runs `callback` on the timer thread once the monotonic time reaches
+/// `deadline_ns`.
+///
+/// A timer cannot be cancelled, so the callback has to check whether it is
+/// still wanted.
+pub fn set_kernel_timer(deadline_ns: u64, callback: impl FnOnce() + Send + 'static) {
+    TIMER_THREAD.call_once(|| {
+        axtask::spawn_raw(timer_thread, "timer".into(), axconfig::TASK_STACK_SIZE);
+    });
+    let id = TIMER_ID.fetch_add(1, Ordering::Relaxed);
+    TIMERS.lock().insert((deadline_ns, id), Box::new(callback));
+    TIMER_EVENTS.fetch_add(1, Ordering::Release);
+    TIMER_WQ.notify_one(false);
+}
+
+fn timer_thread() {
+    loop {
+        let seen = TIMER_EVENTS.load(Ordering::Acquire);
+        let now = monotonic_time_nanos();
+        let mut timers = TIMERS.lock();
+        let next = timers.first_key_value().map(|(&(deadline, _), _)| deadline);
+        match next {
+            Some(deadline) if deadline <= now => {
+                let (_, callback) = timers.pop_first().unwrap();
+                drop(timers);
+                callback();
+            }
+            Some(deadline) => {
+                drop(timers);
+                TIMER_WQ.wait_timeout_until(Duration::from_nanos(deadline - now), || {
+                    TIMER_EVENTS.load(Ordering::Acquire) != seen
+                });
+            }
+            None => {
+                drop(timers);
+                TIMER_WQ.wait_until(|| TIMER_EVENTS.load(Ordering::Acquire) != seen);
+            }
+        }
+    }
+}
+
+/// The value of an interval timer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ITimerValue {
+    /// The period (in nanoseconds) to reload the timer with when it
+    /// expires, or 0 for a one-shot timer.
+    pub interval_ns: u64,
+    /// The time (in nanoseconds) until the timer expires, or 0 if it is
+    /// disarmed.
+    pub value_ns: u64,
+}
+
+impl ITimerValue {
+    /// Counts down `ns` of CPU time. Returns how many times the timer has
+    /// expired, in which case it is reloaded from the interval.
+    fn count_down(&mut self, ns: u64) -> u64 {
+        if self.value_ns == 0 {
+            return 0;
+        }
+        if self.value_ns > ns {
+            self.value_ns -= ns;
+            return 0;
+        }
+        let overrun = ns - self.value_ns;
+        match self.interval_ns {
+            0 => {
+                self.value_ns = 0;
+                1
+            }
+            interval => {
+                self.value_ns = interval - overrun % interval;
+                overrun / interval + 1
+            }
+        }
+    }
+}
+
+/// Returns the next deadline of a periodic kernel timer which expired at
+/// `deadline`, and the number of periods missed since then.
+fn next_deadline(deadline: u64, interval: u64) -> (u64, u64) {
+    let missed = monotonic_time_nanos().saturating_sub(deadline) / interval;
+    (deadline + (missed + 1) * interval, missed)
+}
+
+#[derive(Default)]
+struct ITimersInner {
+    /// `ITIMER_REAL`, whose value is the monotonic deadline instead.
+    real: ITimerValue,
+    /// Incremented whenever `ITIMER_REAL` is set, which invalidates the
+    /// kernel timers of the previous settings.
+    real_generation: u64,
+    virt: ITimerValue,
+    prof: ITimerValue,
+}
+
+/// The interval timers of a process, kept across `execve` but not
+/// inherited by children.
+#[derive(Default)]
+pub struct ITimers {
+    inner: Mutex<ITimersInner>,
+    /// Whether `ITIMER_VIRTUAL` or `ITIMER_PROF` is armed.
+    cpu_armed: AtomicBool,
+}
+
+impl ITimers {
+    /// Returns the current value of the timer `which`, which must not be
+    /// [`TimerType::NONE`].
+    pub fn get(&self, which: TimerType) -> ITimerValue {
+        Self::value(&self.inner.lock(), which)
+    }
+
+    fn value(inner: &ITimersInner, which: TimerType) -> ITimerValue {
+        match which {
+            TimerType::REAL => {
+                let real = inner.real;
+                let value_ns = match real.value_ns {
+                    0 => 0,
+                    // An expiring timer is still armed.
+                    deadline => deadline.saturating_sub(monotonic_time_nanos()).max(1),
+                };
+                ITimerValue {
+                    interval_ns: real.interval_ns,
+                    value_ns,
+                }
+            }
+            TimerType::VIRTUAL => inner.virt,
+            TimerType::PROF => inner.prof,
+            TimerType::NONE => ITimerValue::default(),
+        }
+    }
+}
+
+/// Sets the interval timer `which` of `process`, which must not be
+/// [`TimerType::NONE`], and returns its previous value.
+pub fn set_itimer(process: &Arc<ProcessData>, which: TimerType, new: ITimerValue) -> ITimerValue {
+    let itimers = process.itimers();
+    let mut inner = itimers.inner.lock();
+    let old = ITimers::value(&inner, which);
+    match which {
+        TimerType::REAL => {
+            inner.real_generation += 1;
+            inner.real = new;
+            if new.value_ns != 0 {
+                let deadline = monotonic_time_nanos() + new.value_ns;
+                inner.real.value_ns = deadline;
+                arm_real_timer(process, inner.real_generation, deadline);
+            }
+        }
+        TimerType::VIRTUAL => inner.virt = new,
+        TimerType::PROF => inner.prof = new,
+        TimerType::NONE => {}
+    }
+    itimers.cpu_armed.store(
+        inner.virt.value_ns != 0 || inner.prof.value_ns != 0,
+        Ordering::Relaxed,
+    );
+    old
+}
+
+fn arm_real_timer(process: &Arc<ProcessData>, generation: u64, deadline: u64) {
+    let process = Arc::downgrade(process);
+    set_kernel_timer(deadline, move || {
+        if let Some(process) = process.upgrade() {
+            real_timer_expired(&process, generation);
+        }
+    });
+}
+
+fn real_timer_expired(process: &Arc<ProcessData>, generation: u64) {
+    let mut inner = process.itimers().inner.lock();
+    if inner.real_generation != generation || process.is_zombie() {
+        return;
+    }
+    let real = &mut inner.real;
+    real.value_ns = match real.interval_ns {
+        0 => 0,
+        // Periods that already ended when the expiry is handled are skipped,
+        // rather than expiring one after another at once.
+        interval => next_deadline(real.value_ns, interval).0,
+    };
+    if real.value_ns != 0 {
+        arm_real_timer(process, generation, real.value_ns);
+    }
+    drop(inner);
+    send_signal(process, SIGALRM);
+}
+
+/// Charges `user_ns` of user CPU time and `system_ns` of system CPU time
+/// of the thread `tid` to the CPU-time timers of `process`, sending
+/// `SIGVTALRM`, `SIGPROF` or the signals of POSIX timers on expiry.
+pub fn charge_cpu_time(process: &Arc<ProcessData>, tid: usize, user_ns: u64, system_ns: u64) {
+    let itimers = process.itimers();
+    if itimers.cpu_armed.load(Ordering::Relaxed) {
+        let mut inner = itimers.inner.lock();
+        let virt_expired = inner.virt.count_down(user_ns) > 0;
+        let prof_expired = inner.prof.count_down(user_ns + system_ns) > 0;
+        itimers.cpu_armed.store(
+            inner.virt.value_ns != 0 || inner.prof.value_ns != 0,
+            Ordering::Relaxed,
+        );
+        drop(inner);
+        if virt_expired {
+            send_signal(process, SIGVTALRM);
+        }
+        if prof_expired {
+            send_signal(process, SIGPROF);
+        }
+    }
+
+    let posix_timers = process.posix_timers();
+    if posix_timers.cpu_armed.load(Ordering::Relaxed) {
+        let timers: Vec<_> = posix_timers.timers.lock().values().cloned().collect();
+        let mut armed = false;
+        for timer in timers {
+            let cpu_time = match timer.clock {
+                TimerClock::ProcessCpu(cpu_time) => cpu_time,
+                TimerClock::ThreadCpu(thread, cpu_time) if thread == tid => cpu_time,
+                _ => continue,
+            };
+            let charged = match cpu_time {
+                CpuTime::Total => user_ns + system_ns,
+                CpuTime::User => user_ns,
+            };
+            let mut inner = timer.inner.lock();
+            let expirations = inner.value.count_down(charged);
+            armed |= inner.value.value_ns != 0;
+            if expirations > 0 {
+                timer.notify(process, &mut inner, expirations - 1);
+            }
+        }
+        if !armed {
+            posix_timers.update_cpu_armed();
+        }
+    }
+}
+
+/// The clock of a POSIX timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerClock {
+    /// `CLOCK_REALTIME`, the wall-clock time.
+    Realtime,
+    /// `CLOCK_MONOTONIC`, the time since boot.
+    Monotonic,
+    /// The CPU time of the process owning the timer.
+    ProcessCpu(CpuTime),
+    /// The CPU time of the thread with the TID, in the process owning the
+    /// timer.
+    ThreadCpu(usize, CpuTime),
+}
+
+impl TimerClock {
+    /// Returns whether the clock measures CPU time.
+    fn is_cpu(self) -> bool {
+        matches!(self, Self::ProcessCpu(_) | Self::ThreadCpu(..))
+    }
+
+    /// Returns the current time (in nanoseconds) of the clock for
+    /// `process`.
+    pub fn now(self, process: &ProcessData) -> u64 {
+        match self {
+            Self::Realtime => realtime_nanos(),
+            Self::Monotonic => monotonic_time_nanos(),
+            Self::ProcessCpu(cpu_time) => cpu_time.process_nanos(process),
+            Self::ThreadCpu(tid, cpu_time) => {
+                find_thread(tid).map_or(0, |thread| cpu_time.thread_nanos(&thread))
+            }
+        }
+    }
+}
+
+struct PosixTimerInner {
+    /// For [`TimerClock::Realtime`] and [`TimerClock::Monotonic`], the value
+    /// is the monotonic deadline instead.
+    value: ITimerValue,
+    /// Incremented whenever the timer is set, which invalidates the kernel
+    /// timers of the previous settings.
+    generation: u64,
+    /// The expirations not signaled since the last signal was sent.
+    overrun: u64,
+}
+
+/// The signal a POSIX timer sends on expiry.
+#[derive(Debug, Clone, Copy)]
+pub struct TimerEvent {
+    /// The signal number.
+    pub signo: u32,
+    /// The value passed in `si_value`, or `None` for the ID of the timer.
+    pub value: Option<u64>,
+    /// The TID of the thread to signal (`SIGEV_THREAD_ID`), or `None` to
+    /// signal the process.
+    pub thread: Option<usize>,
+}
+
+/// A POSIX timer created by `timer_create`.
+pub struct PosixTimer {
+    id: i32,
+    clock: TimerClock,
+    /// The signal sent on expiry, or `None` for `SIGEV_NONE`.
+    event: Option<TimerEvent>,
+    inner: Mutex<PosixTimerInner>,
+}
+
+impl PosixTimer {
+    /// Sends the signal of the timer, which has expired `1 + missed` times.
+    ///
+    /// While a signal of the timer is still queued, no other one is sent,
+    /// and the expirations are added to the `si_overrun` of the queued one
+    /// instead. A signal for a thread which has exited goes to the process.
+    fn notify(&self, process: &Arc<ProcessData>, inner: &mut PosixTimerInner, missed: u64) {
+        let Some(event) = self.event else {
+            return;
+        };
+        let thread = event
+            .thread
+            .and_then(find_thread)
+            .filter(|thread| Arc::ptr_eq(&thread.task_ext().process, process));
+        let pending = match &thread {
+            Some(thread) => thread.task_ext().pending_signals(),
+            None => process.pending_signals(),
+        };
+        if pending.add_timer_overrun(self.id, missed + 1) {
+            inner.overrun += missed + 1;
+            return;
+        }
+        inner.overrun = missed;
+        let value = event.value.unwrap_or(self.id as u64);
+        let info = SigInfo::timer(event.signo, self.id, missed, value);
+        match thread {
+            Some(thread) => send_thread_signal(&thread, info),
+            None => send_signal_info(process, info),
+        }
+    }
+
+    fn value(&self, inner: &PosixTimerInner) -> ITimerValue {
+        let value = inner.value;
+        if self.clock.is_cpu() || value.value_ns == 0 {
+            return value;
+        }
+        ITimerValue {
+            interval_ns: value.interval_ns,
+            // An expiring timer is still armed.
+            value_ns: value.value_ns.saturating_sub(monotonic_time_nanos()).max(1),
+        }
+    }
+}
+
+/// The POSIX timers of a process, which are neither inherited by children
+/// nor kept across `execve`.
+#[derive(Default)]
+pub struct PosixTimers {
+    timers: Mutex<BTreeMap<i32, Arc<PosixTimer>>>,
+    /// Whether a timer on a CPU-time clock may be armed.
+    cpu_armed: AtomicBool,
+}
+
+impl PosixTimers {
+    /// Creates a disarmed timer on `clock`, which sends the signal of
+    /// `event` on expiry, and returns its ID.
+    ///
+    /// Returns [`AxError::WouldBlock`] if the process has too many timers.
+    pub fn create(&self, clock: TimerClock, event: Option<TimerEvent>) -> AxResult<i32> {
+        let mut timers = self.timers.lock();
+        let id = match timers.last_key_value() {
+            None => 0,
+            Some((&last, _)) if last < i32::MAX => last + 1,
+            Some(_) => (0..i32::MAX)
+                .find(|id| !timers.contains_key(id))
+                .ok_or(AxError::WouldBlock)?,
+        };
+        let timer = PosixTimer {
+            id,
+            clock,
+            event,
+            inner: Mutex::new(PosixTimerInner {
+                value: ITimerValue::default(),
+                generation: 0,
+                overrun: 0,
+            }),
+        };
+        timers.insert(id, Arc::new(timer));
+        Ok(id)
+    }
+
+    fn find(&self, id: i32) -> AxResult<Arc<PosixTimer>> {
+        self.timers
+            .lock()
+            .get(&id)
+            .cloned()
+            .ok_or(AxError::NotFound)
+    }
+
+    /// Returns the current value of the timer `id`.
+    pub fn get(&self, id: i32) -> AxResult<ITimerValue> {
+        let timer = self.find(id)?;
+        let inner = timer.inner.lock();
+        Ok(timer.value(&inner))
+    }
+
+    /// Returns the number of expirations of the timer `id` which were not
+    /// signaled, since its last signal was sent.
+    pub fn overrun(&self, id: i32) -> AxResult<u64> {
+        Ok(self.find(id)?.inner.lock().overrun)
+    }
+
+    /// Deletes the timer `id`, which disarms it.
+    pub fn delete(&self, id: i32) -> AxResult {
+        self.timers.lock().remove(&id).ok_or(AxError::NotFound)?;
+        Ok(())
+    }
+
+    /// Updates whether a timer on a CPU-time clock is armed. The timers are
+    /// locked, so that a timer armed concurrently is not missed.
+    fn update_cpu_armed(&self) {
+        let timers = self.timers.lock();
+        let armed = timers
+            .values()
+            .any(|timer| timer.clock.is_cpu() && timer.inner.lock().value.value_ns != 0);
+        self.cpu_armed.store(armed, Ordering::Relaxed);
+    }
+
+    /// Deletes all timers, as done by `execve`.
+    pub fn clear(&self) {
+        self.timers.lock().clear();
+    }
+}
+
+/// Sets the POSIX timer `id` of `process` to expire after `new.value_ns`,
+/// or at that time of its clock if `absolute` is set, and returns its
+/// previous value. A zero value disarms the timer.
+pub fn set_posix_timer(
+    process: &Arc<ProcessData>,
+    id: i32,
+    new: ITimerValue,
+    absolute: bool,
+) -> AxResult<ITimerValue> {
+    let timer = process.posix_timers().find(id)?;
+    let mut value = new;
+    if absolute && value.value_ns != 0 {
+        // An expired time still fires the timer, as soon as possible.
+        value.value_ns = value
+            .value_ns
+            .saturating_sub(timer.clock.now(process))
+            .max(1);
+    }
+
+    let mut inner = timer.inner.lock();
+    let old = timer.value(&inner);
+    inner.generation += 1;
+    inner.overrun = 0;
+    if timer.clock.is_cpu() {
+        inner.value = value;
+        drop(inner);
+        if value.value_ns != 0 {
+            process.posix_timers().update_cpu_armed();
+        }
+    } else {
+        if value.value_ns != 0 {
+            value.value_ns += monotonic_time_nanos();
+            arm_posix_timer(process, &timer, inner.generation, value.value_ns);
+        }
+        inner.value = value;
+    }
+    Ok(old)
+}
+
+fn arm_posix_timer(
+    process: &Arc<ProcessData>,
+    timer: &Arc<PosixTimer>,
+    generation: u64,
+    deadline: u64,
+) {
+    let process = Arc::downgrade(process);
+    let timer = Arc::downgrade(timer);
+    set_kernel_timer(deadline, move || {
+        if let (Some(process), Some(timer)) = (process.upgrade(), timer.upgrade()) {
+            posix_timer_expired(&process, &timer, generation);
+        }
+    });
+}
+
+fn posix_timer_expired(process: &Arc<ProcessData>, timer: &Arc<PosixTimer>, generation: u64) {
+    let mut inner = timer.inner.lock();
+    if inner.generation != generation || process.is_zombie() {
+        return;
+    }
+    let value = &mut inner.value;
+    let missed = match value.interval_ns {
+        0 => {
+            value.value_ns = 0;
+            0
+        }
+        interval => {
+            let (deadline, missed) = next_deadline(value.value_ns, interval);
+            value.value_ns = deadline;
+            arm_posix_timer(process, timer, generation, deadline);
+            missed
+        }
+    };
+    timer.notify(process, &mut inner, missed);
+}
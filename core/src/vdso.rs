@@ -0,0 +1,303 @@
+//! The virtual dynamic shared object (vDSO).
+//!
+//! With the `vdso` feature, the vDSO is built from `core/vdso` by the build
+//! script, which needs a C cross compiler for the target. It implements
+//! `clock_gettime`, `gettimeofday`, `clock_getres` and `getcpu` in user
+//! space, as well as the trampoline that signal handlers return to, and is
+//! mapped into every user address space right after the vvar page. The
+//! kernel publishes the parameters to convert the hardware counter to the
+//! clocks in the vvar page, under a sequence lock.
+//!
+//! Without the feature, only a page holding the signal return trampoline is
+//! mapped, and the libcs make system calls to read the clocks.
+
+#[cfg(feature = "vdso")]
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering, fence};
+
+use axerrno::AxResult;
+#[cfg(feature = "vdso")]
+use axhal::{
+    arch::TrapFrame,
+    time::{current_ticks, monotonic_time_nanos, ticks_to_nanos},
+    trap::{USER_RETURN, register_trap_handler},
+};
+use axhal::{mem::virt_to_phys, paging::MappingFlags};
+use axmm::AddrSpace;
+use memory_addr::{PAGE_SIZE_4K, VirtAddr, VirtAddrRange};
+#[cfg(feature = "vdso")]
+use xmas_elf::{ElfFile, sections::SectionData, symbol_table::Entry};
+
+#[cfg(feature = "vdso")]
+use crate::time::{clock_resolution_nanos, realtime_offset_nanos};
+
+#[cfg(feature = "vdso")]
+const VDSO_IMAGE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/vdso.so"));
+/// The size of the vDSO, rounded up to pages.
+#[cfg(feature = "vdso")]
+const VDSO_SIZE: usize = VDSO_IMAGE.len().div_ceil(PAGE_SIZE_4K) * PAGE_SIZE_4K;
+
+/// The user address of the vvar page, which is followed by the vDSO. They are
+/// placed below the user stack, with a guard page in between.
+#[cfg(feature = "vdso")]
+const VVAR_BASE: usize = axconfig::plat::USER_STACK_TOP
+    - axconfig::plat::USER_STACK_SIZE
+    - PAGE_SIZE_4K
+    - VDSO_SIZE
+    - PAGE_SIZE_4K;
+
+/// The vvar page is refreshed at least this often while user code runs, so
+/// that the vDSO clocks do not drift away from the kernel ones.
+#[cfg(feature = "vdso")]
+const REFRESH_INTERVAL_NANOS: u64 = 10_000_000;
+
+/// The clocks can be read from the hardware counter.
+#[cfg(feature = "vdso")]
+const VDSO_CLOCK_MODE_COUNTER: u32 = 1;
+
+#[cfg(feature = "vdso")]
+#[repr(C, align(4096))]
+struct PageAligned<T>(T);
+
+/// The data in the vvar page. Must match `struct vdso_data` in
+/// `core/vdso/vdso.c`.
+#[cfg(feature = "vdso")]
+#[repr(C)]
+struct VdsoData {
+    /// Odd while the data is being updated.
+    seq: AtomicU32,
+    clock_mode: AtomicU32,
+    /// The counter value at the last update.
+    cycle_last: AtomicU64,
+    /// Nanoseconds per 2^32 counter ticks.
+    mult: AtomicU64,
+    /// The monotonic time at the last update.
+    mono_ns: AtomicU64,
+    /// The offset from the monotonic clock to the real-time clock.
+    realtime_offset_ns: AtomicU64,
+    /// The resolution of the clocks.
+    resolution_ns: AtomicU64,
+}
+
+#[cfg(feature = "vdso")]
+static VVAR: PageAligned<VdsoData> = PageAligned(VdsoData {
+    seq: AtomicU32::new(0),
+    clock_mode: AtomicU32::new(0),
+    cycle_last: AtomicU64::new(0),
+    mult: AtomicU64::new(0),
+    mono_ns: AtomicU64::new(0),
+    realtime_offset_ns: AtomicU64::new(0),
+    resolution_ns: AtomicU64::new(0),
+});
+
+#[cfg(feature = "vdso")]
+static VDSO: PageAligned<[u8; VDSO_SIZE]> = PageAligned(pad_image(VDSO_IMAGE));
+
+/// Copies the vDSO image into whole pages, so that no kernel data is mapped
+/// into user space after it.
+#[cfg(feature = "vdso")]
+const fn pad_image<const N: usize>(image: &[u8]) -> [u8; N] {
+    let mut buf = [0; N];
+    let mut i = 0;
+    while i < image.len() {
+        buf[i] = image[i];
+        i += 1;
+    }
+    buf
+}
+
+/// Returns the difference between the counter read by the vDSO and
+/// [`current_ticks`].
+#[cfg(feature = "vdso")]
+fn counter_offset() -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        // `current_ticks` is relative to the TSC value at boot.
+        static OFFSET: spin::Once<u64> = spin::Once::new();
+        *OFFSET.call_once(|| {
+            let ticks = current_ticks();
+            unsafe { core::arch::x86_64::_rdtsc() - ticks }
+        })
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    0
+}
+
+/// Publishes the current clock parameters in the vvar page. Does nothing if
+/// another CPU is doing it.
+#[cfg(feature = "vdso")]
+pub(crate) fn update_vvar() {
+    let data = &VVAR.0;
+    let seq = data.seq.load(Ordering::Relaxed);
+    if seq & 1 != 0
+        || data
+            .seq
+            .compare_exchange(
+                seq,
+                seq.wrapping_add(1),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .is_err()
+    {
+        return;
+    }
+    fence(Ordering::Release);
+
+    let ticks = current_ticks();
+    data.cycle_last
+        .store(ticks.wrapping_add(counter_offset()), Ordering::Relaxed);
+    data.mono_ns.store(ticks_to_nanos(ticks), Ordering::Relaxed);
+    data.mult.store(ticks_to_nanos(1 << 32), Ordering::Relaxed);
+    data.realtime_offset_ns
+        .store(realtime_offset_nanos(), Ordering::Relaxed);
+    data.resolution_ns
+        .store(clock_resolution_nanos(), Ordering::Relaxed);
+    data.clock_mode
+        .store(VDSO_CLOCK_MODE_COUNTER, Ordering::Relaxed);
+
+    data.seq.store(seq.wrapping_add(2), Ordering::Release);
+}
+
+/// There is no vvar page to update without the vDSO.
+#[cfg(not(feature = "vdso"))]
+pub(crate) fn update_vvar() {}
+
+#[cfg(feature = "vdso")]
+#[register_trap_handler(USER_RETURN)]
+fn refresh_vvar(_tf: &mut TrapFrame) {
+    let last = VVAR.0.mono_ns.load(Ordering::Relaxed);
+    if monotonic_time_nanos().saturating_sub(last) >= REFRESH_INTERVAL_NANOS {
+        update_vvar();
+    }
+}
+
+/// Returns the user address range of the vvar page and the vDSO.
+#[cfg(feature = "vdso")]
+pub fn vdso_range() -> VirtAddrRange {
+    VirtAddrRange::from_start_size(VVAR_BASE.into(), PAGE_SIZE_4K + VDSO_SIZE)
+}
+
+/// Returns the offset of the dynamic symbol `name` in the vDSO.
+#[cfg(feature = "vdso")]
+fn symbol_offset(name: &str) -> Option<usize> {
+    let elf = ElfFile::new(VDSO_IMAGE).ok()?;
+    let dynsym = elf.find_section_by_name(".dynsym")?;
+    let Ok(SectionData::DynSymbolTable64(symbols)) = dynsym.get_data(&elf) else {
+        return None;
+    };
+    symbols
+        .iter()
+        .find(|symbol| symbol.get_name(&elf) == Ok(name))
+        .map(|symbol| symbol.value() as usize)
+}
+
+/// Returns the user address of `__vdso_rt_sigreturn`, which signal handlers
+/// return to if no `SA_RESTORER` is given.
+#[cfg(feature = "vdso")]
+pub fn sigreturn_trampoline() -> usize {
+    static OFFSET: spin::Once<usize> = spin::Once::new();
+    let offset = OFFSET.call_once(|| {
+        symbol_offset("__vdso_rt_sigreturn").expect("no __vdso_rt_sigreturn in the vDSO")
+    });
+    VVAR_BASE + PAGE_SIZE_4K + offset
+}
+
+/// Maps the vvar page and the vDSO into `aspace`.
+///
+/// # Returns
+/// - The address of the vDSO, for `AT_SYSINFO_EHDR`.
+#[cfg(feature = "vdso")]
+pub fn map_vdso(aspace: &mut AddrSpace) -> AxResult<Option<usize>> {
+    update_vvar();
+    let vdso_base = VVAR_BASE + PAGE_SIZE_4K;
+    aspace.map_linear(
+        VVAR_BASE.into(),
+        virt_to_phys(VirtAddr::from_usize(&VVAR as *const _ as usize)),
+        PAGE_SIZE_4K,
+        MappingFlags::READ | MappingFlags::USER,
+    )?;
+    aspace.map_linear(
+        vdso_base.into(),
+        virt_to_phys(VirtAddr::from_usize(&VDSO as *const _ as usize)),
+        VDSO_SIZE,
+        MappingFlags::READ | MappingFlags::EXECUTE | MappingFlags::USER,
+    )?;
+    Ok(Some(vdso_base))
+}
+
+/// The user address of the page holding the signal return trampoline, where
+/// the vvar page would be.
+#[cfg(not(feature = "vdso"))]
+const TRAMPOLINE_BASE: usize =
+    axconfig::plat::USER_STACK_TOP - axconfig::plat::USER_STACK_SIZE - 2 * PAGE_SIZE_4K;
+
+#[cfg(all(not(feature = "vdso"), target_arch = "x86_64"))]
+macro_rules! rt_sigreturn {
+    () => {
+        "mov eax, 15\n    syscall"
+    };
+}
+#[cfg(all(not(feature = "vdso"), target_arch = "aarch64"))]
+macro_rules! rt_sigreturn {
+    () => {
+        "mov x8, #139\n    svc #0"
+    };
+}
+#[cfg(all(not(feature = "vdso"), target_arch = "riscv64"))]
+macro_rules! rt_sigreturn {
+    () => {
+        "li a7, 139\n    ecall"
+    };
+}
+#[cfg(all(not(feature = "vdso"), target_arch = "loongarch64"))]
+macro_rules! rt_sigreturn {
+    () => {
+        "li.w $a7, 139\n    syscall 0"
+    };
+}
+
+// The trampoline fills a page of its own, so that no other kernel code is
+// mapped into user space.
+#[cfg(not(feature = "vdso"))]
+core::arch::global_asm!(
+    ".pushsection .text.sigreturn_trampoline, \"ax\"",
+    ".balign 4096",
+    ".globl __sigreturn_trampoline",
+    "__sigreturn_trampoline:",
+    rt_sigreturn!(),
+    ".balign 4096",
+    ".popsection",
+);
+
+#[cfg(not(feature = "vdso"))]
+unsafe extern "C" {
+    fn __sigreturn_trampoline();
+}
+
+/// Returns the user address range of the signal return trampoline.
+#[cfg(not(feature = "vdso"))]
+pub fn vdso_range() -> VirtAddrRange {
+    VirtAddrRange::from_start_size(TRAMPOLINE_BASE.into(), PAGE_SIZE_4K)
+}
+
+/// Returns the user address of the trampoline that signal handlers return to
+/// if no `SA_RESTORER` is given.
+#[cfg(not(feature = "vdso"))]
+pub fn sigreturn_trampoline() -> usize {
+    TRAMPOLINE_BASE
+}
+
+/// Maps the signal return trampoline into `aspace`.
+///
+/// # Returns
+/// - `None`, as there is no vDSO for `AT_SYSINFO_EHDR`.
+#[cfg(not(feature = "vdso"))]
+pub fn map_vdso(aspace: &mut AddrSpace) -> AxResult<Option<usize>> {
+    aspace.map_linear(
+        TRAMPOLINE_BASE.into(),
+        virt_to_phys(VirtAddr::from_usize(__sigreturn_trampoline as usize)),
+        PAGE_SIZE_4K,
+        MappingFlags::READ | MappingFlags::EXECUTE | MappingFlags::USER,
+    )?;
+    Ok(None)
+}
@@ -0,0 +1,255 @@
+/*
+ * The vDSO of Starry.
+ *
+ * Clocks are computed from the hardware counter and the parameters that the
+ * kernel publishes in the vvar page (see `core/src/vdso.rs`), so reading the
+ * time does not enter the kernel. Everything else falls back to a syscall.
+ */
+
+#include <stdint.h>
+
+#define CLOCK_REALTIME          0
+#define CLOCK_MONOTONIC         1
+#define CLOCK_MONOTONIC_RAW     4
+#define CLOCK_REALTIME_COARSE   5
+#define CLOCK_MONOTONIC_COARSE  6
+#define CLOCK_BOOTTIME          7
+
+#define NSEC_PER_SEC            1000000000ULL
+
+/* The clocks can be read from the hardware counter. */
+#define VDSO_CLOCK_MODE_COUNTER 1
+
+#if defined(__x86_64__)
+#define NR_gettimeofday         96
+#define NR_clock_gettime        228
+#define NR_clock_getres         229
+#define NR_getcpu               309
+#else
+#define NR_getcpu               168
+#define NR_gettimeofday         169
+#define NR_clock_gettime        113
+#define NR_clock_getres         114
+#endif
+
+/* Must match `VdsoData` in `core/src/vdso.rs`. */
+struct vdso_data {
+    uint32_t seq;
+    uint32_t clock_mode;
+    /* The counter value at the last update. */
+    uint64_t cycle_last;
+    /* Nanoseconds per 2^32 counter ticks. */
+    uint64_t mult;
+    /* The monotonic time at the last update. */
+    uint64_t mono_ns;
+    /* The offset from the monotonic clock to the real-time clock. */
+    uint64_t realtime_offset_ns;
+    /* The resolution of the clocks. */
+    uint64_t resolution_ns;
+};
+
+struct timespec {
+    long tv_sec;
+    long tv_nsec;
+};
+
+struct timeval {
+    long tv_sec;
+    long tv_usec;
+};
+
+struct timezone {
+    int tz_minuteswest;
+    int tz_dsttime;
+};
+
+extern const struct vdso_data __vvar_page __attribute__((visibility("hidden")));
+
+static inline uint64_t read_counter(void)
+{
+    uint64_t cnt;
+#if defined(__x86_64__)
+    uint32_t lo, hi;
+    __asm__ volatile("lfence; rdtsc" : "=a"(lo), "=d"(hi) : : "memory");
+    cnt = ((uint64_t)hi << 32) | lo;
+#elif defined(__aarch64__)
+    __asm__ volatile("isb; mrs %0, cntpct_el0" : "=r"(cnt) : : "memory");
+#elif defined(__riscv)
+    __asm__ volatile("rdtime %0" : "=r"(cnt) : : "memory");
+#elif defined(__loongarch64)
+    uint64_t id;
+    __asm__ volatile("rdtime.d %0, %1" : "=r"(cnt), "=r"(id) : : "memory");
+#else
+#error "unsupported architecture"
+#endif
+    return cnt;
+}
+
+static inline long syscall3(long nr, long arg0, long arg1, long arg2)
+{
+#if defined(__x86_64__)
+    long ret;
+    __asm__ volatile("syscall"
+                     : "=a"(ret)
+                     : "a"(nr), "D"(arg0), "S"(arg1), "d"(arg2)
+                     : "rcx", "r11", "memory");
+    return ret;
+#elif defined(__aarch64__)
+    register long x8 __asm__("x8") = nr;
+    register long x0 __asm__("x0") = arg0;
+    register long x1 __asm__("x1") = arg1;
+    register long x2 __asm__("x2") = arg2;
+    __asm__ volatile("svc #0" : "+r"(x0) : "r"(x8), "r"(x1), "r"(x2) : "memory");
+    return x0;
+#elif defined(__riscv)
+    register long a7 __asm__("a7") = nr;
+    register long a0 __asm__("a0") = arg0;
+    register long a1 __asm__("a1") = arg1;
+    register long a2 __asm__("a2") = arg2;
+    __asm__ volatile("ecall" : "+r"(a0) : "r"(a7), "r"(a1), "r"(a2) : "memory");
+    return a0;
+#elif defined(__loongarch64)
+    register long a7 __asm__("$a7") = nr;
+    register long a0 __asm__("$a0") = arg0;
+    register long a1 __asm__("$a1") = arg1;
+    register long a2 __asm__("$a2") = arg2;
+    __asm__ volatile("syscall 0"
+                     : "+r"(a0)
+                     : "r"(a7), "r"(a1), "r"(a2)
+                     : "$t0", "$t1", "$t2", "$t3", "$t4", "$t5", "$t6", "$t7", "$t8",
+                       "memory");
+    return a0;
+#endif
+}
+
+static inline int is_realtime(int clk)
+{
+    return clk == CLOCK_REALTIME || clk == CLOCK_REALTIME_COARSE;
+}
+
+static inline int is_supported(int clk)
+{
+    switch (clk) {
+    case CLOCK_REALTIME:
+    case CLOCK_MONOTONIC:
+    case CLOCK_MONOTONIC_RAW:
+    case CLOCK_REALTIME_COARSE:
+    case CLOCK_MONOTONIC_COARSE:
+    case CLOCK_BOOTTIME:
+        return 1;
+    default:
+        return 0;
+    }
+}
+
+/* Reads a clock in nanoseconds. Returns -1 if the counter cannot be used. */
+static int read_clock(int clk, uint64_t *ns)
+{
+    const volatile struct vdso_data *vd = &__vvar_page;
+    uint32_t seq;
+    uint64_t now;
+
+    do {
+        seq = __atomic_load_n(&vd->seq, __ATOMIC_ACQUIRE);
+        if (seq & 1)
+            continue;
+        if (vd->clock_mode != VDSO_CLOCK_MODE_COUNTER)
+            return -1;
+        uint64_t cycles = read_counter();
+        uint64_t delta = cycles > vd->cycle_last ? cycles - vd->cycle_last : 0;
+        now = vd->mono_ns + (uint64_t)(((unsigned __int128)delta * vd->mult) >> 32);
+        if (is_realtime(clk))
+            now += vd->realtime_offset_ns;
+        __atomic_thread_fence(__ATOMIC_ACQUIRE);
+    } while ((seq & 1) || __atomic_load_n(&vd->seq, __ATOMIC_RELAXED) != seq);
+
+    *ns = now;
+    return 0;
+}
+
+int __vdso_clock_gettime(int clk, struct timespec *ts)
+{
+    uint64_t ns;
+
+    if (!is_supported(clk) || read_clock(clk, &ns))
+        return syscall3(NR_clock_gettime, clk, (long)ts, 0);
+    ts->tv_sec = ns / NSEC_PER_SEC;
+    ts->tv_nsec = ns % NSEC_PER_SEC;
+    return 0;
+}
+
+int __vdso_gettimeofday(struct timeval *tv, struct timezone *tz)
+{
+    uint64_t ns;
+
+    if (tv) {
+        if (read_clock(CLOCK_REALTIME, &ns))
+            return syscall3(NR_gettimeofday, (long)tv, (long)tz, 0);
+        tv->tv_sec = ns / NSEC_PER_SEC;
+        tv->tv_usec = ns % NSEC_PER_SEC / 1000;
+    }
+    if (tz) {
+        tz->tz_minuteswest = 0;
+        tz->tz_dsttime = 0;
+    }
+    return 0;
+}
+
+int __vdso_clock_getres(int clk, struct timespec *res)
+{
+    const volatile struct vdso_data *vd = &__vvar_page;
+
+    if (!is_supported(clk) || vd->clock_mode != VDSO_CLOCK_MODE_COUNTER)
+        return syscall3(NR_clock_getres, clk, (long)res, 0);
+    if (res) {
+        res->tv_sec = 0;
+        res->tv_nsec = vd->resolution_ns;
+    }
+    return 0;
+}
+
+/* The running CPU is only known to the kernel. */
+int __vdso_getcpu(unsigned *cpu, unsigned *node, void *tcache)
+{
+    return syscall3(NR_getcpu, (long)cpu, (long)node, (long)tcache);
+}
+
+/*
+ * Signal handlers return here, unless the libc gives its own restorer with
+ * `SA_RESTORER`. The kernel finds it by the symbol name.
+ */
+__asm__(".text\n"
+        ".globl __vdso_rt_sigreturn\n"
+        ".type __vdso_rt_sigreturn, @function\n"
+#if defined(__aarch64__)
+        ".globl __kernel_rt_sigreturn\n"
+        ".type __kernel_rt_sigreturn, @function\n"
+        "__kernel_rt_sigreturn:\n"
+#endif
+        "__vdso_rt_sigreturn:\n"
+#if defined(__x86_64__)
+        "    mov $15, %rax\n"
+        "    syscall\n"
+#elif defined(__aarch64__)
+        "    mov x8, #139\n"
+        "    svc #0\n"
+#elif defined(__riscv)
+        "    li a7, 139\n"
+        "    ecall\n"
+#elif defined(__loongarch64)
+        "    li.w $a7, 139\n"
+        "    syscall 0\n"
+#endif
+        ".size __vdso_rt_sigreturn, . - __vdso_rt_sigreturn\n");
+
+#if defined(__aarch64__)
+/* AArch64 libcs look up the `__kernel_` names. */
+int __kernel_clock_gettime(int, struct timespec *)
+    __attribute__((alias("__vdso_clock_gettime")));
+int __kernel_gettimeofday(struct timeval *, struct timezone *)
+    __attribute__((alias("__vdso_gettimeofday")));
+int __kernel_clock_getres(int, struct timespec *)
+    __attribute__((alias("__vdso_clock_getres")));
+int __kernel_getcpu(unsigned *, unsigned *, void *)
+    __attribute__((alias("__vdso_getcpu")));
+#endif
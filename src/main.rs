@@ -10,10 +10,24 @@ mod syscall;
 
 use alloc::string::String;
 use alloc::vec::Vec;
-use starry_core::entry::run_user_app;
+use starry_core::{entry::run_user_app, mm::set_interp_alias, process::set_pid_max};
 
 #[unsafe(no_mangle)]
 fn main() {
+    if let Some(pid_max) = option_env!("AX_PID_MAX") {
+        let pid_max = pid_max.parse().expect("AX_PID_MAX is not a number");
+        set_pid_max(pid_max).expect("AX_PID_MAX is out of range");
+    }
+
+    // Each alias is given as `interp=target`, separated by commas.
+    let interp_aliases = option_env!("AX_INTERP_ALIASES").unwrap_or_default();
+    for alias in interp_aliases.split(',').filter(|&x| !x.is_empty()) {
+        let (interp, target) = alias
+            .split_once('=')
+            .expect("AX_INTERP_ALIASES is not a list of interp=target");
+        set_interp_alias(interp, Some(target));
+    }
+
     let testcases = option_env!("AX_TESTCASES_LIST")
         .unwrap_or_else(|| "Please specify the testcases list by making user_apps")
         .split(',')
@@ -3,14 +3,26 @@ use axhal::{
     arch::TrapFrame,
     trap::{SYSCALL, register_trap_handler},
 };
+use axtask::{TaskExtRef, current};
 use starry_api::*;
-use starry_core::task::{time_stat_from_kernel_to_user, time_stat_from_user_to_kernel};
+use starry_core::{signal::SyscallReturn, task::time_stat_from_user_to_kernel};
 use syscalls::Sysno;
 
+/// Returns `true` if the syscall can be restarted after being interrupted by
+/// a signal with `SA_RESTART`. Sleeps are not, as they would start over with
+/// the full duration.
+fn is_restartable(sysno: Sysno) -> bool {
+    !matches!(
+        sysno,
+        Sysno::rt_sigreturn | Sysno::nanosleep | Sysno::clock_nanosleep | Sysno::rt_sigtimedwait
+    )
+}
+
 #[register_trap_handler(SYSCALL)]
-fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
+fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
     info!("[syscall] <{:?}> begin", Sysno::from(syscall_num as u32));
     time_stat_from_user_to_kernel();
+    let arg0 = tf.arg0();
     let result: LinuxResult<isize> = match Sysno::from(syscall_num as u32) {
         Sysno::read => sys_read(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
         Sysno::write => sys_write(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
@@ -25,14 +37,40 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
         Sysno::ioctl => sys_ioctl(tf.arg0() as _, tf.arg1() as _, tf.arg2().into()),
         Sysno::writev => sys_writev(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
         Sysno::sched_yield => sys_sched_yield(),
+        Sysno::getcpu => sys_getcpu(tf.arg0().into(), tf.arg1().into()),
+        Sysno::sched_setaffinity => {
+            sys_sched_setaffinity(tf.arg0() as _, tf.arg1() as _, tf.arg2().into())
+        }
+        Sysno::sched_getaffinity => {
+            sys_sched_getaffinity(tf.arg0() as _, tf.arg1() as _, tf.arg2().into())
+        }
+        Sysno::sched_setscheduler => {
+            sys_sched_setscheduler(tf.arg0() as _, tf.arg1() as _, tf.arg2().into())
+        }
+        Sysno::sched_getscheduler => sys_sched_getscheduler(tf.arg0() as _),
+        Sysno::sched_setparam => sys_sched_setparam(tf.arg0() as _, tf.arg1().into()),
+        Sysno::sched_getparam => sys_sched_getparam(tf.arg0() as _, tf.arg1().into()),
+        Sysno::sched_get_priority_max => sys_sched_get_priority_max(tf.arg0() as _),
+        Sysno::sched_get_priority_min => sys_sched_get_priority_min(tf.arg0() as _),
+        Sysno::sched_rr_get_interval => sys_sched_rr_get_interval(tf.arg0() as _, tf.arg1().into()),
+        Sysno::setpriority => sys_setpriority(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::getpriority => sys_getpriority(tf.arg0() as _, tf.arg1() as _),
         Sysno::nanosleep => sys_nanosleep(tf.arg0().into(), tf.arg1().into()),
         Sysno::getpid => sys_getpid(),
         Sysno::getppid => sys_getppid(),
+        Sysno::setpgid => sys_setpgid(tf.arg0() as _, tf.arg1() as _),
+        Sysno::getpgid => sys_getpgid(tf.arg0() as _),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::getpgrp => sys_getpgrp(),
+        Sysno::setsid => sys_setsid(),
+        Sysno::getsid => sys_getsid(tf.arg0() as _),
+        Sysno::prctl => sys_prctl(tf.arg0() as _, tf.arg1() as _),
         Sysno::exit => sys_exit(tf.arg0() as _),
-        Sysno::gettimeofday => sys_get_time_of_day(tf.arg0().into()),
+        Sysno::gettimeofday => sys_get_time_of_day(tf.arg0().into(), tf.arg1().into()),
+        Sysno::settimeofday => sys_settimeofday(tf.arg0().into(), tf.arg1().into()),
         Sysno::getcwd => sys_getcwd(tf.arg0().into(), tf.arg1() as _),
         Sysno::dup => sys_dup(tf.arg0() as _),
-        Sysno::dup3 => sys_dup3(tf.arg0() as _, tf.arg1() as _),
+        Sysno::dup3 => sys_dup3(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         Sysno::fcntl => sys_fcntl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         Sysno::clone => sys_clone(
             tf.arg0() as _,
@@ -41,14 +79,34 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
             tf.arg3() as _,
             tf.arg4() as _,
         ),
-        Sysno::wait4 => sys_wait4(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
-        Sysno::pipe2 => sys_pipe2(tf.arg0().into()),
+        Sysno::clone3 => sys_clone3(tf.arg0().into(), tf.arg1() as _),
+        Sysno::wait4 => sys_wait4(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3().into(),
+        ),
+        Sysno::waitid => sys_waitid(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3() as _,
+            tf.arg4().into(),
+        ),
+        Sysno::pipe2 => sys_pipe2(tf.arg0().into(), tf.arg1() as _),
         #[cfg(target_arch = "x86_64")]
-        Sysno::pipe => sys_pipe2(tf.arg0().into()),
+        Sysno::pipe => sys_pipe2(tf.arg0().into(), 0),
         Sysno::close => sys_close(tf.arg0() as _),
         Sysno::chdir => sys_chdir(tf.arg0().into()),
         Sysno::mkdirat => sys_mkdirat(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
         Sysno::execve => sys_execve(tf.arg0().into(), tf.arg1().into(), tf.arg2().into()),
+        Sysno::execveat => sys_execveat(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2().into(),
+            tf.arg3().into(),
+            tf.arg4() as _,
+        ),
         Sysno::openat => sys_openat(
             tf.arg0() as _,
             tf.arg1().into(),
@@ -100,13 +158,51 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
         Sysno::munmap => sys_munmap(tf.arg0().into(), tf.arg1() as _),
         Sysno::mprotect => sys_mprotect(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
         Sysno::times => sys_times(tf.arg0().into()),
+        Sysno::getitimer => sys_getitimer(tf.arg0() as _, tf.arg1().into()),
+        Sysno::setitimer => sys_setitimer(tf.arg0() as _, tf.arg1().into(), tf.arg2().into()),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::alarm => sys_alarm(tf.arg0() as _),
+        Sysno::timer_create => sys_timer_create(tf.arg0() as _, tf.arg1().into(), tf.arg2().into()),
+        Sysno::timer_settime => sys_timer_settime(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3().into(),
+        ),
+        Sysno::timer_gettime => sys_timer_gettime(tf.arg0() as _, tf.arg1().into()),
+        Sysno::timer_getoverrun => sys_timer_getoverrun(tf.arg0() as _),
+        Sysno::timer_delete => sys_timer_delete(tf.arg0() as _),
+        Sysno::getrusage => sys_getrusage(tf.arg0() as _, tf.arg1().into()),
         Sysno::brk => sys_brk(tf.arg0() as _),
         #[cfg(target_arch = "x86_64")]
         Sysno::arch_prctl => sys_arch_prctl(tf.arg0() as _, tf.arg1().into()),
         Sysno::set_tid_address => sys_set_tid_address(tf.arg0().into()),
         Sysno::clock_gettime => sys_clock_gettime(tf.arg0() as _, tf.arg1().into()),
+        Sysno::clock_getres => sys_clock_getres(tf.arg0() as _, tf.arg1().into()),
+        Sysno::clock_settime => sys_clock_settime(tf.arg0() as _, tf.arg1().into()),
+        Sysno::clock_nanosleep => sys_clock_nanosleep(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3().into(),
+        ),
         Sysno::exit_group => sys_exit_group(tf.arg0() as _),
         Sysno::getuid => sys_getuid(),
+        Sysno::geteuid => sys_geteuid(),
+        Sysno::getgid => sys_getgid(),
+        Sysno::getegid => sys_getegid(),
+        Sysno::setuid => sys_setuid(tf.arg0() as _),
+        Sysno::setgid => sys_setgid(tf.arg0() as _),
+        Sysno::setreuid => sys_setreuid(tf.arg0() as _, tf.arg1() as _),
+        Sysno::setregid => sys_setregid(tf.arg0() as _, tf.arg1() as _),
+        Sysno::setresuid => sys_setresuid(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::setresgid => sys_setresgid(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::getresuid => sys_getresuid(tf.arg0().into(), tf.arg1().into(), tf.arg2().into()),
+        Sysno::getresgid => sys_getresgid(tf.arg0().into(), tf.arg1().into(), tf.arg2().into()),
+        Sysno::getgroups => sys_getgroups(tf.arg0() as _, tf.arg1().into()),
+        Sysno::setgroups => sys_setgroups(tf.arg0() as _, tf.arg1().into()),
+        Sysno::capget => sys_capget(tf.arg0().into(), tf.arg1().into()),
+        Sysno::capset => sys_capset(tf.arg0().into(), tf.arg1().into()),
         Sysno::rt_sigprocmask => sys_rt_sigprocmask(
             tf.arg0() as _,
             tf.arg1().into(),
@@ -119,8 +215,11 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
             tf.arg2().into(),
             tf.arg3() as _,
         ),
+        Sysno::sigaltstack => sys_sigaltstack(tf.arg0().into(), tf.arg1().into()),
         #[cfg(target_arch = "x86_64")]
         Sysno::fork => sys_fork(),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::vfork => sys_vfork(),
         Sysno::gettid => sys_gettid(),
         Sysno::lseek => sys_lseek(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         Sysno::pread64 => sys_pread64(
@@ -129,6 +228,10 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
             tf.arg2() as _,
             tf.arg3() as _,
         ),
+        #[cfg(not(target_arch = "loongarch64"))]
+        Sysno::getrlimit => sys_getrlimit(tf.arg0() as _, tf.arg1().into()),
+        #[cfg(not(target_arch = "loongarch64"))]
+        Sysno::setrlimit => sys_setrlimit(tf.arg0() as _, tf.arg1().into()),
         Sysno::prlimit64 => sys_prlimit64(
             tf.arg0() as _,
             tf.arg1() as _,
@@ -136,6 +239,18 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
             tf.arg3().into(),
         ),
         Sysno::readv => sys_readv(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        Sysno::futex => sys_futex(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4() as _,
+            tf.arg5() as _,
+        ),
+        Sysno::rt_sigreturn => sys_rt_sigreturn(tf),
+        Sysno::kill => sys_kill(tf.arg0() as _, tf.arg1() as _),
+        Sysno::tkill => sys_tkill(tf.arg0() as _, tf.arg1() as _),
+        Sysno::tgkill => sys_tgkill(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         Sysno::rt_sigtimedwait => sys_rt_sigtimedwait(
             tf.arg0() as _,
             tf.arg1().into(),
@@ -147,11 +262,16 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
         Sysno::unlink => sys_unlink(tf.arg0().into()),
         _ => {
             warn!("Unimplemented syscall: {}", syscall_num);
-            axtask::exit(LinuxError::ENOSYS as _)
+            Err(LinuxError::ENOSYS)
         }
     };
     let ans = result.unwrap_or_else(|err| -err.code() as _);
-    time_stat_from_kernel_to_user();
+    current().task_ext().set_syscall_return(SyscallReturn {
+        sysno: syscall_num,
+        arg0,
+        restartable: ans == -(LinuxError::EINTR.code() as isize)
+            && is_restartable(Sysno::from(syscall_num as u32)),
+    });
     info!(
         "[syscall] <{:?}> return {}",
         Sysno::from(syscall_num as u32),